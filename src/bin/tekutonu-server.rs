@@ -0,0 +1,48 @@
+use std::{io::BufRead, sync::mpsc, thread};
+
+use tekutonu::server::{Server, ServerCommand, ServerConfig};
+
+
+/// Usage: tekutonu-server [world directory] [address]
+fn main() {
+    tracing_subscriber::fmt()
+        .with_timer(tracing_subscriber::fmt::time::uptime())
+        .init();
+
+    let mut args = std::env::args().skip(1);
+    let mut config = ServerConfig::default();
+    if let Some(world) = args.next() {
+        config.world = world.into();
+    }
+    if let Some(address) = args.next() {
+        config.address = address.parse().expect("Invalid address");
+    }
+
+    let mut server = Server::new(config).expect("Failed to start the server");
+
+    // Read commands from the console. Without one, e.g. under a service
+    // manager or with stdin closed, the server keeps running until stopped by
+    // a signal.
+    let (commands, commands_rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let command = match line.as_deref().map(str::trim) {
+                Ok("save") => ServerCommand::Save,
+                Ok("stop") => ServerCommand::Stop,
+                Err(e) => {
+                    println!("Stopped reading commands from the console: {}", e);
+                    break;
+                },
+                Ok(other) => {
+                    println!("Unknown command: {:?}. Available: save, stop", other);
+                    continue;
+                },
+            };
+            if commands.send(command).is_err() {
+                break;
+            }
+        }
+    });
+
+    server.run(commands_rx).expect("Failed to save the world");
+}
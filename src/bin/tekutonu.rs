@@ -1,8 +1,22 @@
+use std::path::PathBuf;
+
 use tekutonu::{
     controller::GameInput,
-    model::GameModel,
+    model::{
+        chunk_manager::ChunkManager,
+        generator::DemoGenerator,
+        region::Region,
+        settings::Settings,
+        storage::WorldStorage,
+        GameModel,
+    },
+    net::client::NetClient,
     view::{
-        renderer::{instance::make_instance, Renderer},
+        capture_world,
+        renderer::{
+            instance::{make_headless_instance, make_instance},
+            Renderer,
+        },
         texture::TextureLoader,
         GameView,
     },
@@ -16,7 +30,39 @@ fn terminal_clear() {
     print!("{esc}[2J{esc}[1;1H", esc = 27 as char);
 }
 
+/// Size of the images taken with `--screenshot`.
+const SCREENSHOT_EXTENT: [u32; 2] = [640, 480];
+
+/// Render the demo world without a window, with the debug overlay if `hud`,
+/// and save it to `path`.
+fn screenshot(loader_tex: TextureLoader, path: &str, hud: bool) {
+    let mut renderer = Renderer::headless(make_headless_instance(), SCREENSHOT_EXTENT);
+    let mut game = GameModel::default();
+
+    let screenshot = capture_world(&mut renderer, &loader_tex, &mut game, hud);
+    screenshot
+        .save(path)
+        .expect("Failed to save the screenshot");
+}
+
+/// Usage: tekutonu [server address]
+///        tekutonu --screenshot <file> [--hud]
 fn main() {
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    let loader_tex = TextureLoader::new("res/".into());
+
+    match &args[..] {
+        [flag, path] if flag == "--screenshot" => {
+            screenshot(loader_tex, path, false);
+            return;
+        },
+        [flag, path, hud] if flag == "--screenshot" && hud == "--hud" => {
+            screenshot(loader_tex, path, true);
+            return;
+        },
+        _ => (),
+    }
+
     terminal_clear();
 
     tracing_subscriber::fmt()
@@ -24,16 +70,42 @@ fn main() {
         .with_timer(tracing_subscriber::fmt::time::uptime())
         .init();
 
-    let loader_tex = TextureLoader::new("res/".into());
+    let settings_path = Settings::default_path();
+    let settings = match &settings_path {
+        Some(path) => Settings::load(path).unwrap_or_else(|e| {
+            println!("Failed to load the settings from {}: {}", path.display(), e);
+            Settings::default()
+        }),
+        None => Settings::default(),
+    };
+
+    // The world is kept next to the settings
+    let world_path = settings_path
+        .as_ref()
+        .and_then(|path| path.parent())
+        .map_or_else(|| PathBuf::from("world"), |dir| dir.join("world"));
 
     let vk = make_instance();
     let event_loop = EventLoop::new();
 
-    let renderer = Renderer::new(vk, &event_loop);
-    let view = GameView::new(renderer, loader_tex, event_loop);
+    let renderer = Renderer::new(vk, &event_loop, &settings);
+    let view = GameView::new(renderer, loader_tex, settings_path, event_loop);
 
-    let game = GameModel::default();
+    let (mut game, net) = match args.into_iter().next() {
+        Some(address) => {
+            let net = NetClient::connect(address).expect("Failed to connect to the server");
+            // The server will send the world
+            (GameModel::new(Region::default(), 0), Some(net))
+        },
+        None => {
+            let storage = WorldStorage::new(world_path);
+            let chunks =
+                ChunkManager::new(Default::default(), Box::new(DemoGenerator), Some(storage));
+            (GameModel::with_chunk_manager(chunks), None)
+        },
+    };
+    game.settings = settings;
     let input = GameInput::new();
 
-    view.run(game, input);
+    view.run(game, input, net);
 }
@@ -1,4 +1,4 @@
-use std::{collections::HashSet, f64::consts::FRAC_PI_2};
+use std::collections::HashSet;
 
 use cgmath::{Point3, Rad, Vector3, Zero};
 use winit::{
@@ -6,12 +6,24 @@ use winit::{
     event_loop::ControlFlow,
 };
 
-use crate::model::effect::GameModelEffect;
+use crate::model::{
+    effect::GameModelEffect,
+    settings::{Settings, FOV_RANGE},
+};
+
+/// How much the field of view changes per key press, in degrees.
+const FOV_STEP: f32 = 5.0;
 
 
 #[derive(Default)]
 pub struct GameInput {
     keys_held: HashSet<VirtualKeyCode>,
+    /// A screenshot was asked for and not taken yet
+    screenshot: bool,
+    /// Whether the debug overlay is shown
+    hud: bool,
+    /// The latest settings of the game
+    settings: Settings,
 }
 
 impl GameInput {
@@ -19,12 +31,17 @@ impl GameInput {
         Default::default()
     }
 
+    /// Use the mouse sensitivity and movement speed of `settings`.
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        self.settings = settings.clone();
+    }
+
     pub fn mouse_movement(&self, delta: (f64, f64)) -> GameModelEffect {
-        const RAD_PER_PX: f64 = FRAC_PI_2 / 90.0;
+        let rad_per_px = self.settings.mouse_sensitivity.to_radians();
 
         GameModelEffect::AdjustCameraAngles {
-            delta_pitch: Rad(delta.1 * RAD_PER_PX),
-            delta_yaw: Rad(delta.0 * RAD_PER_PX),
+            delta_pitch: Rad(delta.1 * rad_per_px),
+            delta_yaw: Rad(delta.0 * rad_per_px),
         }
     }
 
@@ -42,6 +59,25 @@ impl GameInput {
                 None
             },
             (Tab, Released) => Some(GameModelEffect::Debug),
+            (F2, Released) => {
+                self.screenshot = true;
+                None
+            },
+            (F3, Released) => {
+                self.hud = !self.hud;
+                None
+            },
+            (F11, Released) => {
+                let mut settings = self.settings.clone();
+                settings.window_mode = settings.window_mode.next();
+                Some(GameModelEffect::ChangeSettings(Box::new(settings)))
+            },
+            (Equals | Minus, Released) => {
+                let step = if key == Equals { FOV_STEP } else { -FOV_STEP };
+                let mut settings = self.settings.clone();
+                settings.fov = (settings.fov + step).clamp(*FOV_RANGE.start(), *FOV_RANGE.end());
+                Some(GameModelEffect::ChangeSettings(Box::new(settings)))
+            },
             (W | A | S | D | R | F | O, Pressed) => {
                 self.keys_held.insert(key);
                 None
@@ -54,30 +90,40 @@ impl GameInput {
         }
     }
 
-    fn keyboard_held(key: &VirtualKeyCode) -> Option<GameModelEffect> {
+    /// Whether a screenshot was asked for since the last call.
+    pub fn take_screenshot(&mut self) -> bool {
+        std::mem::take(&mut self.screenshot)
+    }
+
+    pub fn shows_hud(&self) -> bool {
+        self.hud
+    }
+
+    fn keyboard_held(&self, key: &VirtualKeyCode) -> Option<GameModelEffect> {
         use winit::event::VirtualKeyCode::*;
 
+        let speed = self.settings.movement_speed;
         match key {
             W => Some(GameModelEffect::ShiftCamera {
-                direction: Vector3::new(0.0, 0.0, 0.05),
+                direction: Vector3::new(0.0, 0.0, speed),
             }),
             A => Some(GameModelEffect::ShiftCamera {
-                direction: Vector3::new(-0.05, 0.0, 0.0),
+                direction: Vector3::new(-speed, 0.0, 0.0),
             }),
             S => Some(GameModelEffect::ShiftCamera {
-                direction: Vector3::new(0.0, 0.0, -0.05),
+                direction: Vector3::new(0.0, 0.0, -speed),
             }),
             D => Some(GameModelEffect::ShiftCamera {
-                direction: Vector3::new(0.05, 0.0, 0.0),
+                direction: Vector3::new(speed, 0.0, 0.0),
             }),
             R => Some(GameModelEffect::ShiftCamera {
-                direction: Vector3::new(0.0, 0.05, 0.0),
+                direction: Vector3::new(0.0, speed, 0.0),
             }),
             F => Some(GameModelEffect::ShiftCamera {
-                direction: Vector3::new(0.0, -0.05, 0.0),
+                direction: Vector3::new(0.0, -speed, 0.0),
             }),
             O => Some(GameModelEffect::TeleportCamera {
-                point: Point3::new(0., 0.5, 0.),
+                point: Point3::new(8., 8.5, 8.),
                 pitch: Rad(0.),
                 yaw: Rad(0.),
             }),
@@ -89,7 +135,7 @@ impl GameInput {
         let mut camera_shift_acc = None;
 
         for key in self.keys_held.iter() {
-            let effect = Self::keyboard_held(key);
+            let effect = self.keyboard_held(key);
             match effect {
                 Some(GameModelEffect::ShiftCamera { direction }) => {
                     camera_shift_acc =
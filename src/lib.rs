@@ -1,6 +1,10 @@
 // Regrets
 
+#[cfg(feature = "client")]
 pub mod controller;
 pub mod model;
+pub mod net;
+pub mod server;
 pub mod util;
+#[cfg(feature = "client")]
 pub mod view;
@@ -0,0 +1,150 @@
+use cgmath::Point3;
+
+use super::{
+    block::{BlockKind, Fluid},
+    region::Region,
+};
+
+/// How the world fades into the sky with distance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fog {
+    /// Clear up to `start` blocks away, fully fogged from `end` on
+    Linear { start: f32, end: f32 },
+    /// Thickens smoothly, `density` per block
+    Exponential { density: f32 },
+}
+
+impl Fog {
+    /// How much of the fog colour covers something `distance` blocks away,
+    /// from 0 to 1. The fragment shader does the same.
+    pub fn amount(&self, distance: f32) -> f32 {
+        match *self {
+            Self::Linear { start, end } => ((distance - start) / (end - start)).clamp(0.0, 1.0),
+            Self::Exponential { density } => 1.0 - (-density * distance).exp(),
+        }
+    }
+}
+
+/// Sky, fog and ambient light around the camera.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Atmosphere {
+    pub fog: Fog,
+    /// Colour of the sky straight up
+    pub zenith: [f32; 3],
+    /// Colour of the sky at the horizon and below, which the fog fades into
+    pub horizon: [f32; 3],
+    /// Light every face gets, even when no light reaches it
+    pub ambient: [f32; 3],
+}
+
+impl Atmosphere {
+    pub const DAY: Self = Self {
+        fog: Fog::Linear {
+            start: 96.0,
+            end: 160.0,
+        },
+        zenith: [0.25, 0.45, 0.85],
+        horizon: [0.7, 0.82, 0.95],
+        ambient: [0.2, 0.2, 0.2],
+    };
+
+    pub const UNDERWATER: Self = Self {
+        fog: Fog::Exponential { density: 0.12 },
+        zenith: [0.05, 0.2, 0.45],
+        horizon: [0.05, 0.2, 0.45],
+        ambient: [0.1, 0.15, 0.25],
+    };
+
+    pub const UNDER_LAVA: Self = Self {
+        fog: Fog::Exponential { density: 1.5 },
+        zenith: [0.8, 0.25, 0.0],
+        horizon: [0.8, 0.25, 0.0],
+        ambient: [0.6, 0.3, 0.1],
+    };
+
+    /// Out of reach of the sky: dark, and the fog closes in.
+    pub const CAVE: Self = Self {
+        fog: Fog::Linear {
+            start: 16.0,
+            end: 64.0,
+        },
+        zenith: [0.02, 0.02, 0.03],
+        horizon: [0.02, 0.02, 0.03],
+        ambient: [0.1, 0.1, 0.1],
+    };
+
+    /// The atmosphere seen from `position`, in block coordinates.
+    pub fn at(region: &Region, position: Point3<f64>) -> Self {
+        let loc = [position.x, position.y, position.z].map(|v| v.floor());
+        if loc.iter().any(|&v| v < 0.0) {
+            return Self::DAY;
+        }
+        let Some((chunk, local)) = Region::split_location(loc.map(|v| v as usize)) else {
+            return Self::DAY;
+        };
+        let chunk = region.get_chunk(chunk);
+
+        match chunk.get_block(local).kind {
+            BlockKind::Fluid {
+                fluid: Fluid::Water,
+                ..
+            } => Self::UNDERWATER,
+            BlockKind::Fluid {
+                fluid: Fluid::Lava, ..
+            } => Self::UNDER_LAVA,
+            _ if chunk.get_light_sky(local) == 0 => Self::CAVE,
+            _ => Self::DAY,
+        }
+    }
+}
+
+impl Default for Atmosphere {
+    fn default() -> Self {
+        Self::DAY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::block::Block;
+
+    #[test]
+    fn fog_amount() {
+        let linear = Fog::Linear {
+            start: 10.0,
+            end: 20.0,
+        };
+        assert_eq!(linear.amount(5.0), 0.0);
+        assert_eq!(linear.amount(15.0), 0.5);
+        assert_eq!(linear.amount(30.0), 1.0);
+
+        let exponential = Fog::Exponential { density: 0.1 };
+        assert_eq!(exponential.amount(0.0), 0.0);
+        assert!((exponential.amount(10.0) - (1.0 - (-1.0f32).exp())).abs() < 1e-6);
+        assert!(exponential.amount(100.0) > 0.99);
+    }
+
+    #[test]
+    fn depends_on_surroundings() {
+        let mut region = Region::default();
+        region.set_block([5, 5, 5], Block::water());
+        for x in 9..12 {
+            for y in 4..7 {
+                for z in 4..7 {
+                    if [x, y, z] != [10, 5, 5] {
+                        region.set_block([x, y, z], Block::solid());
+                    }
+                }
+            }
+        }
+        region.recalculate_chunk_light([0, 0, 0]);
+
+        let at = |x, y, z| Atmosphere::at(&region, Point3::new(x, y, z));
+        assert_eq!(at(5.5, 5.2, 5.9), Atmosphere::UNDERWATER);
+        assert_eq!(at(10.5, 5.5, 5.5), Atmosphere::CAVE);
+        assert_eq!(at(1.0, 1.0, 1.0), Atmosphere::DAY);
+        // Outside of the region
+        assert_eq!(at(-1.0, 1.0, 1.0), Atmosphere::DAY);
+    }
+}
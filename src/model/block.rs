@@ -1,13 +1,84 @@
+use super::consts as c;
+
 pub type LightLevel = u8;
 
+/// Names of the block textures. Meshes refer to them by index, see
+/// [`BlockKind::texture`].
+pub const TEXTURES: [&str; 10] = [
+    "stone",
+    "light",
+    "grass_top",
+    "grass_side",
+    "dirt",
+    "glass",
+    "water",
+    "lava",
+    "sand",
+    "gravel",
+];
+
+/// Level of fluid sources. Flowing fluid has lower levels, down to 1.
+pub const FLUID_SOURCE: u8 = 8;
+/// Level of falling fluid, and the highest level of flowing fluid.
+pub const FLUID_FALLING: u8 = FLUID_SOURCE - 1;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, PartialOrd, Ord)]
+pub enum Fluid {
+    Water,
+    Lava,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum BlockKind {
     Air,
     Solid,
     Light { brightness: LightLevel },
+    Grass,
+    Dirt,
+    Glass,
+    Fluid { fluid: Fluid, level: u8 },
+    Sand,
+    Gravel,
 }
 
-impl BlockKind {}
+impl BlockKind {
+    /// Index into [`TEXTURES`] of the face pointing in `direction` (index into
+    /// `ADJACENCY`).
+    pub fn texture(&self, direction: usize) -> u32 {
+        match (self, direction) {
+            (Self::Air | Self::Solid, _) => 0,
+            (Self::Light { .. }, _) => 1,
+            (Self::Grass, c::FACE_TOP) => 2,
+            (Self::Grass, c::FACE_BOTTOM) | (Self::Dirt, _) => 4,
+            (Self::Grass, _) => 3,
+            (Self::Glass, _) => 5,
+            (Self::Fluid { fluid, .. }, _) => match fluid {
+                Fluid::Water => 6,
+                Fluid::Lava => 7,
+            },
+            (Self::Sand, _) => 8,
+            (Self::Gravel, _) => 9,
+        }
+    }
+
+    /// Whether the kinds are the same, regardless of their state.
+    pub fn is_same(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+            && self.fluid() == other.fluid()
+    }
+
+    pub fn fluid(&self) -> Option<Fluid> {
+        match self {
+            Self::Fluid { fluid, .. } => Some(*fluid),
+            _ => None,
+        }
+    }
+
+    /// Whether the block falls when there is nothing under it.
+    pub fn has_gravity(&self) -> bool {
+        matches!(self, Self::Sand | Self::Gravel)
+    }
+}
 
 #[derive(Clone, Copy, Debug)]
 pub struct Block {
@@ -33,17 +104,193 @@ impl Block {
         }
     }
 
+    pub fn grass() -> Self {
+        Self {
+            kind: BlockKind::Grass,
+        }
+    }
+
+    pub fn dirt() -> Self {
+        Self {
+            kind: BlockKind::Dirt,
+        }
+    }
+
+    pub fn glass() -> Self {
+        Self {
+            kind: BlockKind::Glass,
+        }
+    }
+
+    /// A fluid source.
+    pub fn water() -> Self {
+        Self::fluid(Fluid::Water, FLUID_SOURCE)
+    }
+
+    /// A fluid source.
+    pub fn lava() -> Self {
+        Self::fluid(Fluid::Lava, FLUID_SOURCE)
+    }
+
+    pub fn fluid(fluid: Fluid, level: u8) -> Self {
+        Self {
+            kind: BlockKind::Fluid { fluid, level },
+        }
+    }
+
+    pub fn sand() -> Self {
+        Self {
+            kind: BlockKind::Sand,
+        }
+    }
+
+    pub fn gravel() -> Self {
+        Self {
+            kind: BlockKind::Gravel,
+        }
+    }
+
+    /// Whether light passes through the block and faces behind it can be
+    /// seen.
     pub fn is_transparent(&self) -> bool {
         match self.kind {
             BlockKind::Air => true,
             BlockKind::Solid => false,
             BlockKind::Light { .. } => false,
+            BlockKind::Grass | BlockKind::Dirt => false,
+            BlockKind::Glass => true,
+            BlockKind::Fluid { fluid, .. } => fluid == Fluid::Water,
+            BlockKind::Sand | BlockKind::Gravel => false,
+        }
+    }
+
+    /// Whether falling blocks fall through the block and take its place.
+    pub fn is_replaceable(&self) -> bool {
+        matches!(self.kind, BlockKind::Air | BlockKind::Fluid { .. })
+    }
+
+    /// Whether the block is transparent but still drawn, blended over what
+    /// is behind it.
+    pub fn is_translucent(&self) -> bool {
+        matches!(
+            self.kind,
+            BlockKind::Glass
+                | BlockKind::Fluid {
+                    fluid: Fluid::Water,
+                    ..
+                }
+        )
+    }
+
+    /// Level of the light the block gives off.
+    pub fn light_emission(&self) -> LightLevel {
+        match self.kind {
+            BlockKind::Light { brightness } => brightness,
+            BlockKind::Fluid {
+                fluid: Fluid::Lava, ..
+            } => c::LIGHT_MAX as LightLevel,
+            _ => 0,
         }
     }
 }
 
+impl Block {
+    /// Pack the block into two bytes: kind and kind-specific data.
+    pub fn encode(&self) -> [u8; 2] {
+        match self.kind {
+            BlockKind::Air => [0, 0],
+            BlockKind::Solid => [1, 0],
+            BlockKind::Light { brightness } => [2, brightness],
+            BlockKind::Grass => [3, 0],
+            BlockKind::Dirt => [4, 0],
+            BlockKind::Glass => [5, 0],
+            BlockKind::Fluid {
+                fluid: Fluid::Water,
+                level,
+            } => [6, level],
+            BlockKind::Fluid {
+                fluid: Fluid::Lava,
+                level,
+            } => [7, level],
+            BlockKind::Sand => [8, 0],
+            BlockKind::Gravel => [9, 0],
+        }
+    }
+
+    /// Inverse of [`Block::encode`]. Returns `None` for unknown kinds and
+    /// out of range brightness or fluid levels.
+    pub fn decode(bytes: [u8; 2]) -> Option<Self> {
+        let kind = match bytes {
+            [0, _] => BlockKind::Air,
+            [1, _] => BlockKind::Solid,
+            [2, brightness] if brightness as isize <= c::LIGHT_MAX => {
+                BlockKind::Light { brightness }
+            },
+            [3, _] => BlockKind::Grass,
+            [4, _] => BlockKind::Dirt,
+            [5, _] => BlockKind::Glass,
+            [6, level @ 1..=FLUID_SOURCE] => BlockKind::Fluid {
+                fluid: Fluid::Water,
+                level,
+            },
+            [7, level @ 1..=FLUID_SOURCE] => BlockKind::Fluid {
+                fluid: Fluid::Lava,
+                level,
+            },
+            [8, _] => BlockKind::Sand,
+            [9, _] => BlockKind::Gravel,
+            _ => return None,
+        };
+
+        Some(Self { kind })
+    }
+}
+
 impl Default for Block {
     fn default() -> Self {
         Self::air()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grass_faces() {
+        let grass = BlockKind::Grass;
+
+        assert_eq!(TEXTURES[grass.texture(c::FACE_TOP) as usize], "grass_top");
+        assert_eq!(TEXTURES[grass.texture(c::FACE_BOTTOM) as usize], "dirt");
+        for side in 0..4 {
+            assert_eq!(TEXTURES[grass.texture(side) as usize], "grass_side");
+        }
+        assert_eq!(
+            TEXTURES[BlockKind::Dirt.texture(c::FACE_TOP) as usize],
+            "dirt"
+        );
+    }
+
+    #[test]
+    fn encoding_roundtrip() {
+        for block in [
+            Block::air(),
+            Block::solid(),
+            Block::light_source(),
+            Block::grass(),
+            Block::dirt(),
+            Block::glass(),
+            Block::water(),
+            Block::fluid(Fluid::Lava, 3),
+            Block::sand(),
+            Block::gravel(),
+        ] {
+            assert_eq!(Block::decode(block.encode()).unwrap().kind, block.kind);
+        }
+
+        assert!(Block::decode([2, 16]).is_none());
+        assert!(Block::decode([6, 0]).is_none());
+        assert!(Block::decode([7, FLUID_SOURCE + 1]).is_none());
+        assert!(Block::decode([10, 0]).is_none());
+    }
+}
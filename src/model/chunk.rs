@@ -1,12 +1,14 @@
 use std::collections::{HashSet, VecDeque};
 
-use cgmath::{Point3, Vector3};
+use cgmath::Vector3;
 use tracing::instrument;
 
 use super::{
     block::{Block, BlockKind},
     consts as c,
+    mesh::{self, MeshConfig, Mesher, Quad, RenderData},
     types as t,
+    visibility::Connectivity,
 };
 
 type ChunkBlockData<Data> = [[[Data; c::CHUNK_Z_BLOCKS]; c::CHUNK_Y_BLOCKS]; c::CHUNK_X_BLOCKS];
@@ -22,6 +24,30 @@ pub enum AdjacentDirection {
     ZNeg,
 }
 
+impl AdjacentDirection {
+    /// All directions, in the order `SurroundingChunks` expects them.
+    pub const ALL: [Self; 6] = [
+        Self::XPos,
+        Self::XNeg,
+        Self::YPos,
+        Self::YNeg,
+        Self::ZPos,
+        Self::ZNeg,
+    ];
+
+    /// Offset to the adjacent chunk in this direction.
+    pub fn offset(&self) -> [isize; 3] {
+        match self {
+            Self::XPos => [1, 0, 0],
+            Self::XNeg => [-1, 0, 0],
+            Self::YPos => [0, 1, 0],
+            Self::YNeg => [0, -1, 0],
+            Self::ZPos => [0, 0, 1],
+            Self::ZNeg => [0, 0, -1],
+        }
+    }
+}
+
 impl From<t::PointIntLocal> for AdjacentDirection {
     fn from(loc: t::PointIntLocal) -> Self {
         let x_pos = loc.x() >= c::CHUNK_X_BLOCKS as isize;
@@ -129,6 +155,25 @@ impl<'a> SurroundingChunks<'a> {
         Self { chunks }
     }
 
+    /// Call `f` with `chunks` in the order of [`AdjacentDirection::ALL`].
+    ///
+    /// Missing chunks are filled in with open sky, except for the one below.
+    pub fn with_missing_as_sky<R>(
+        chunks: [Option<&Chunk>; 6],
+        f: impl FnOnce(SurroundingChunks) -> R,
+    ) -> R {
+        let sky = ChunkEmpty::new(c::LIGHT_MAX as u8);
+        let ground = ChunkEmpty::new(0);
+
+        let chunks = AdjacentDirection::ALL.map(|direction| match chunks[direction as usize] {
+            Some(chunk) => chunk as &dyn ChunkAdjacent,
+            None if direction == AdjacentDirection::YNeg => &ground,
+            None => &sky,
+        });
+
+        f(SurroundingChunks::new(chunks))
+    }
+
     pub fn get_chunk_for_direction(&self, direction: &AdjacentDirection) -> &'a dyn ChunkAdjacent {
         self.chunks[*direction as usize]
     }
@@ -208,6 +253,13 @@ pub struct Chunk {
     light_sky: ChunkBlockData<u8>,
     light_local: ChunkBlockData<u8>,
     light_sources: HashSet<t::PointIntLocal>,
+    /// Number of blocks that are not air
+    non_air: usize,
+    /// Incremented on every change to the blocks
+    version: u64,
+    /// Which faces see each other, see [`Connectivity::of`]. Reset to
+    /// everything connected on change until recomputed.
+    connectivity: Connectivity,
 }
 
 impl Default for Chunk {
@@ -218,6 +270,9 @@ impl Default for Chunk {
                 c::CHUNK_X_BLOCKS],
             light_local: [[[0; c::CHUNK_Z_BLOCKS]; c::CHUNK_Y_BLOCKS]; c::CHUNK_X_BLOCKS],
             light_sources: HashSet::new(),
+            non_air: 0,
+            version: 0,
+            connectivity: Connectivity::ALL,
         }
     }
 }
@@ -231,13 +286,50 @@ impl Chunk {
     pub fn set_block(&mut self, loc: impl Into<t::PointIntLocal>, block: Block) {
         let loc = loc.into();
         let block_place = &mut self.blocks[loc.ux()][loc.uy()][loc.uz()];
-        if let BlockKind::Light { .. } = block_place.kind {
+        if block_place.light_emission() > 0 {
             self.light_sources.remove(&loc);
         }
+        if block_place.kind != BlockKind::Air {
+            self.non_air -= 1;
+        }
         *block_place = block;
-        if let BlockKind::Light { .. } = block_place.kind {
+        if block_place.light_emission() > 0 {
             self.light_sources.insert(loc);
         }
+        if block_place.kind != BlockKind::Air {
+            self.non_air += 1;
+        }
+        self.version += 1;
+        self.connectivity = Connectivity::ALL;
+    }
+
+    /// Changes every time a block in the chunk changes.
+    /// Light recalculation does not count as a change.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// Make sure the chunk replacing `previous` does not share any versions
+    /// with it.
+    pub(super) fn succeed(&mut self, previous: &Chunk) {
+        self.version = self.version.max(previous.version + 1);
+    }
+
+    pub fn connectivity(&self) -> Connectivity {
+        self.connectivity
+    }
+
+    pub(super) fn set_connectivity(&mut self, connectivity: Connectivity) {
+        self.connectivity = connectivity;
+    }
+
+    /// Take the light data from a copy of this chunk that was lit elsewhere.
+    /// Returns whether the light changed.
+    pub fn copy_light_from(&mut self, other: &Chunk) -> bool {
+        let changed = self.light_sky != other.light_sky || self.light_local != other.light_local;
+        self.light_sky = other.light_sky;
+        self.light_local = other.light_local;
+        changed
     }
 
     pub fn get_light_local(&self, loc: impl Into<t::PointIntLocal>) -> u8 {
@@ -321,14 +413,10 @@ impl Chunk {
         }
 
         for s in self.light_sources.iter() {
-            let block = self.get_block(*s);
-            match block.kind {
-                BlockKind::Light { brightness } => {
-                    self.light_local[s.ux()][s.uy()][s.uz()] = brightness;
-                    updated.push_back(*s);
-                },
-                _ => panic!("Light source is not a light block"),
-            }
+            let brightness = self.get_block(*s).light_emission();
+            assert!(brightness > 0, "Light source does not give off light");
+            self.light_local[s.ux()][s.uy()][s.uz()] = brightness;
+            updated.push_back(*s);
         }
 
         while let Some(loc) = updated.pop_front() {
@@ -362,8 +450,52 @@ impl Chunk {
         self.recalculate_light_local(around);
     }
 
-    fn assemble_faces_with_light(&self) -> Vec<([Point3<f32>; 4], u8)> {
-        let mut faces = Vec::<([Point3<f32>; 4], u8)>::new();
+    /// Block and light at `loc`, which may lie in one of the surrounding
+    /// chunks. Returns `None` for locations diagonal to the chunk, which
+    /// `around` does not cover.
+    pub(super) fn sample<'a>(
+        &'a self,
+        around: SurroundingChunks<'a>,
+        loc: t::PointIntLocal,
+    ) -> Option<(&'a Block, u8)> {
+        let outside = loc
+            .0
+            .iter()
+            .filter(|&&v| v < 0 || v >= c::CHUNK_EDGE_BLOCKS as isize)
+            .count();
+
+        match outside {
+            0 => Some((
+                self.get_block(loc),
+                self.get_light_sky(loc).max(self.get_light_local(loc)),
+            )),
+            1 => {
+                let (chunk, _) = around.get_chunk_of_location(loc);
+                let loc = loc.localize();
+                Some((
+                    chunk.get_block(loc),
+                    chunk.get_light_sky(loc).max(chunk.get_light_local(loc)),
+                ))
+            },
+            _ => None,
+        }
+    }
+
+    /// Light shining on the face of the block at `loc` that points in
+    /// `direction` (index into `ADJACENCY`), or `None` if the face is hidden
+    /// behind an opaque block. Faces on the chunk borders look into `around`.
+    pub(super) fn face_light(
+        &self,
+        around: SurroundingChunks,
+        loc: t::PointIntLocal,
+        direction: usize,
+    ) -> Option<u8> {
+        let (block, light) = self.sample(around, loc + &c::ADJACENCY[direction])?;
+        block.is_transparent().then_some(light)
+    }
+
+    fn assemble_faces_with_light(&self, around: SurroundingChunks, smooth: bool) -> Vec<Quad> {
+        let mut faces = Vec::<Quad>::new();
 
         for x in 0..c::CHUNK_X_BLOCKS {
             let fx = x as f32;
@@ -376,23 +508,8 @@ impl Chunk {
                     let block = self.get_block(loc);
                     if !block.is_transparent() {
                         faces.extend(c::BLOCK_FACES.iter().enumerate().filter_map(|(i, face)| {
-                            let loc2 = loc + &c::ADJACENCY[i];
-
-                            // TODO: make it work on chunk borders
-                            let light = if loc2.is_in_chunk() {
-                                let adjacent_block = self.get_block(loc2);
-                                if !adjacent_block.is_transparent() {
-                                    return None;
-                                }
-
-                                let local = self.get_light_local(loc2);
-                                let sky = self.get_light_sky(loc2);
-                                sky.max(local)
-                            } else {
-                                0
-                            };
-
-                            Some((face.map(|p| p + offset), light))
+                            let light = mesh::face_light(self, around, loc, i, smooth)?;
+                            Some((face.map(|p| p + offset), block.kind.texture(i), light))
                         }));
                     }
                 }
@@ -402,38 +519,92 @@ impl Chunk {
         faces
     }
 
+    /// Mesh the chunk. Faces on the borders are culled and lit using the
+    /// surrounding chunks.
     pub fn get_render_data(
         &self,
+        around: SurroundingChunks,
         global_offset: Vector3<f32>,
-    ) -> (Vec<Point3<f32>>, Vec<u8>, Vec<usize>) {
-        let faces = self.assemble_faces_with_light();
-
-        let add_offset = |p: Point3<f32>| p + global_offset;
-
-        // Vertices
-        let mut vs = vec![];
-        // Light levels
-        let mut ls = vec![];
-        // Indices
-        let mut is = vec![];
-
-        for face in faces {
-            let i = vs.len();
-            // Four vertices
-            vs.extend(face.0.into_iter().map(add_offset));
-            let light = face.1;
-            // TODO: calculate based on the light level of the block in front of us
-            ls.extend([
-                light, // For vertex 0
-                light, // For vertex 1
-                light, // For vertex 2
-                light, // For vertex 3
-            ]);
-            // Two triangles
-            is.extend([i, i + 1, i + 2, i, i + 2, i + 3]);
+        config: MeshConfig,
+    ) -> RenderData {
+        let smooth = config.smooth_lighting;
+        let mut data = match config.mesher {
+            Mesher::Naive => mesh::naive(self.assemble_faces_with_light(around, smooth)),
+            Mesher::Greedy => mesh::greedy(self, around, smooth),
+        };
+        data.translate(global_offset);
+        data
+    }
+
+    /// Mesh the translucent blocks of the chunk, to be drawn after the
+    /// opaque ones.
+    pub fn get_translucent_render_data(
+        &self,
+        around: SurroundingChunks,
+        global_offset: Vector3<f32>,
+        config: MeshConfig,
+    ) -> RenderData {
+        let mut data = mesh::translucent(self, around, config.smooth_lighting);
+        data.translate(global_offset);
+        data
+    }
+}
+
+impl Chunk {
+    /// Size of [`Chunk::to_bytes`] output.
+    pub const BYTES: usize = c::CHUNK_TOTAL_BLOCKS * 4;
+
+    /// Whether the chunk contains nothing but air.
+    pub fn is_empty(&self) -> bool {
+        self.non_air == 0
+    }
+
+    /// Serialize blocks and light as four planes: block kinds, block data, sky
+    /// light and local light.
+    ///
+    /// Blocks are laid out the way `DESIGN.md` describes, so that runs of
+    /// equal values stay close together and compress well.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; Self::BYTES];
+        let (kinds, rest) = bytes.split_at_mut(c::CHUNK_TOTAL_BLOCKS);
+        let (data, rest) = rest.split_at_mut(c::CHUNK_TOTAL_BLOCKS);
+        let (sky, local) = rest.split_at_mut(c::CHUNK_TOTAL_BLOCKS);
+
+        for (i, [x, y, z]) in Self::iter_locations().enumerate() {
+            [kinds[i], data[i]] = self.blocks[x][y][z].encode();
+            sky[i] = self.light_sky[x][y][z];
+            local[i] = self.light_local[x][y][z];
         }
 
-        (vs, ls, is)
+        bytes
+    }
+
+    /// Inverse of [`Chunk::to_bytes`]. Returns `None` if the data is malformed.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::BYTES {
+            return None;
+        }
+
+        let (kinds, rest) = bytes.split_at(c::CHUNK_TOTAL_BLOCKS);
+        let (data, rest) = rest.split_at(c::CHUNK_TOTAL_BLOCKS);
+        let (sky, local) = rest.split_at(c::CHUNK_TOTAL_BLOCKS);
+
+        let mut chunk = Self::default();
+
+        for (i, [x, y, z]) in Self::iter_locations().enumerate() {
+            chunk.set_block([x, y, z], Block::decode([kinds[i], data[i]])?);
+            chunk.light_sky[x][y][z] = sky[i];
+            chunk.light_local[x][y][z] = local[i];
+        }
+
+        Some(chunk)
+    }
+
+    /// Iterate over all block locations in the chunk with X changing fastest.
+    fn iter_locations() -> impl Iterator<Item = [usize; 3]> {
+        (0..c::CHUNK_Z_BLOCKS).flat_map(|z| {
+            (0..c::CHUNK_Y_BLOCKS).flat_map(move |y| (0..c::CHUNK_X_BLOCKS).map(move |x| [x, y, z]))
+        })
     }
 }
 
@@ -443,12 +614,19 @@ mod tests {
 
     mod assemble_faces {
         use super::*;
+        use crate::model::{mesh::FaceLight, test_util::filled};
+
+        fn assemble(chunk: &Chunk) -> Vec<Quad> {
+            SurroundingChunks::with_missing_as_sky([None; 6], |around| {
+                chunk.assemble_faces_with_light(around, false)
+            })
+        }
 
         #[test]
         fn empty() {
             let chunk = Chunk::default();
 
-            let ts = chunk.assemble_faces_with_light();
+            let ts = assemble(&chunk);
 
             assert_eq!(ts.len(), 0, "there should be zero faces generated");
         }
@@ -463,7 +641,7 @@ mod tests {
                 },
             );
 
-            let ts = chunk.assemble_faces_with_light();
+            let ts = assemble(&chunk);
 
             assert_eq!(ts.len(), 6, "there should be 6 faces generated");
         }
@@ -484,9 +662,45 @@ mod tests {
                 },
             );
 
-            let ts = chunk.assemble_faces_with_light();
+            let ts = assemble(&chunk);
+
+            // The two faces between the blocks are hidden
+            assert_eq!(ts.len(), 10, "there should be 10 faces generated");
+        }
+
+        #[test]
+        fn hidden_by_neighbour() {
+            let (left, right) = (filled(Block::solid()), filled(Block::solid()));
+
+            let mut neighbours = [None; 6];
+            neighbours[AdjacentDirection::XPos as usize] = Some(&right);
+            let ts = SurroundingChunks::with_missing_as_sky(neighbours, |around| {
+                left.assemble_faces_with_light(around, false)
+            });
+
+            let inner = ts
+                .iter()
+                .filter(|(face, ..)| face.iter().all(|p| p.x == 16.0));
+            assert_eq!(
+                inner.count(),
+                0,
+                "there should be no faces between the chunks"
+            );
+            assert_eq!(ts.len(), 5 * 16 * 16);
+        }
+
+        #[test]
+        fn lit_by_neighbour() {
+            let mut chunk = Chunk::default();
+            chunk.set_block([15isize, 0, 0], Block::solid());
+
+            let ts = assemble(&chunk);
 
-            assert_eq!(ts.len(), 12, "there should be 12 faces generated");
+            // Only the face on the border sees the sky of the missing neighbour
+            let border = ts
+                .iter()
+                .find(|(face, ..)| face.iter().all(|p| p.x == 16.0));
+            assert_eq!(border.unwrap().2, FaceLight::flat(c::LIGHT_MAX as u8));
         }
     }
 }
@@ -0,0 +1,372 @@
+use std::collections::HashSet;
+
+use tracing::{instrument, warn};
+
+use super::{
+    chunk::Chunk,
+    consts as c,
+    generator::ChunkGenerator,
+    region::Region,
+    storage::WorldStorage,
+    Camera,
+};
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkEvent {
+    Loaded([usize; 3]),
+    Unloaded([usize; 3]),
+}
+
+
+pub struct ChunkManagerConfig {
+    /// Chunks within this distance (in chunks) from the camera are loaded
+    pub load_radius: usize,
+    /// Chunks further than this from the camera are unloaded.
+    /// Should be larger than `load_radius` so that chunks on the edge do not
+    /// get reloaded every time the camera crosses a chunk border.
+    pub unload_radius: usize,
+    /// Maximum number of chunks loaded per update, nearest first
+    pub loads_per_update: usize,
+}
+
+impl Default for ChunkManagerConfig {
+    fn default() -> Self {
+        Self {
+            load_radius: 4,
+            unload_radius: 6,
+            loads_per_update: 8,
+        }
+    }
+}
+
+
+/// Keeps the chunks around the camera loaded.
+///
+/// Chunks are loaded from the storage if they were saved before, or from the
+/// generator otherwise. Unloaded chunks are saved and replaced with empty ones.
+pub struct ChunkManager {
+    config: ChunkManagerConfig,
+    generator: Box<dyn ChunkGenerator>,
+    storage: Option<WorldStorage>,
+
+    loaded: HashSet<[usize; 3]>,
+    /// Chunk the camera was in during the last update
+    center: Option<[isize; 3]>,
+    /// Whether some chunks in the load radius were not loaded yet
+    pending: bool,
+}
+
+impl ChunkManager {
+    pub fn new(
+        config: ChunkManagerConfig,
+        generator: Box<dyn ChunkGenerator>,
+        storage: Option<WorldStorage>,
+    ) -> Self {
+        assert!(
+            config.unload_radius >= config.load_radius,
+            "Chunks would be unloaded right after loading"
+        );
+
+        Self {
+            config,
+            generator,
+            storage,
+            loaded: HashSet::new(),
+            center: None,
+            pending: false,
+        }
+    }
+
+    pub fn is_loaded(&self, loc: [usize; 3]) -> bool {
+        self.loaded.contains(&loc)
+    }
+
+    pub fn loaded_count(&self) -> usize {
+        self.loaded.len()
+    }
+
+    fn distance_squared(a: [isize; 3], b: [usize; 3]) -> usize {
+        a.iter()
+            .zip(b)
+            .map(|(&a, b)| a.abs_diff(b as isize).pow(2))
+            .sum()
+    }
+
+    /// Chunks in the load radius around `center` that are not loaded yet,
+    /// nearest first.
+    fn missing_chunks(&self, center: [isize; 3]) -> Vec<[usize; 3]> {
+        let r = self.config.load_radius as isize;
+        let limits = [c::REGION_X_CHUNKS, c::REGION_Y_CHUNKS, c::REGION_Z_CHUNKS];
+
+        let mut missing = Vec::new();
+        for dx in -r..=r {
+            for dy in -r..=r {
+                for dz in -r..=r {
+                    let loc = [center[0] + dx, center[1] + dy, center[2] + dz];
+                    if (0..3).any(|i| loc[i] < 0 || loc[i] >= limits[i] as isize) {
+                        continue;
+                    }
+
+                    let loc = loc.map(|v| v as usize);
+                    if Self::distance_squared(center, loc) <= self.config.load_radius.pow(2)
+                        && !self.loaded.contains(&loc)
+                    {
+                        missing.push(loc);
+                    }
+                }
+            }
+        }
+
+        missing.sort_by_key(|&loc| Self::distance_squared(center, loc));
+        missing
+    }
+
+    fn load(&mut self, loc: [usize; 3], region: &mut Region) {
+        let saved = self.storage.as_ref().and_then(|s| {
+            s.load_chunk(loc).unwrap_or_else(|e| {
+                warn!("Failed to load chunk {:?}, regenerating: {}", loc, e);
+                None
+            })
+        });
+
+        match saved {
+            // Light was saved along with the blocks
+            Some(chunk) => region.set_chunk(loc, chunk),
+            None => {
+                region.set_chunk(loc, self.generator.generate(loc));
+                region.recalculate_chunk_light(loc);
+            },
+        }
+
+        self.loaded.insert(loc);
+    }
+
+    fn unload(&mut self, loc: [usize; 3], region: &mut Region) {
+        if let Some(storage) = &self.storage {
+            if let Err(e) = storage.save_chunk(loc, region.get_chunk(loc)) {
+                warn!("Failed to save chunk {:?}: {}", loc, e);
+            }
+        }
+
+        region.set_chunk(loc, Chunk::default());
+        self.loaded.remove(&loc);
+    }
+
+    /// Load and unload chunks according to where the camera is.
+    #[instrument(skip_all)]
+    pub fn update(&mut self, camera: &Camera, region: &mut Region) -> Vec<ChunkEvent> {
+        let center = camera.get_chunk_location();
+        if self.center == Some(center) && !self.pending {
+            return Vec::new();
+        }
+        self.center = Some(center);
+
+        let mut events = Vec::new();
+
+        let mut far: Vec<_> = self
+            .loaded
+            .iter()
+            .copied()
+            .filter(|&loc| Self::distance_squared(center, loc) > self.config.unload_radius.pow(2))
+            .collect();
+        far.sort_by_key(|&loc| std::cmp::Reverse(Self::distance_squared(center, loc)));
+        for loc in far {
+            self.unload(loc, region);
+            events.push(ChunkEvent::Unloaded(loc));
+        }
+
+        let missing = self.missing_chunks(center);
+        self.pending = missing.len() > self.config.loads_per_update;
+        for loc in missing.into_iter().take(self.config.loads_per_update) {
+            self.load(loc, region);
+            events.push(ChunkEvent::Loaded(loc));
+        }
+
+        events
+    }
+
+    /// Save every loaded chunk without unloading it.
+    #[instrument(skip_all)]
+    pub fn save_all(&self, region: &Region) -> std::io::Result<()> {
+        if let Some(storage) = &self.storage {
+            for &loc in &self.loaded {
+                storage.save_chunk(loc, region.get_chunk(loc))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Point3;
+
+    use super::*;
+    use crate::model::{block::Block, generator::FlatGenerator};
+
+    /// Camera in the middle of the chunk `loc`.
+    fn camera_at(loc: [usize; 3]) -> Camera {
+        let block = |i: usize, size: usize| (loc[i] * size) as f64 + size as f64 / 2.0;
+        let position = Point3::new(
+            block(0, c::CHUNK_X_BLOCKS),
+            block(1, c::CHUNK_Y_BLOCKS),
+            block(2, c::CHUNK_Z_BLOCKS),
+        ) * c::BLOCK_SIZE;
+
+        Camera {
+            position,
+            ..Default::default()
+        }
+    }
+
+    fn manager(
+        load_radius: usize,
+        unload_radius: usize,
+        storage: Option<WorldStorage>,
+    ) -> ChunkManager {
+        ChunkManager::new(
+            ChunkManagerConfig {
+                load_radius,
+                unload_radius,
+                loads_per_update: usize::MAX,
+            },
+            Box::new(FlatGenerator { ground: 20 }),
+            storage,
+        )
+    }
+
+    fn loaded(events: &[ChunkEvent]) -> Vec<[usize; 3]> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                ChunkEvent::Loaded(loc) => Some(*loc),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn unloaded(events: &[ChunkEvent]) -> Vec<[usize; 3]> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                ChunkEvent::Unloaded(loc) => Some(*loc),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn loads_nearest_first() {
+        let mut region = Region::default();
+        let mut chunks = manager(1, 2, None);
+
+        let events = chunks.update(&camera_at([5, 1, 5]), &mut region);
+
+        let loaded = loaded(&events);
+        assert_eq!(loaded.len(), 7, "center and its six neighbours");
+        assert_eq!(loaded[0], [5, 1, 5]);
+        assert!(loaded.contains(&[5, 0, 5]));
+        assert!(!loaded.contains(&[6, 0, 5]), "diagonal is too far");
+
+        // Generated ground
+        assert_eq!(
+            region.get_chunk([5, 0, 5]).get_block([0isize, 0, 0]).kind,
+            Block::solid().kind
+        );
+        assert_eq!(
+            region.get_chunk([5, 1, 5]).get_block([0isize, 3, 0]).kind,
+            Block::grass().kind
+        );
+        assert_eq!(
+            region.get_chunk([5, 1, 5]).get_block([0isize, 0, 0]).kind,
+            Block::dirt().kind
+        );
+        assert!(region
+            .get_chunk([5, 1, 5])
+            .get_block([0isize, 4, 0])
+            .is_transparent());
+
+        // Nothing changes when the camera stays in the same chunk
+        assert!(chunks.update(&camera_at([5, 1, 5]), &mut region).is_empty());
+    }
+
+    #[test]
+    fn unloads_with_hysteresis() {
+        let mut region = Region::default();
+        let mut chunks = manager(1, 2, None);
+
+        chunks.update(&camera_at([5, 5, 5]), &mut region);
+
+        let events = chunks.update(&camera_at([6, 5, 5]), &mut region);
+        assert!(
+            unloaded(&events).is_empty(),
+            "[4, 5, 5] is still within the unload radius"
+        );
+        assert_eq!(loaded(&events).len(), 5);
+        assert!(chunks.is_loaded([4, 5, 5]));
+
+        let events = chunks.update(&camera_at([7, 5, 5]), &mut region);
+        let unloaded = unloaded(&events);
+        assert_eq!(unloaded.len(), 5);
+        assert!(unloaded.contains(&[4, 5, 5]));
+        assert!(!chunks.is_loaded([4, 5, 5]));
+        assert_eq!(chunks.loaded_count(), 7 + 5 + 5 - 5);
+    }
+
+    #[test]
+    fn clamps_to_region() {
+        let mut region = Region::default();
+        let mut chunks = manager(1, 1, None);
+
+        let mut camera = camera_at([0, 0, 0]);
+        camera.position = camera.position.map(|v| -v);
+        let events = chunks.update(&camera, &mut region);
+        assert!(events.is_empty());
+
+        let events = chunks.update(&camera_at([0, 0, 0]), &mut region);
+        assert_eq!(loaded(&events).len(), 4);
+    }
+
+    #[test]
+    fn respects_budget() {
+        let mut region = Region::default();
+        let mut chunks = ChunkManager::new(
+            ChunkManagerConfig {
+                load_radius: 1,
+                unload_radius: 1,
+                loads_per_update: 4,
+            },
+            Box::new(FlatGenerator { ground: 0 }),
+            None,
+        );
+        let camera = camera_at([5, 5, 5]);
+
+        assert_eq!(loaded(&chunks.update(&camera, &mut region)).len(), 4);
+        assert_eq!(loaded(&chunks.update(&camera, &mut region)).len(), 3);
+        assert!(chunks.update(&camera, &mut region).is_empty());
+    }
+
+    #[test]
+    fn saves_unloaded_chunks() {
+        let path = std::env::temp_dir().join(format!("tekutonu-chunks-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+
+        let mut region = Region::default();
+        let mut chunks = manager(0, 0, Some(WorldStorage::new(path.clone())));
+
+        chunks.update(&camera_at([5, 5, 5]), &mut region);
+        region.set_block([81, 82, 83], Block::light_source());
+
+        chunks.update(&camera_at([9, 9, 9]), &mut region);
+        assert!(region.get_chunk([5, 5, 5]).is_empty());
+
+        chunks.update(&camera_at([5, 5, 5]), &mut region);
+        assert_eq!(
+            region.get_block([81, 82, 83]).unwrap().kind,
+            Block::light_source().kind
+        );
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -2,7 +2,7 @@ use cgmath::Point3;
 
 use super::types::PointIntLocal;
 
-const CHUNK_EDGE_BLOCKS: usize = 16;
+pub const CHUNK_EDGE_BLOCKS: usize = 16;
 
 pub const CHUNK_X_BLOCKS: usize = CHUNK_EDGE_BLOCKS;
 pub const CHUNK_Y_BLOCKS: usize = CHUNK_EDGE_BLOCKS;
@@ -19,6 +19,14 @@ pub const REGION_Z_CHUNKS: usize = REGION_SIDE_CHUNKS;
 
 pub const REGION_TOTAL_CHUNKS: usize = REGION_X_CHUNKS * REGION_Y_CHUNKS * REGION_Z_CHUNKS;
 
+pub const REGION_X_BLOCKS: usize = REGION_X_CHUNKS * CHUNK_X_BLOCKS;
+pub const REGION_Y_BLOCKS: usize = REGION_Y_CHUNKS * CHUNK_Y_BLOCKS;
+pub const REGION_Z_BLOCKS: usize = REGION_Z_CHUNKS * CHUNK_Z_BLOCKS;
+
+/// Edge length of a block in camera coordinates.
+/// The view matrix scales the world down by this much.
+pub const BLOCK_SIZE: f64 = 0.5;
+
 pub const BLOCK_FACES: [[Point3<f32>; 4]; 6] = {
     // l, r - left, right
     // b, t - bottom, top
@@ -59,4 +67,8 @@ pub const ADJACENCY: [PointIntLocal; 6] = [
     PointIntLocal::new(0, 1, 0),  // Y positive, top
 ];
 
+/// Indices into `BLOCK_FACES` and `ADJACENCY`
+pub const FACE_BOTTOM: usize = 4;
+pub const FACE_TOP: usize = 5;
+
 pub const LIGHT_MAX: isize = 15;
@@ -1,5 +1,7 @@
 use cgmath::{Point3, Rad, Vector3};
 
+use super::settings::Settings;
+
 pub enum GameModelEffect {
     Debug,
     TeleportCamera {
@@ -16,4 +18,6 @@ pub enum GameModelEffect {
         delta_pitch: Rad<f64>,
         delta_yaw: Rad<f64>,
     },
+    /// Replace the settings, unless the new ones are out of range.
+    ChangeSettings(Box<Settings>),
 }
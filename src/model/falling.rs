@@ -0,0 +1,321 @@
+use cgmath::Point3;
+
+use super::{
+    block::Block,
+    region::Region,
+    updates::{BlockUpdate, BlockUpdates, UpdateContext},
+};
+
+/// Acceleration of falling blocks, in blocks per tick squared.
+pub const GRAVITY: f64 = 0.04;
+/// Fastest speed of falling blocks, in blocks per tick.
+pub const TERMINAL_VELOCITY: f64 = 2.0;
+/// Ticks between a block losing its support and starting to fall.
+pub const FALL_DELAY: u64 = 2;
+
+/// A block that lost its support, on its way down.
+#[derive(Clone, Copy, Debug)]
+pub struct FallingBlock {
+    pub block: Block,
+    /// Lowest corner, in block coordinates
+    pub position: Point3<f64>,
+    /// Blocks per tick, positive is up
+    pub velocity: f64,
+}
+
+impl FallingBlock {
+    /// A block starting to fall from `loc`.
+    pub fn new(block: Block, loc: [usize; 3]) -> Self {
+        Self {
+            block,
+            position: Point3::from(loc.map(|v| v as f64)),
+            velocity: 0.0,
+        }
+    }
+
+    /// Location of the block the falling block is in.
+    pub fn location(&self) -> [usize; 3] {
+        let Point3 { x, y, z } = self.position;
+        [x as usize, y.floor() as usize, z as usize]
+    }
+
+    /// Move by one tick. Returns the location the block lands in, if it does.
+    ///
+    /// It waits without moving above chunks that are not loaded.
+    fn step(
+        &mut self,
+        region: &Region,
+        is_loaded: impl Fn([usize; 3]) -> bool,
+    ) -> Option<[usize; 3]> {
+        let [x, mut y, z] = self.location();
+        self.velocity = (self.velocity - GRAVITY).max(-TERMINAL_VELOCITY);
+        let target = self.position.y + self.velocity;
+
+        // Go down block by block, so that nothing is skipped at high speed
+        while target < y as f64 {
+            let Some(below) = y.checked_sub(1).map(|y| [x, y, z]) else {
+                // The bottom of the region
+                return Some([x, y, z]);
+            };
+
+            let (chunk, _) = Region::split_location(below).unwrap();
+            if !is_loaded(chunk) {
+                self.position.y = y as f64;
+                self.velocity = 0.0;
+                return None;
+            }
+            if !region.get_block(below).unwrap().is_replaceable() {
+                return Some([x, y, z]);
+            }
+            y -= 1;
+        }
+
+        self.position.y = target;
+        None
+    }
+}
+
+/// Blocks like sand and gravel start falling [`FALL_DELAY`] ticks after the
+/// block under them changes to one they fall through.
+pub(super) fn update(ctx: &mut UpdateContext, loc: [usize; 3], cause: BlockUpdate) {
+    match cause {
+        BlockUpdate::Changed | BlockUpdate::Neighbour => ctx.schedule(loc, FALL_DELAY),
+        BlockUpdate::Scheduled => {
+            let supported = Region::offset_location(loc, [0, -1, 0])
+                .is_none_or(|below| !ctx.region().get_block(below).unwrap().is_replaceable());
+            if supported {
+                return;
+            }
+
+            let block = *ctx.region().get_block(loc).unwrap();
+            ctx.set_block(loc, Block::air());
+            ctx.spawn(FallingBlock::new(block, loc));
+        },
+        BlockUpdate::Random => {},
+    }
+}
+
+/// Blocks falling through the world.
+///
+/// They fall through air and fluids, and become blocks again on top of
+/// anything else, replacing the fluid they land in. Light is recalculated
+/// around the block both when it leaves and when it lands.
+#[derive(Debug, Default)]
+pub struct FallingBlocks {
+    falling: Vec<FallingBlock>,
+}
+
+impl FallingBlocks {
+    /// Start simulating a block that already left the world.
+    pub fn spawn(&mut self, region: &mut Region, falling: FallingBlock) {
+        region.relight_around(falling.location());
+        self.falling.push(falling);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &FallingBlock> {
+        self.falling.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.falling.is_empty()
+    }
+
+    /// Move the falling blocks by one tick, in the order they started to
+    /// fall. Blocks that land are set in `region` and `updates` are
+    /// notified. Returns the locations they landed in.
+    ///
+    /// `is_loaded` tells whether the chunk at a location is loaded.
+    pub fn tick(
+        &mut self,
+        region: &mut Region,
+        updates: &mut BlockUpdates,
+        is_loaded: impl Fn([usize; 3]) -> bool,
+    ) -> Vec<[usize; 3]> {
+        let mut landed = Vec::new();
+
+        self.falling.retain_mut(|falling| {
+            let Some(loc) = falling.step(region, &is_loaded) else {
+                return true;
+            };
+
+            // Something may have been placed where the block is. Then it
+            // lands on top of it, or is lost if there is no room.
+            let free = (loc[1]..)
+                .map_while(|y| {
+                    Region::split_location([loc[0], y, loc[2]]).map(|_| [loc[0], y, loc[2]])
+                })
+                .find(|&loc| region.get_block(loc).unwrap().is_replaceable());
+            if let Some(loc) = free {
+                region.set_block(loc, falling.block);
+                updates.block_changed(loc);
+                region.relight_around(loc);
+                landed.push(loc);
+            }
+            false
+        });
+
+        landed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{
+        block::{BlockKind, Fluid},
+        consts as c,
+        GameModel,
+    };
+
+    /// Run the game until nothing is scheduled or falling. Returns the
+    /// blocks that changed on the way.
+    fn settle(game: &mut GameModel) -> Vec<[usize; 3]> {
+        let mut changed = Vec::new();
+        while !game.updates.is_idle() || !game.falling.is_empty() {
+            game.tick();
+            changed.append(&mut game.changed_blocks);
+            assert!(game.time < 10_000, "the world never settles");
+        }
+        changed
+    }
+
+    fn kind(game: &GameModel, loc: [usize; 3]) -> BlockKind {
+        game.world.get_block(loc).unwrap().kind
+    }
+
+    /// A world with random ticks off, so that only the blocks under test
+    /// change.
+    fn world() -> GameModel {
+        let mut game = GameModel::new(Region::default(), 0);
+        game.updates.random_ticks_per_chunk = 0;
+        game
+    }
+
+    #[test]
+    fn stacked_column_collapses() {
+        let mut game = world();
+        game.set_block([5, 0, 5], Block::solid());
+        game.set_block([5, 4, 5], Block::dirt());
+        for y in 5..10 {
+            let block = if y % 2 == 0 {
+                Block::sand()
+            } else {
+                Block::gravel()
+            };
+            game.set_block([5, y, 5], block);
+        }
+        settle(&mut game);
+        // Held up by the dirt
+        assert_eq!(kind(&game, [5, 5, 5]), BlockKind::Gravel);
+        assert_eq!(kind(&game, [5, 9, 5]), BlockKind::Gravel);
+
+        game.set_block([5, 4, 5], Block::air());
+        let changed = settle(&mut game);
+
+        // The column keeps its order and lands on the floor
+        for y in 1..6 {
+            let expected = if y % 2 == 0 {
+                BlockKind::Sand
+            } else {
+                BlockKind::Gravel
+            };
+            assert_eq!(kind(&game, [5, y, 5]), expected, "y = {}", y);
+        }
+        for y in 6..11 {
+            assert_eq!(kind(&game, [5, y, 5]), BlockKind::Air, "y = {}", y);
+        }
+        for y in 1..10 {
+            assert!(changed.contains(&[5, y, 5]), "y = {}", y);
+        }
+    }
+
+    #[test]
+    fn lands_in_fluid() {
+        let mut game = world();
+        game.set_block([5, 0, 5], Block::solid());
+        game.set_block([5, 1, 5], Block::water());
+        game.set_block([5, 8, 5], Block::sand());
+        // Flowing lava drains away, but not before the gravel lands
+        game.set_block([9, 0, 5], Block::solid());
+        game.set_block([9, 1, 5], Block::fluid(Fluid::Lava, 3));
+        game.set_block([9, 8, 5], Block::gravel());
+        settle(&mut game);
+
+        // Both replace the fluid they land in, flowing or not
+        assert_eq!(kind(&game, [5, 1, 5]), BlockKind::Sand);
+        assert_eq!(kind(&game, [9, 1, 5]), BlockKind::Gravel);
+        assert_eq!(kind(&game, [5, 2, 5]), BlockKind::Air);
+    }
+
+    #[test]
+    fn falls_across_chunks_and_relights() {
+        let mut game = world();
+        let top = c::CHUNK_Y_BLOCKS;
+        game.world.set_block([5, 2, 5], Block::solid());
+        game.world.set_block([5, top, 5], Block::sand());
+        game.world.recalculate_chunk_light([0, 1, 0]);
+        game.world.recalculate_chunk_light([0, 0, 0]);
+        // Shaded by the sand in the chunk above
+        let shaded = [5usize, top - 1, 5];
+        assert!(game.world.get_chunk([0, 0, 0]).get_light_sky(shaded) < c::LIGHT_MAX as u8);
+
+        // Notified on the first tick, starts to fall after the delay
+        game.updates.block_changed([5, top, 5]);
+        for _ in 0..=FALL_DELAY {
+            game.tick();
+        }
+        assert_eq!(game.falling.iter().count(), 1);
+        assert_eq!(
+            game.world.get_chunk([0, 0, 0]).get_light_sky(shaded),
+            c::LIGHT_MAX as u8,
+            "light comes through once the sand leaves"
+        );
+
+        settle(&mut game);
+        assert_eq!(kind(&game, [5, 3, 5]), BlockKind::Sand);
+        assert_eq!(
+            game.world
+                .get_chunk([0, 0, 0])
+                .get_light_sky([5usize, 3, 5]),
+            0
+        );
+    }
+
+    #[test]
+    fn waits_above_unloaded_chunks() {
+        let mut region = Region::default();
+        let mut updates = BlockUpdates::default();
+        let mut falling = FallingBlocks::default();
+        let top = c::CHUNK_Y_BLOCKS;
+        falling.spawn(
+            &mut region,
+            FallingBlock::new(Block::sand(), [5, top + 1, 5]),
+        );
+
+        for _ in 0..100 {
+            let landed = falling.tick(&mut region, &mut updates, |chunk| chunk != [0, 0, 0]);
+            assert!(landed.is_empty());
+        }
+        assert_eq!(falling.iter().next().unwrap().location(), [5, top, 5]);
+
+        for _ in 0..100 {
+            falling.tick(&mut region, &mut updates, |_| true);
+        }
+        assert!(falling.is_empty());
+        assert_eq!(region.get_block([5, 0, 5]).unwrap().kind, BlockKind::Sand);
+    }
+
+    #[test]
+    fn lands_on_top_of_blocks_placed_in_the_way() {
+        let mut region = Region::default();
+        let mut updates = BlockUpdates::default();
+        let mut falling = FallingBlocks::default();
+        region.set_block([5, 0, 5], Block::solid());
+        falling.spawn(&mut region, FallingBlock::new(Block::sand(), [5, 1, 5]));
+        region.set_block([5, 1, 5], Block::glass());
+
+        let landed = falling.tick(&mut region, &mut updates, |_| true);
+        assert_eq!(landed, vec![[5, 2, 5]]);
+        assert_eq!(region.get_block([5, 2, 5]).unwrap().kind, BlockKind::Sand);
+    }
+}
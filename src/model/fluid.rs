@@ -0,0 +1,306 @@
+use super::{
+    block::{Block, BlockKind, Fluid, FLUID_FALLING, FLUID_SOURCE},
+    consts as c,
+    region::Region,
+    updates::{BlockUpdate, UpdateContext},
+};
+
+const DOWN: [isize; 3] = [0, -1, 0];
+const UP: [isize; 3] = [0, 1, 0];
+/// Offsets to the horizontal neighbours, in the order of `ADJACENCY`
+const SIDES: [[isize; 3]; 4] = [
+    c::ADJACENCY[0].0,
+    c::ADJACENCY[1].0,
+    c::ADJACENCY[2].0,
+    c::ADJACENCY[3].0,
+];
+
+impl Fluid {
+    /// Ticks between a change around the fluid and its reaction to it.
+    pub fn delay(&self) -> u64 {
+        match self {
+            Self::Water => 5,
+            Self::Lava => 30,
+        }
+    }
+
+    /// Level lost with each block the fluid flows sideways.
+    pub fn decay(&self) -> u8 {
+        match self {
+            Self::Water => 1,
+            Self::Lava => 2,
+        }
+    }
+}
+
+
+fn fluid_at(region: &Region, loc: [usize; 3]) -> Option<(Fluid, u8)> {
+    match region.get_block(loc)?.kind {
+        BlockKind::Fluid { fluid, level } => Some((fluid, level)),
+        _ => None,
+    }
+}
+
+/// Whether fluid at `loc` spreads sideways, rather than falling down or
+/// sitting on the border of the region.
+fn spreads_sideways(region: &Region, loc: [usize; 3], fluid: Fluid) -> bool {
+    let Some(below) = Region::offset_location(loc, DOWN) else {
+        return false;
+    };
+
+    match region.get_block(below).unwrap().kind {
+        BlockKind::Air => false,
+        BlockKind::Fluid { fluid: f, level } if f == fluid => level == FLUID_SOURCE,
+        _ => true,
+    }
+}
+
+/// Level of flowing fluid at `loc` given the fluid around it.
+fn expected_level(region: &Region, loc: [usize; 3], fluid: Fluid) -> u8 {
+    let above = Region::offset_location(loc, UP).and_then(|above| fluid_at(region, above));
+    if above.is_some_and(|(f, _)| f == fluid) {
+        return FLUID_FALLING;
+    }
+
+    SIDES
+        .iter()
+        .filter_map(|&side| Region::offset_location(loc, side))
+        .filter_map(|side| match fluid_at(region, side) {
+            Some((f, level)) if f == fluid && spreads_sideways(region, side, fluid) => {
+                Some(level.saturating_sub(fluid.decay()))
+            },
+            _ => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Let fluid at `level` flow into `loc`.
+fn flow_into(ctx: &mut UpdateContext, loc: [usize; 3], fluid: Fluid, level: u8) {
+    let block = match ctx.region().get_block(loc).unwrap().kind {
+        BlockKind::Air => Block::fluid(fluid, level),
+        BlockKind::Fluid { fluid: f, level: l } if f == fluid => {
+            if l >= level {
+                return;
+            }
+            Block::fluid(fluid, level)
+        },
+        BlockKind::Fluid { .. } => Block::solid(),
+        _ => return,
+    };
+
+    ctx.set_block(loc, block);
+}
+
+/// Flowing water and lava.
+///
+/// A change at or next to fluid schedules its update after the delay of the
+/// fluid. Flowing fluid falls down if it can, otherwise it spreads sideways
+/// with decreasing level. Its level follows the fluid around it, so it drains
+/// away when the source is removed. Different fluids flowing into each other
+/// turn into stone.
+pub(super) fn update(ctx: &mut UpdateContext, loc: [usize; 3], fluid: Fluid, cause: BlockUpdate) {
+    match cause {
+        BlockUpdate::Changed | BlockUpdate::Neighbour => {
+            ctx.schedule(loc, fluid.delay());
+            return;
+        },
+        BlockUpdate::Scheduled => {},
+        BlockUpdate::Random => return,
+    }
+
+    let region = ctx.region();
+    let current = match region.get_block(loc).unwrap().kind {
+        BlockKind::Fluid { level, .. } => level,
+        _ => return,
+    };
+
+    let level = if current == FLUID_SOURCE {
+        current
+    } else {
+        expected_level(region, loc, fluid)
+    };
+    if level != current {
+        let block = match level {
+            0 => Block::air(),
+            level => Block::fluid(fluid, level),
+        };
+        ctx.set_block(loc, block);
+    }
+    if level == 0 {
+        return;
+    }
+
+    if let Some(below) = Region::offset_location(loc, DOWN) {
+        flow_into(ctx, below, fluid, FLUID_FALLING);
+    }
+    if !spreads_sideways(ctx.region(), loc, fluid) {
+        return;
+    }
+
+    let level = level.saturating_sub(fluid.decay());
+    if level == 0 {
+        return;
+    }
+    for side in SIDES {
+        let Some(side) = Region::offset_location(loc, side) else {
+            continue;
+        };
+        flow_into(ctx, side, fluid, level);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::updates::BlockUpdates;
+
+    /// Run the simulation until nothing is scheduled. Returns the tick it
+    /// stopped on.
+    fn settle(sim: &mut BlockUpdates, region: &mut Region, mut now: u64) -> u64 {
+        while !sim.is_idle() {
+            now += 1;
+            sim.tick(region, now);
+            assert!(now < 10_000, "the fluid never settles");
+        }
+        now
+    }
+
+    fn place(sim: &mut BlockUpdates, region: &mut Region, loc: [usize; 3], block: Block) {
+        region.set_block(loc, block);
+        sim.block_changed(loc);
+    }
+
+    fn level(region: &Region, loc: [usize; 3]) -> Option<u8> {
+        fluid_at(region, loc).map(|(_, level)| level)
+    }
+
+    /// A 5x5 basin with its floor at y = 10, inside x and z 11..16.
+    fn basin() -> Region {
+        let mut region = Region::default();
+        for x in 10..17 {
+            for z in 10..17 {
+                region.set_block([x, 10, z], Block::solid());
+                if x == 10 || x == 16 || z == 10 || z == 16 {
+                    region.set_block([x, 11, z], Block::solid());
+                }
+            }
+        }
+        region
+    }
+
+    #[test]
+    fn basin_fills_and_drains() {
+        let mut region = basin();
+        let mut sim = BlockUpdates::default();
+
+        place(&mut sim, &mut region, [13, 11, 13], Block::water());
+        let now = settle(&mut sim, &mut region, 0);
+
+        for x in 11..16usize {
+            for z in 11..16usize {
+                let distance = (x.abs_diff(13) + z.abs_diff(13)) as u8;
+                assert_eq!(level(&region, [x, 11, z]), Some(FLUID_SOURCE - distance));
+            }
+        }
+        // Nothing leaks over the walls
+        assert_eq!(level(&region, [13, 12, 13]), None);
+        assert_eq!(level(&region, [9, 11, 13]), None);
+
+        region.set_block([13, 11, 13], Block::air());
+        sim.block_changed([13, 11, 13]);
+        settle(&mut sim, &mut region, now);
+
+        for x in 11..16 {
+            for z in 11..16 {
+                assert_eq!(region.get_block([x, 11, z]).unwrap().kind, BlockKind::Air);
+            }
+        }
+    }
+
+    #[test]
+    fn falls_before_spreading() {
+        let mut region = Region::default();
+        let mut sim = BlockUpdates::default();
+        for x in 0..20 {
+            for z in 0..20 {
+                region.set_block([x, 0, z], Block::solid());
+            }
+        }
+
+        place(&mut sim, &mut region, [10, 5, 10], Block::water());
+        settle(&mut sim, &mut region, 0);
+
+        // A column down to the ground, without spreading on the way
+        for y in 1..5 {
+            assert_eq!(level(&region, [10, y, 10]), Some(FLUID_FALLING));
+            assert_eq!(level(&region, [11, y + 1, 10]), None);
+        }
+        // Then it spreads along the ground
+        assert_eq!(level(&region, [11, 1, 10]), Some(FLUID_FALLING - 1));
+        assert_eq!(level(&region, [10, 1, 16]), Some(1));
+        assert_eq!(level(&region, [10, 1, 17]), None);
+    }
+
+    #[test]
+    fn lava_is_slow_and_short() {
+        let mut region = basin();
+        let mut sim = BlockUpdates::default();
+
+        place(&mut sim, &mut region, [11, 11, 11], Block::lava());
+        sim.tick(&mut region, Fluid::Water.delay());
+        assert_eq!(level(&region, [12, 11, 11]), None, "not yet");
+
+        settle(&mut sim, &mut region, 0);
+        assert_eq!(level(&region, [12, 11, 11]), Some(6));
+        assert_eq!(level(&region, [14, 11, 11]), Some(2));
+        assert_eq!(level(&region, [15, 11, 11]), None);
+    }
+
+    #[test]
+    fn water_and_lava_make_stone() {
+        let mut region = basin();
+        let mut sim = BlockUpdates::default();
+
+        place(&mut sim, &mut region, [11, 11, 11], Block::lava());
+        place(&mut sim, &mut region, [15, 11, 15], Block::water());
+        settle(&mut sim, &mut region, 0);
+        // Water is faster and reaches the lava before it spreads
+        assert_eq!(level(&region, [11, 11, 11]), Some(FLUID_SOURCE));
+        for loc in [[12, 11, 11], [11, 11, 12]] {
+            assert_eq!(region.get_block(loc).unwrap().kind, BlockKind::Solid);
+        }
+        assert_eq!(level(&region, [13, 11, 11]), Some(2));
+    }
+
+    #[test]
+    fn lava_lights_and_water_lets_light_through() {
+        let mut region = basin();
+        let mut sim = BlockUpdates::default();
+
+        place(&mut sim, &mut region, [11, 11, 11], Block::lava());
+        place(&mut sim, &mut region, [15, 11, 15], Block::water());
+        settle(&mut sim, &mut region, 0);
+        region.recalculate_chunk_light([0, 0, 0]);
+
+        let chunk = region.get_chunk([0, 0, 0]);
+        // [11, 12, 11] is just above the lava
+        assert_eq!(chunk.get_light_local([11usize, 12, 11]), 14);
+        assert_eq!(chunk.get_light_sky([15usize, 11, 15]), c::LIGHT_MAX as u8);
+    }
+
+    #[test]
+    fn deterministic() {
+        let run = || {
+            let mut region = basin();
+            let mut sim = BlockUpdates::default();
+            place(&mut sim, &mut region, [11, 11, 11], Block::lava());
+            place(&mut sim, &mut region, [15, 11, 15], Block::water());
+            place(&mut sim, &mut region, [13, 15, 13], Block::water());
+            settle(&mut sim, &mut region, 0);
+            region.get_chunk([0, 0, 0]).to_bytes()
+        };
+
+        assert_eq!(run(), run());
+    }
+}
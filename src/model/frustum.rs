@@ -0,0 +1,167 @@
+use cgmath::{InnerSpace, Matrix, Matrix4, Point3, Vector3, Vector4};
+
+use super::consts as c;
+
+
+/// Points with `normal · p + distance >= 0` are on the inner side.
+#[derive(Clone, Copy, Debug)]
+struct Plane {
+    normal: Vector3<f32>,
+    distance: f32,
+}
+
+impl Plane {
+    fn from_row(row: Vector4<f32>) -> Self {
+        let normal = row.truncate();
+        let length = normal.magnitude();
+        Self {
+            normal: normal / length,
+            distance: row.w / length,
+        }
+    }
+
+    fn signed_distance(&self, p: Point3<f32>) -> f32 {
+        self.normal.dot(Vector3::new(p.x, p.y, p.z)) + self.distance
+    }
+}
+
+
+/// The volume visible through a camera.
+#[derive(Clone, Copy, Debug)]
+pub struct Frustum {
+    /// Left, right, bottom, top, near, far
+    planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extract the frustum from a matrix taking points to OpenGL clip space,
+    /// such as `proj * view`.
+    pub fn from_matrix(m: Matrix4<f32>) -> Self {
+        let [r0, r1, r2, r3] = [0, 1, 2, 3].map(|i| m.row(i));
+
+        Self {
+            planes: [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r3 + r2, r3 - r2].map(Plane::from_row),
+        }
+    }
+
+    pub fn contains_point(&self, p: Point3<f32>) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(p) >= 0.0)
+    }
+
+    /// Whether any part of the box may be visible. Boxes near the corners of
+    /// the frustum can be reported visible when they are not.
+    pub fn intersects_box(&self, min: Point3<f32>, max: Point3<f32>) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the normal
+            let n = plane.normal;
+            let corner = Point3::new(
+                if n.x >= 0.0 { max.x } else { min.x },
+                if n.y >= 0.0 { max.y } else { min.y },
+                if n.z >= 0.0 { max.z } else { min.z },
+            );
+            plane.signed_distance(corner) >= 0.0
+        })
+    }
+
+    /// Whether any part of the chunk at `loc` may be visible, with the
+    /// frustum in block coordinates of the region.
+    pub fn intersects_chunk(&self, loc: [usize; 3]) -> bool {
+        let min = Point3::from(loc.map(|v| (v * c::CHUNK_EDGE_BLOCKS) as f32));
+        let max = min + Vector3::from([c::CHUNK_EDGE_BLOCKS as f32; 3]);
+        self.intersects_box(min, max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Rad, Transform};
+
+    use super::*;
+    use crate::model::{settings::Settings, test_util::Sequence, Camera};
+
+    fn frustum(camera: &Camera) -> Frustum {
+        Frustum::from_matrix(
+            Settings::default().projection_matrix(16.0 / 9.0) * camera.view_matrix(),
+        )
+    }
+
+    /// Camera in the middle of the region looking along +Z.
+    fn camera() -> Camera {
+        Camera {
+            position: Point3::new(64.0, 64.0, 64.0),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn agrees_with_clip_space() {
+        let mut camera = camera();
+        camera.pitch = Rad(0.3);
+        camera.yaw = Rad(-1.2);
+        let m = Settings::default().projection_matrix(16.0 / 9.0) * camera.view_matrix();
+        let f = Frustum::from_matrix(m);
+
+        let mut sequence = Sequence::new(7);
+
+        for _ in 0..10_000 {
+            let p = Point3::new(sequence.unit(), sequence.unit(), sequence.unit())
+                .map(|v| v * 512.0 - 128.0);
+
+            let clip = m * p.to_homogeneous();
+            let xyz = [clip.x, clip.y, clip.z];
+
+            // Too close to a plane to tell with floats
+            if xyz
+                .iter()
+                .any(|v| (clip.w - v.abs()).abs() < 1e-3 * clip.w.abs())
+            {
+                continue;
+            }
+
+            let inside = xyz.iter().all(|v| v.abs() <= clip.w);
+
+            assert_eq!(f.contains_point(p), inside, "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn culls_chunks_behind_and_beside() {
+        let f = frustum(&camera());
+        // The camera is in chunk [8, 8, 8]
+        assert!(f.intersects_chunk([8, 8, 8]));
+        assert!(f.intersects_chunk([8, 8, 10]));
+
+        assert!(!f.intersects_chunk([8, 8, 6]), "behind");
+        assert!(!f.intersects_chunk([2, 8, 9]), "far to the left");
+        assert!(!f.intersects_chunk([14, 8, 9]), "far to the right");
+    }
+
+    #[test]
+    fn far_plane_is_in_camera_units() {
+        // The far plane is 100 camera units away, which are half a block each
+        let f = frustum(&camera());
+        let z = 128.0 + 100.0 / c::BLOCK_SIZE as f32;
+
+        assert!(f.contains_point(Point3::new(128.0, 128.0, z - 5.0)));
+        assert!(!f.contains_point(Point3::new(128.0, 128.0, z + 5.0)));
+    }
+
+    #[test]
+    fn pitch_follows_y_up() {
+        // Pitch turns the camera towards negative Y
+        let mut camera = camera();
+        camera.pitch = Rad(1.2);
+        let f = frustum(&camera);
+
+        assert!(f.intersects_chunk([8, 5, 8]), "below");
+        assert!(!f.intersects_chunk([8, 11, 8]), "above");
+
+        // The look vector is the center of the screen
+        let look = camera.get_look().map(|v| v as f32);
+        let ahead = camera.get_block_position().map(|v| v as f32) + look * 10.0;
+        let view = camera.view_matrix().transform_point(ahead);
+        assert!(view.x.abs() < 1e-3 && view.y.abs() < 1e-3);
+    }
+}
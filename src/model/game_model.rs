@@ -1,7 +1,19 @@
-use cgmath::{InnerSpace, Matrix4, Point3, Rad, Vector3};
-use tracing::instrument;
-
-use super::{block::Block, chunk::Chunk, consts, effect::GameModelEffect, region::Region};
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Rad, Vector3};
+use tracing::{instrument, warn};
+
+use super::{
+    atmosphere::Atmosphere,
+    block::Block,
+    chunk_manager::{ChunkEvent, ChunkManager},
+    consts,
+    effect::GameModelEffect,
+    falling::FallingBlocks,
+    generator::{ChunkGenerator, DemoGenerator},
+    raycast::{self, Hit},
+    region::Region,
+    settings::Settings,
+    updates::BlockUpdates,
+};
 use crate::util::{limit_yaw, normalize_angle};
 
 pub struct Camera {
@@ -15,8 +27,9 @@ pub struct Camera {
 
 impl Default for Camera {
     fn default() -> Self {
+        // Just above the corner of the demo chunk
         Self {
-            position: Point3::new(0.0, 0.5, 0.0),
+            position: Point3::new(8.0, 8.5, 8.0),
             pitch: Rad(0.0),
             yaw: Rad(0.0),
         }
@@ -67,6 +80,40 @@ impl Camera {
         Vector3::new(look.z, 0.0, -look.x).normalize()
     }
 
+    /// Position of the camera in block coordinates.
+    pub fn get_block_position(&self) -> Point3<f64> {
+        self.position / consts::BLOCK_SIZE
+    }
+
+    /// Location of the chunk the camera is in. May be outside of the region.
+    pub fn get_chunk_location(&self) -> [isize; 3] {
+        let p = self.get_block_position();
+        [
+            (p.x / consts::CHUNK_X_BLOCKS as f64).floor() as isize,
+            (p.y / consts::CHUNK_Y_BLOCKS as f64).floor() as isize,
+            (p.z / consts::CHUNK_Z_BLOCKS as f64).floor() as isize,
+        ]
+    }
+
+    /// Matrix from block coordinates to view space.
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        let position = self.position.map(|v| v as f32);
+        let direction = self.get_look().map(|v| v as f32);
+
+        // Vulkan has Y pointing down
+        let view = Matrix4::look_to_rh(position, direction, Vector3::new(0.0, -1.0, 0.0));
+        let scale = Matrix4::from_scale(consts::BLOCK_SIZE as f32);
+
+        view * scale
+    }
+
+    /// Rotation of the view, without moving to the camera. For things
+    /// infinitely far away, like the sky.
+    pub fn rotation_matrix(&self) -> Matrix4<f32> {
+        let direction = self.get_look().map(|v| v as f32);
+        Matrix4::look_to_rh(Point3::origin(), direction, Vector3::new(0.0, -1.0, 0.0))
+    }
+
     /// `v` is relative to the camera look. Get `v'` in real world.
     ///
     /// self.camera_to_world(self.get_look()) === [0.0, 0.0, 1.0]
@@ -85,6 +132,20 @@ impl Camera {
 pub struct GameModel {
     pub camera: Camera,
     pub world: Region,
+    /// Number of simulation ticks since the world was created
+    pub time: u64,
+    /// Loads chunks around the camera. `None` when someone else (e.g. a server)
+    /// takes care of the world contents.
+    pub chunks: Option<ChunkManager>,
+    pub updates: BlockUpdates,
+    pub falling: FallingBlocks,
+    /// Sky and fog around the camera, follows the camera every tick
+    pub atmosphere: Atmosphere,
+    /// Blocks changed by the simulation in the last tick
+    pub changed_blocks: Vec<[usize; 3]>,
+    pub settings: Settings,
+    /// The settings changed since [`GameModel::take_changed_settings`]
+    settings_changed: bool,
 }
 
 impl Default for GameModel {
@@ -92,56 +153,90 @@ impl Default for GameModel {
     fn default() -> Self {
         let mut world = Region::default();
 
-        let s = Block::solid();
-        let mut c = Chunk::default();
-
-        // Zero
-        c.set_block([0isize, 0, 0], s);
-
-        // Orientation
-        c.set_block([3isize, 3, 3], s);
-        c.set_block([3isize, 3, 4], s);
-        c.set_block([3isize, 4, 3], s);
-        c.set_block([4isize, 3, 3], s);
-
-        // Smileyface
-        c.set_block([1isize, 2, 8], s);
-        c.set_block([2isize, 1, 8], s);
-        c.set_block([3isize, 1, 8], s);
-        c.set_block([4isize, 1, 8], s);
-        c.set_block([5isize, 2, 8], s);
-        c.set_block([2isize, 4, 8], s);
-        c.set_block([4isize, 4, 8], s);
+        world.set_chunk([1, 1, 1], DemoGenerator.generate([1, 1, 1]));
+        world.recalculate_chunk_light([1, 1, 1]);
 
-        c.set_block([5isize, 5, 5], Block::light_source());
+        Self::new(world, 0)
+    }
+}
 
-        c.set_block([14isize, 14, 14], Block::light_source());
+impl GameModel {
+    pub fn new(world: Region, time: u64) -> Self {
+        Self {
+            camera: Default::default(),
+            world,
+            time,
+            chunks: None,
+            updates: BlockUpdates::default(),
+            falling: FallingBlocks::default(),
+            atmosphere: Atmosphere::default(),
+            changed_blocks: Vec::new(),
+            settings: Settings::default(),
+            settings_changed: false,
+        }
+    }
 
-        // Wall
-        for y in 0..consts::CHUNK_Y_BLOCKS {
-            for z in 0..consts::CHUNK_Z_BLOCKS {
-                c.set_block([consts::CHUNK_X_BLOCKS - 1, y, z], s);
-            }
+    /// An empty world that gets filled in around the camera as it moves.
+    pub fn with_chunk_manager(chunks: ChunkManager) -> Self {
+        Self {
+            chunks: Some(chunks),
+            ..Self::new(Region::default(), 0)
         }
+    }
 
-        // Roof
-        for x in 0..consts::CHUNK_X_BLOCKS {
-            for z in 5..consts::CHUNK_Z_BLOCKS {
-                c.set_block([x, consts::CHUNK_Y_BLOCKS - 1, z], s);
-            }
+    /// Set a block by its location in the world and let the simulation react
+    /// to it. Returns `false` if the location is outside of the world.
+    pub fn set_block(&mut self, loc: [usize; 3], block: Block) -> bool {
+        if !self.world.set_block(loc, block) {
+            return false;
         }
+        self.updates.block_changed(loc);
+        true
+    }
 
-        world.set_chunk([1, 1, 1], c);
-        world.recalculate_chunk_light([1, 1, 1]);
+    /// The settings, if they changed since the last call.
+    pub fn take_changed_settings(&mut self) -> Option<&Settings> {
+        std::mem::take(&mut self.settings_changed).then_some(&self.settings)
+    }
 
-        Self {
-            camera: Default::default(),
-            world,
+    /// The block the camera looks at, if it is within reach.
+    pub fn target(&self) -> Option<Hit> {
+        raycast::cast(
+            &self.world,
+            self.camera.get_block_position(),
+            self.camera.get_look(),
+            raycast::REACH,
+        )
+    }
+
+    /// Advance the simulation by one tick.
+    pub fn tick(&mut self) -> Vec<ChunkEvent> {
+        self.time += 1;
+        self.changed_blocks = self.updates.tick(&mut self.world, self.time);
+        for falling in self.updates.take_spawned() {
+            self.falling.spawn(&mut self.world, falling);
+        }
+        let chunks = &self.chunks;
+        let landed = self
+            .falling
+            .tick(&mut self.world, &mut self.updates, |loc| {
+                chunks.as_ref().is_none_or(|chunks| chunks.is_loaded(loc))
+            });
+        self.changed_blocks.extend(landed);
+        self.atmosphere = Atmosphere::at(&self.world, self.camera.get_block_position());
+
+        match &mut self.chunks {
+            Some(chunks) => chunks.update(&self.camera, &mut self.world),
+            None => Vec::new(),
         }
     }
-}
 
-impl GameModel {
+    /// Follow a world simulated by a server, which sends every change: only
+    /// what depends on the camera is updated.
+    pub fn follow(&mut self) {
+        self.atmosphere = Atmosphere::at(&self.world, self.camera.get_block_position());
+    }
+
     pub fn apply_effect(&mut self, effect: GameModelEffect) {
         use GameModelEffect::*;
 
@@ -167,6 +262,13 @@ impl GameModel {
                 let movement = self.camera.camera_to_world(direction);
                 self.camera.position += movement;
             },
+            ChangeSettings(settings) => match settings.validate() {
+                Ok(()) => {
+                    self.settings = *settings;
+                    self.settings_changed = true;
+                },
+                Err(e) => warn!("Ignoring new settings: {}", e),
+            },
         }
     }
 }
@@ -175,7 +277,7 @@ impl GameModel {
 mod test {
     use cgmath::assert_relative_eq;
 
-    use super::Camera;
+    use super::{Camera, GameModel, GameModelEffect};
 
     #[test]
     fn camera_to_world() {
@@ -187,4 +289,23 @@ mod test {
         assert_relative_eq!(v.y, 0.0);
         assert_relative_eq!(v.z, 1.0);
     }
+
+    #[test]
+    fn change_settings() {
+        let mut game = GameModel::default();
+        assert_eq!(game.take_changed_settings(), None);
+
+        let mut settings = game.settings.clone();
+        settings.fov = 60.0;
+        game.apply_effect(GameModelEffect::ChangeSettings(Box::new(settings.clone())));
+        assert_eq!(game.take_changed_settings(), Some(&settings));
+        assert_eq!(game.take_changed_settings(), None);
+
+        // Out of range
+        let mut invalid = settings.clone();
+        invalid.fov = 1.0;
+        game.apply_effect(GameModelEffect::ChangeSettings(Box::new(invalid)));
+        assert_eq!(game.take_changed_settings(), None);
+        assert_eq!(game.settings, settings);
+    }
 }
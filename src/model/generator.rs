@@ -0,0 +1,128 @@
+use super::{block::Block, chunk::Chunk, consts as c};
+
+
+/// Source of chunks that were never saved.
+pub trait ChunkGenerator: Send {
+    fn generate(&self, loc: [usize; 3]) -> Chunk;
+}
+
+
+/// A single hand-made chunk at `[1, 1, 1]` and nothing else.
+pub struct DemoGenerator;
+
+impl ChunkGenerator for DemoGenerator {
+    fn generate(&self, loc: [usize; 3]) -> Chunk {
+        let mut c = Chunk::default();
+
+        if loc != [1, 1, 1] {
+            return c;
+        }
+
+        let s = Block::solid();
+
+        // Zero
+        c.set_block([0isize, 0, 0], s);
+
+        // Orientation
+        c.set_block([3isize, 3, 3], s);
+        c.set_block([3isize, 3, 4], s);
+        c.set_block([3isize, 4, 3], s);
+        c.set_block([4isize, 3, 3], s);
+
+        // Smileyface
+        c.set_block([1isize, 2, 8], s);
+        c.set_block([2isize, 1, 8], s);
+        c.set_block([3isize, 1, 8], s);
+        c.set_block([4isize, 1, 8], s);
+        c.set_block([5isize, 2, 8], s);
+        c.set_block([2isize, 4, 8], s);
+        c.set_block([4isize, 4, 8], s);
+
+        c.set_block([5isize, 5, 5], Block::light_source());
+
+        c.set_block([14isize, 14, 14], Block::light_source());
+
+        // Patch of grass
+        for x in 8..11 {
+            c.set_block([x, 0isize, 1], Block::dirt());
+            c.set_block([x, 1isize, 1], Block::grass());
+        }
+        c.set_block([11isize, 0, 1], Block::grass());
+
+        // Ground under the pond and the heap
+        for x in 8..14 {
+            for z in 3..11 {
+                c.set_block([x, 0isize, z], s);
+            }
+        }
+
+        // Pond behind a glass pane
+        for x in 8..11 {
+            for z in 4..7 {
+                c.set_block([x, 1isize, z], Block::water());
+            }
+            c.set_block([x, 2isize, 3], Block::glass());
+        }
+
+        // Heap of sand and gravel, resting on the ground
+        for x in 12..14 {
+            c.set_block([x, 1isize, 10], Block::sand());
+        }
+        c.set_block([12isize, 2, 10], Block::gravel());
+
+        // Wall
+        for y in 0..c::CHUNK_Y_BLOCKS {
+            for z in 0..c::CHUNK_Z_BLOCKS {
+                c.set_block([c::CHUNK_X_BLOCKS - 1, y, z], s);
+            }
+        }
+
+        // Roof
+        for x in 0..c::CHUNK_X_BLOCKS {
+            for z in 5..c::CHUNK_Z_BLOCKS {
+                c.set_block([x, c::CHUNK_Y_BLOCKS - 1, z], s);
+            }
+        }
+
+        c
+    }
+}
+
+
+/// Solid ground up to a fixed height, covered with a layer of grass on top of
+/// a few layers of dirt.
+pub struct FlatGenerator {
+    /// Number of solid block layers from the bottom of the region
+    pub ground: usize,
+}
+
+impl FlatGenerator {
+    const DIRT_LAYERS: usize = 3;
+}
+
+impl ChunkGenerator for FlatGenerator {
+    fn generate(&self, [_, cy, _]: [usize; 3]) -> Chunk {
+        let mut chunk = Chunk::default();
+
+        for y in 0..c::CHUNK_Y_BLOCKS {
+            let height = cy * c::CHUNK_Y_BLOCKS + y;
+            if height >= self.ground {
+                break;
+            }
+
+            let block = match self.ground - height {
+                1 => Block::grass(),
+                depth if depth <= 1 + Self::DIRT_LAYERS => Block::dirt(),
+                _ => Block::solid(),
+            };
+
+            for x in 0..c::CHUNK_X_BLOCKS {
+                for z in 0..c::CHUNK_Z_BLOCKS {
+                    chunk.set_block([x, y, z], block);
+                }
+            }
+        }
+
+        chunk
+    }
+}
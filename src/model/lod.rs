@@ -0,0 +1,414 @@
+use cgmath::Vector3;
+
+use super::{
+    block::{Block, BlockKind},
+    chunk::{AdjacentDirection, Chunk, ChunkAdjacent, SurroundingChunks},
+    consts as c,
+    mesh::{FaceLight, RenderData},
+    types as t,
+};
+
+
+/// How coarsely a chunk is meshed. Coarser levels merge cubes of blocks into
+/// single cells.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Lod {
+    #[default]
+    Full,
+    Half,
+    Quarter,
+    Eighth,
+}
+
+impl Lod {
+    /// Edge length of a cell in blocks.
+    pub fn scale(&self) -> usize {
+        1 << *self as usize
+    }
+
+    /// Number of cells along a chunk edge.
+    pub fn cells(&self) -> usize {
+        c::CHUNK_EDGE_BLOCKS / self.scale()
+    }
+}
+
+
+/// Distances from the camera, in chunks, from which chunks are meshed at
+/// coarser levels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LodConfig {
+    pub half: usize,
+    pub quarter: usize,
+    pub eighth: usize,
+}
+
+impl Default for LodConfig {
+    fn default() -> Self {
+        Self {
+            half: 3,
+            quarter: 5,
+            eighth: 8,
+        }
+    }
+}
+
+impl LodConfig {
+    /// Level of detail of the chunk at `loc` seen from the chunk `camera` is
+    /// in.
+    pub fn select(&self, camera: [isize; 3], loc: [usize; 3]) -> Lod {
+        let distance_squared: usize = camera
+            .iter()
+            .zip(loc)
+            .map(|(&a, b)| a.abs_diff(b as isize).pow(2))
+            .sum();
+
+        match distance_squared {
+            d if d >= self.eighth.pow(2) => Lod::Eighth,
+            d if d >= self.quarter.pow(2) => Lod::Quarter,
+            d if d >= self.half.pow(2) => Lod::Half,
+            _ => Lod::Full,
+        }
+    }
+}
+
+
+/// A cell of a downsampled chunk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cell {
+    pub kind: BlockKind,
+    /// Brightest light of the transparent blocks in the cell
+    pub light: u8,
+}
+
+impl Cell {
+    pub fn is_transparent(&self) -> bool {
+        Block { kind: self.kind }.is_transparent()
+    }
+}
+
+
+/// A chunk with cubes of `lod.scale()` blocks merged into single cells.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Downsampled {
+    lod: Lod,
+    cells: Vec<Cell>,
+}
+
+impl Downsampled {
+    /// Merge the blocks of each cell into the dominant one.
+    ///
+    /// A cell is opaque if at least half of its blocks are. It then takes the
+    /// most common opaque kind, ties going to the kind that reaches higher up,
+    /// so that grass stays on top of dirt.
+    pub fn of(chunk: &Chunk, lod: Lod) -> Self {
+        let n = lod.cells();
+        let s = lod.scale();
+        let mut cells = Vec::with_capacity(n * n * n);
+
+        for cx in 0..n {
+            for cy in 0..n {
+                for cz in 0..n {
+                    // Opaque kinds in the order they are found, top down
+                    let mut counts: Vec<(BlockKind, usize)> = Vec::new();
+                    let mut opaque = 0;
+                    let mut light = 0;
+
+                    for y in (cy * s..(cy + 1) * s).rev() {
+                        for x in cx * s..(cx + 1) * s {
+                            for z in cz * s..(cz + 1) * s {
+                                let block = chunk.get_block([x, y, z]);
+                                if block.is_transparent() {
+                                    light = light.max(
+                                        chunk
+                                            .get_light_sky([x, y, z])
+                                            .max(chunk.get_light_local([x, y, z])),
+                                    );
+                                    continue;
+                                }
+
+                                opaque += 1;
+                                match counts.iter_mut().find(|(kind, _)| *kind == block.kind) {
+                                    Some((_, count)) => *count += 1,
+                                    None => counts.push((block.kind, 1)),
+                                }
+                            }
+                        }
+                    }
+
+                    let kind = if opaque * 2 >= s * s * s {
+                        // `max_by_key` would prefer the last of equal counts
+                        counts
+                            .iter()
+                            .rev()
+                            .max_by_key(|(_, count)| count)
+                            .unwrap()
+                            .0
+                    } else {
+                        BlockKind::Air
+                    };
+                    cells.push(Cell { kind, light });
+                }
+            }
+        }
+
+        Self { lod, cells }
+    }
+
+    pub fn lod(&self) -> Lod {
+        self.lod
+    }
+
+    pub fn get(&self, loc: [usize; 3]) -> Cell {
+        let n = self.lod.cells();
+        let [x, y, z] = loc;
+        self.cells[(x * n + y) * n + z]
+    }
+}
+
+
+/// Neighbour of a chunk that is meshed at another level of detail. Its blocks
+/// read as air so that faces along the seam are kept, which keeps gaps from
+/// opening where the two meshes disagree. Light is read through.
+struct Seam<'a>(&'a dyn ChunkAdjacent);
+
+static SEAM_BLOCK: Block = Block {
+    kind: BlockKind::Air,
+};
+
+impl ChunkAdjacent for Seam<'_> {
+    fn get_block(&self, _loc: t::PointIntLocal) -> &Block {
+        &SEAM_BLOCK
+    }
+
+    fn get_light_local(&self, loc: t::PointIntLocal) -> u8 {
+        self.0.get_light_local(loc)
+    }
+
+    fn get_light_sky(&self, loc: t::PointIntLocal) -> u8 {
+        self.0.get_light_sky(loc)
+    }
+}
+
+/// Call `f` with the chunks of `around` that have `seams` set (in the order of
+/// [`AdjacentDirection::ALL`]) replaced by seams.
+pub fn with_seams<R>(
+    around: SurroundingChunks,
+    seams: [bool; 6],
+    f: impl FnOnce(SurroundingChunks) -> R,
+) -> R {
+    let wrapped = AdjacentDirection::ALL.map(|d| Seam(around.get_chunk_for_direction(&d)));
+    let chunks = AdjacentDirection::ALL.map(|d| {
+        let chunk = around.get_chunk_for_direction(&d);
+        if seams[d as usize] {
+            &wrapped[d as usize] as &dyn ChunkAdjacent
+        } else {
+            chunk
+        }
+    });
+
+    f(SurroundingChunks::new(chunks))
+}
+
+
+/// Mesh a downsampled chunk, one quad per visible cell face.
+///
+/// `around` are the neighbours downsampled to the same level, in the order of
+/// [`AdjacentDirection::ALL`]. Missing ones are open sky, except for the one
+/// below. Faces towards neighbours with `seams` set are kept even when they
+/// are hidden, see [`with_seams`].
+pub fn mesh(
+    down: &Downsampled,
+    around: [Option<&Downsampled>; 6],
+    seams: [bool; 6],
+    global_offset: Vector3<f32>,
+) -> RenderData {
+    let n = down.lod.cells() as isize;
+    let s = down.lod.scale() as f32;
+    let tex_coords = [[0.0, 0.0], [s, 0.0], [s, s], [0.0, s]];
+    let mut data = RenderData::default();
+
+    // Cell next to `loc`, which may lie in a neighbour
+    let neighbour = |loc: [isize; 3]| -> Cell {
+        if loc.iter().all(|v| (0..n).contains(v)) {
+            return down.get(loc.map(|v| v as usize));
+        }
+
+        let direction = AdjacentDirection::from(t::PointIntLocal::from(
+            loc.map(|v| v * down.lod.scale() as isize),
+        ));
+        let local = loc.map(|v| v.rem_euclid(n) as usize);
+        match around[direction as usize] {
+            Some(chunk) if seams[direction as usize] => Cell {
+                kind: BlockKind::Air,
+                light: chunk.get(local).light,
+            },
+            Some(chunk) => chunk.get(local),
+            None if direction == AdjacentDirection::YNeg => Cell {
+                kind: BlockKind::Air,
+                light: 0,
+            },
+            None => Cell {
+                kind: BlockKind::Air,
+                light: c::LIGHT_MAX as u8,
+            },
+        }
+    };
+
+    for x in 0..n {
+        for y in 0..n {
+            for z in 0..n {
+                let cell = down.get([x, y, z].map(|v| v as usize));
+                if cell.is_transparent() {
+                    continue;
+                }
+
+                let offset = Vector3::new(x, y, z).map(|v| v as f32 * s);
+                for (i, face) in c::BLOCK_FACES.iter().enumerate() {
+                    let adjacent = c::ADJACENCY[i].0;
+                    let next = neighbour([x + adjacent[0], y + adjacent[1], z + adjacent[2]]);
+                    if !next.is_transparent() {
+                        continue;
+                    }
+
+                    let corners = face.map(|p| p * s + offset);
+                    data.push_quad(
+                        corners,
+                        tex_coords,
+                        cell.kind.texture(i),
+                        FaceLight::flat(next.light),
+                    );
+                }
+            }
+        }
+    }
+
+    data.translate(global_offset);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{mesh::MeshConfig, test_util::filled};
+
+    const LODS: [Lod; 4] = [Lod::Full, Lod::Half, Lod::Quarter, Lod::Eighth];
+
+    #[test]
+    fn coarser_with_distance() {
+        let config = LodConfig::default();
+        let camera = [4, 4, 4];
+
+        assert_eq!(config.select(camera, [4, 4, 4]), Lod::Full);
+        assert_eq!(config.select(camera, [6, 4, 4]), Lod::Full);
+        assert_eq!(config.select(camera, [7, 4, 4]), Lod::Half);
+        assert_eq!(config.select(camera, [4, 4, 9]), Lod::Quarter);
+        assert_eq!(config.select(camera, [12, 4, 4]), Lod::Eighth);
+        // The camera may be outside of the region
+        assert_eq!(config.select([-8, 4, 4], [0, 4, 4]), Lod::Eighth);
+    }
+
+    #[test]
+    fn dominant_block() {
+        let mut chunk = Chunk::default();
+        // Cell [0, 0, 0]: mostly dirt
+        for loc in [[0usize, 0, 0], [1, 0, 0], [0, 0, 1], [1, 0, 1], [0, 1, 0]] {
+            chunk.set_block(loc, Block::dirt());
+        }
+        // Cell [1, 0, 0]: grass above dirt, half each
+        for x in 2usize..4 {
+            for z in 0..2 {
+                chunk.set_block([x, 0, z], Block::dirt());
+                chunk.set_block([x, 1, z], Block::grass());
+            }
+        }
+        // Cell [2, 0, 0]: mostly air
+        for loc in [[4usize, 0, 0], [5, 0, 0], [4, 0, 1]] {
+            chunk.set_block(loc, Block::solid());
+        }
+
+        let down = Downsampled::of(&chunk, Lod::Half);
+
+        assert_eq!(down.get([0, 0, 0]).kind, BlockKind::Dirt);
+        assert_eq!(down.get([1, 0, 0]).kind, BlockKind::Grass);
+        assert_eq!(down.get([2, 0, 0]).kind, BlockKind::Air);
+        assert_eq!(down.get([3, 3, 3]).kind, BlockKind::Air);
+    }
+
+    #[test]
+    fn brightest_light_of_open_blocks() {
+        let mut chunk = filled(Block::solid());
+        chunk.set_block([5usize, 5, 5], Block::air());
+        chunk.set_block([5usize, 5, 6], Block::light_source());
+        SurroundingChunks::with_missing_as_sky([None; 6], |around| chunk.recalculate_light(around));
+
+        let down = Downsampled::of(&chunk, Lod::Eighth);
+
+        assert_eq!(
+            down.get([0, 0, 0]).light,
+            chunk.get_light_local([5usize, 5, 5])
+        );
+        assert!(down.get([0, 0, 0]).light > 0);
+        assert_eq!(down.get([1, 1, 1]).light, 0);
+    }
+
+    #[test]
+    fn solid_chunk_is_a_closed_box() {
+        let chunk = filled(Block::solid());
+
+        for lod in LODS {
+            let down = Downsampled::of(&chunk, lod);
+            let data = mesh(&down, [None; 6], [false; 6], Vector3::new(0.0, 0.0, 0.0));
+
+            assert_eq!(data.quad_count(), 6 * lod.cells().pow(2), "{:?}", lod);
+            assert!(data.vertices.iter().all(|v| {
+                [v.x, v.y, v.z]
+                    .iter()
+                    .all(|v| (0.0..=c::CHUNK_EDGE_BLOCKS as f32).contains(v))
+            }));
+            // Textures still repeat once per block
+            let max = data
+                .tex_coords
+                .iter()
+                .flatten()
+                .fold(0.0, |a: f32, &b| a.max(b));
+            assert_eq!(max, lod.scale() as f32);
+        }
+    }
+
+    #[test]
+    fn seams_keep_border_faces() {
+        let chunk = filled(Block::solid());
+        let down = Downsampled::of(&chunk, Lod::Quarter);
+        let around = [Some(&down); 6];
+        let offset = Vector3::new(0.0, 0.0, 0.0);
+
+        assert_eq!(mesh(&down, around, [false; 6], offset).quad_count(), 0);
+
+        let mut seams = [false; 6];
+        seams[AdjacentDirection::XPos as usize] = true;
+        let data = mesh(&down, around, seams, offset);
+        assert_eq!(data.quad_count(), Lod::Quarter.cells().pow(2));
+        assert!(data.vertices.iter().all(|v| v.x == 16.0));
+    }
+
+    #[test]
+    fn seams_at_full_detail() {
+        let chunk = filled(Block::solid());
+        let around = SurroundingChunks::new([&chunk as &dyn ChunkAdjacent; 6]);
+        let offset = Vector3::new(0.0, 0.0, 0.0);
+        let config = MeshConfig::default();
+
+        let data = with_seams(around, [false; 6], |around| {
+            chunk.get_render_data(around, offset, config)
+        });
+        assert_eq!(data.quad_count(), 0);
+
+        let mut seams = [false; 6];
+        seams[AdjacentDirection::YPos as usize] = true;
+        let data = with_seams(around, seams, |around| {
+            chunk.get_render_data(around, offset, config)
+        });
+        assert_eq!(data.quad_count(), 1);
+        assert!(data.vertices.iter().all(|v| v.y == 16.0));
+    }
+}
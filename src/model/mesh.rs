@@ -0,0 +1,788 @@
+use cgmath::{EuclideanSpace, MetricSpace, Point3, Vector3};
+
+use super::{
+    block::{Block, BlockKind},
+    chunk::{Chunk, SurroundingChunks},
+    consts as c,
+    falling::FallingBlock,
+    region::Region,
+    types as t,
+};
+
+/// Texture coordinates of the four vertices of a face, see `BLOCK_FACES`.
+const TEX_CORNERS: [[f32; 2]; 4] = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+
+
+/// How chunk geometry is assembled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mesher {
+    /// One quad per visible block face
+    Naive,
+    /// Adjacent coplanar faces of the same block kind and light are merged
+    /// into larger quads
+    #[default]
+    Greedy,
+}
+
+
+/// How chunks are meshed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MeshConfig {
+    pub mesher: Mesher,
+    /// Interpolate light between vertices and darken corners (ambient
+    /// occlusion). Otherwise every face is lit evenly.
+    pub smooth_lighting: bool,
+}
+
+impl Default for MeshConfig {
+    fn default() -> Self {
+        Self {
+            mesher: Mesher::default(),
+            smooth_lighting: true,
+        }
+    }
+}
+
+
+/// Light at the four vertices of a face, in the order of `BLOCK_FACES`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FaceLight {
+    pub lights: [u8; 4],
+    /// Ambient occlusion, from 0 (fully occluded) to 3 (open)
+    pub occlusion: [u8; 4],
+}
+
+impl FaceLight {
+    pub const OPEN: u8 = 3;
+
+    /// The same light at every vertex, with no occlusion.
+    pub fn flat(light: u8) -> Self {
+        Self {
+            lights: [light; 4],
+            occlusion: [Self::OPEN; 4],
+        }
+    }
+
+    /// Whether the quad should be split along the 1-3 diagonal rather than
+    /// 0-2. Splitting along the brighter diagonal keeps a single dark corner
+    /// from bleeding across the whole quad.
+    fn is_flipped(&self) -> bool {
+        let (l, o) = (self.lights, self.occlusion);
+        (o[0] + o[2], l[0] + l[2]) < (o[1] + o[3], l[1] + l[3])
+    }
+}
+
+
+/// Geometry ready to be uploaded: indexed quads, two triangles each.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RenderData {
+    pub vertices: Vec<Point3<f32>>,
+    /// Texture coordinates. Merged faces repeat the texture once per block.
+    pub tex_coords: Vec<[f32; 2]>,
+    /// Texture of each vertex, index into `block::TEXTURES`
+    pub textures: Vec<u32>,
+    /// Light levels
+    pub lights: Vec<u8>,
+    /// Ambient occlusion, see [`FaceLight::occlusion`]
+    pub occlusion: Vec<u8>,
+    pub indices: Vec<usize>,
+}
+
+impl RenderData {
+    pub fn push_quad(
+        &mut self,
+        corners: [Point3<f32>; 4],
+        tex_coords: [[f32; 2]; 4],
+        texture: u32,
+        light: FaceLight,
+    ) {
+        let i = self.vertices.len();
+        // Four vertices
+        self.vertices.extend(corners);
+        self.tex_coords.extend(tex_coords);
+        self.textures.extend([texture; 4]);
+        self.lights.extend(light.lights);
+        self.occlusion.extend(light.occlusion);
+        // Two triangles
+        if light.is_flipped() {
+            self.indices.extend([i + 1, i + 2, i + 3, i + 1, i + 3, i]);
+        } else {
+            self.indices.extend([i, i + 1, i + 2, i, i + 2, i + 3]);
+        }
+    }
+
+    pub fn quad_count(&self) -> usize {
+        self.vertices.len() / 4
+    }
+
+    pub fn translate(&mut self, offset: Vector3<f32>) {
+        for v in &mut self.vertices {
+            *v += offset;
+        }
+    }
+
+    /// Indices with the quads ordered from the furthest to the nearest to
+    /// `eye`, so that blended faces cover the ones behind them.
+    pub fn back_to_front(&self, eye: Point3<f32>) -> Vec<usize> {
+        let mut quads: Vec<_> = self
+            .vertices
+            .chunks(4)
+            .map(|corners| {
+                let centre = Point3::centroid(corners);
+                centre.distance2(eye)
+            })
+            .enumerate()
+            .collect();
+        quads.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        quads
+            .into_iter()
+            .flat_map(|(q, _)| self.indices[q * 6..q * 6 + 6].iter().copied())
+            .collect()
+    }
+}
+
+
+/// Light of the face of the block at `loc` pointing in `direction`, or `None`
+/// if the face is hidden.
+///
+/// With `smooth`, the light of each vertex is the average of the open blocks
+/// touching it in front of the face, and the vertex is occluded by the opaque
+/// ones.
+pub fn face_light(
+    chunk: &Chunk,
+    around: SurroundingChunks,
+    loc: t::PointIntLocal,
+    direction: usize,
+    smooth: bool,
+) -> Option<FaceLight> {
+    let light = chunk.face_light(around, loc, direction)?;
+    let mut face = FaceLight::flat(light);
+    if !smooth {
+        return Some(face);
+    }
+
+    let front = loc + &c::ADJACENCY[direction];
+    let normal = c::ADJACENCY[direction].0;
+    let n = (0..3).find(|&i| normal[i] != 0).unwrap();
+
+    for (i, corner) in c::BLOCK_FACES[direction].iter().enumerate() {
+        // Towards the vertex along each of the two axes of the face
+        let [du, dv] = [(n + 1) % 3, (n + 2) % 3].map(|axis| {
+            let mut d = [0; 3];
+            d[axis] = if corner[axis] > 0.5 { 1 } else { -1 };
+            t::PointIntLocal(d)
+        });
+
+        let side_u = chunk.sample(around, front + &du);
+        let side_v = chunk.sample(around, front + &dv);
+        let diagonal = chunk.sample(around, front + &du + &dv);
+
+        let is_opaque = |s: Option<(&Block, u8)>| s.is_some_and(|(b, _)| !b.is_transparent());
+        let [u, v, d] = [side_u, side_v, diagonal].map(is_opaque);
+
+        // Light can not reach the diagonal block through two opaque sides
+        let diagonal = if u && v { None } else { diagonal };
+
+        let (sum, count) = [side_u, side_v, diagonal]
+            .into_iter()
+            .flatten()
+            .filter(|(b, _)| b.is_transparent())
+            .fold((light as u16, 1), |(sum, count), (_, l)| {
+                (sum + l as u16, count + 1)
+            });
+
+        face.lights[i] = ((sum + count / 2) / count) as u8;
+        face.occlusion[i] = if u && v {
+            0
+        } else {
+            FaceLight::OPEN - (u as u8 + v as u8 + d as u8)
+        };
+    }
+
+    Some(face)
+}
+
+
+/// Corners, texture and light of a single face.
+pub type Quad = ([Point3<f32>; 4], u32, FaceLight);
+
+/// One quad per face, as given.
+pub fn naive(faces: Vec<Quad>) -> RenderData {
+    let mut data = RenderData::default();
+    for (corners, texture, light) in faces {
+        data.push_quad(corners, TEX_CORNERS, texture, light);
+    }
+    data
+}
+
+
+/// Faces of translucent blocks, one quad each. Faces against opaque blocks and
+/// between blocks of the same kind are hidden.
+pub fn translucent(chunk: &Chunk, around: SurroundingChunks, smooth: bool) -> RenderData {
+    let mut data = RenderData::default();
+
+    for x in 0..c::CHUNK_X_BLOCKS {
+        for y in 0..c::CHUNK_Y_BLOCKS {
+            for z in 0..c::CHUNK_Z_BLOCKS {
+                let loc = t::PointIntLocal::from([x, y, z]);
+                let block = chunk.get_block(loc);
+                if !block.is_translucent() {
+                    continue;
+                }
+
+                let offset = Vector3::new(x, y, z).map(|v| v as f32);
+                for (i, face) in c::BLOCK_FACES.iter().enumerate() {
+                    let next = chunk.sample(around, loc + &c::ADJACENCY[i]);
+                    if next.is_some_and(|(next, _)| next.kind.is_same(&block.kind)) {
+                        continue;
+                    }
+                    let Some(light) = face_light(chunk, around, loc, i, smooth) else {
+                        continue;
+                    };
+
+                    data.push_quad(
+                        face.map(|p| p + offset),
+                        TEX_CORNERS,
+                        block.kind.texture(i),
+                        light,
+                    );
+                }
+            }
+        }
+    }
+
+    data
+}
+
+
+/// Index of the axis along which the coordinates of `a` and `b` differ.
+fn axis_between(a: Point3<f32>, b: Point3<f32>) -> usize {
+    let d = b - a;
+    (0..3).find(|&i| d[i] != 0.0).unwrap()
+}
+
+/// What is visible on a face: block kind and the light in front of it.
+type Face = (BlockKind, FaceLight);
+
+type FaceMask = [[Option<Face>; c::CHUNK_EDGE_BLOCKS]; c::CHUNK_EDGE_BLOCKS];
+
+/// Visible faces pointing in `direction` (index into `ADJACENCY`) in the slice
+/// `depth` along the normal axis, indexed by the two other axes in order.
+fn face_mask(
+    chunk: &Chunk,
+    around: SurroundingChunks,
+    direction: usize,
+    axes: [usize; 3],
+    depth: usize,
+    smooth: bool,
+) -> FaceMask {
+    let [n, u, v] = axes;
+    let mut mask = [[None; c::CHUNK_EDGE_BLOCKS]; c::CHUNK_EDGE_BLOCKS];
+
+    for (a, row) in mask.iter_mut().enumerate() {
+        for (b, cell) in row.iter_mut().enumerate() {
+            let mut loc = [0; 3];
+            loc[n] = depth;
+            loc[u] = a;
+            loc[v] = b;
+            let loc = t::PointIntLocal::from(loc);
+
+            let block = chunk.get_block(loc);
+            if block.is_transparent() {
+                continue;
+            }
+
+            *cell =
+                face_light(chunk, around, loc, direction, smooth).map(|light| (block.kind, light));
+        }
+    }
+
+    mask
+}
+
+/// Merge equal faces in the mask into rectangles, greedily: first as far as
+/// possible along `u`, then along `v`. Cleared cells are consumed.
+///
+/// Returns `(a, b, width, height, face)`.
+fn merge_mask(mask: &mut FaceMask) -> Vec<(usize, usize, usize, usize, Face)> {
+    let size = c::CHUNK_EDGE_BLOCKS;
+    let mut rects = Vec::new();
+
+    for b in 0..size {
+        for a in 0..size {
+            let Some(face) = mask[a][b] else {
+                continue;
+            };
+
+            let mut w = 1;
+            while a + w < size && mask[a + w][b] == Some(face) {
+                w += 1;
+            }
+
+            let mut h = 1;
+            while b + h < size && (a..a + w).all(|a| mask[a][b + h] == Some(face)) {
+                h += 1;
+            }
+
+            for row in &mut mask[a..a + w] {
+                for cell in &mut row[b..b + h] {
+                    *cell = None;
+                }
+            }
+
+            rects.push((a, b, w, h, face));
+        }
+    }
+
+    rects
+}
+
+/// Faces with the same block kind and light merged into as few quads as
+/// possible. With `smooth` lighting only faces with equal light at every
+/// vertex are merged.
+pub fn greedy(chunk: &Chunk, around: SurroundingChunks, smooth: bool) -> RenderData {
+    let mut data = RenderData::default();
+
+    for (direction, face) in c::BLOCK_FACES.iter().enumerate() {
+        let normal = c::ADJACENCY[direction];
+        let n = (0..3).find(|&i| normal.0[i] != 0).unwrap();
+        let [u, v] = match n {
+            0 => [1, 2],
+            1 => [0, 2],
+            _ => [0, 1],
+        };
+
+        // Axes the texture coordinates follow on this face
+        let s_axis = axis_between(face[0], face[1]);
+        let t_axis = axis_between(face[1], face[2]);
+
+        for depth in 0..c::CHUNK_EDGE_BLOCKS {
+            let mut mask = face_mask(chunk, around, direction, [n, u, v], depth, smooth);
+
+            for (a, b, w, h, (kind, light)) in merge_mask(&mut mask) {
+                let mut origin = Vector3::new(0.0, 0.0, 0.0);
+                origin[n] = depth as f32;
+                origin[u] = a as f32;
+                origin[v] = b as f32;
+
+                let mut size = Vector3::new(1.0, 1.0, 1.0);
+                size[u] = w as f32;
+                size[v] = h as f32;
+
+                let corners =
+                    face.map(|p| Point3::new(p.x * size.x, p.y * size.y, p.z * size.z) + origin);
+                let tex_coords = TEX_CORNERS.map(|[s, t]| [s * size[s_axis], t * size[t_axis]]);
+
+                data.push_quad(corners, tex_coords, kind.texture(direction), light);
+            }
+        }
+    }
+
+    data
+}
+
+
+/// Cubes of the falling blocks, lit evenly with the light of the block they
+/// are in.
+pub fn falling<'a>(
+    blocks: impl IntoIterator<Item = &'a FallingBlock>,
+    region: &Region,
+) -> RenderData {
+    let mut data = RenderData::default();
+    for falling in blocks {
+        let light = Region::split_location(falling.location()).map_or(0, |(chunk, local)| {
+            let chunk = region.get_chunk(chunk);
+            chunk.get_light_sky(local).max(chunk.get_light_local(local))
+        });
+        let offset = falling.position.map(|v| v as f32).to_vec();
+
+        for (direction, face) in c::BLOCK_FACES.iter().enumerate() {
+            data.push_quad(
+                face.map(|p| p + offset),
+                TEX_CORNERS,
+                falling.block.kind.texture(direction),
+                FaceLight::flat(light),
+            );
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use cgmath::InnerSpace;
+
+    use super::*;
+    use crate::model::{
+        block::TEXTURES,
+        chunk::{AdjacentDirection, ChunkAdjacent, ChunkEmpty},
+        test_util::{lit, render, Sequence},
+    };
+
+    /// Every unit square covered by the quads: its lowest corner, the normal,
+    /// the light and the texture.
+    fn coverage(data: &RenderData) -> HashSet<([i32; 3], [i32; 3], u8, u32)> {
+        let mut cells = HashSet::new();
+
+        for q in 0..data.quad_count() {
+            let vs = &data.vertices[q * 4..q * 4 + 4];
+            let normal = (vs[1] - vs[0]).cross(vs[2] - vs[1]).normalize();
+            let normal = [normal.x, normal.y, normal.z].map(|v| v.round() as i32);
+
+            let min = vs.iter().fold([f32::MAX; 3], |m, p| {
+                [m[0].min(p.x), m[1].min(p.y), m[2].min(p.z)]
+            });
+            let max = vs.iter().fold([f32::MIN; 3], |m, p| {
+                [m[0].max(p.x), m[1].max(p.y), m[2].max(p.z)]
+            });
+            let (min, max) = (min.map(|v| v as i32), max.map(|v| v as i32));
+            // Flat along the normal
+            let max = [0, 1, 2].map(|i| max[i].max(min[i] + 1));
+
+            for x in min[0]..max[0] {
+                for y in min[1]..max[1] {
+                    for z in min[2]..max[2] {
+                        assert!(
+                            cells.insert((
+                                [x, y, z],
+                                normal,
+                                data.lights[q * 4],
+                                data.textures[q * 4]
+                            )),
+                            "faces overlap"
+                        );
+                    }
+                }
+            }
+        }
+
+        cells
+    }
+
+    fn random_chunk(seed: u64, fill: u64) -> Chunk {
+        let mut sequence = Sequence::new(seed);
+
+        let mut chunk = Chunk::default();
+        for x in 0..c::CHUNK_X_BLOCKS {
+            for y in 0..c::CHUNK_Y_BLOCKS {
+                for z in 0..c::CHUNK_Z_BLOCKS {
+                    match sequence.number() % 100 {
+                        r if r < fill => chunk.set_block([x, y, z], Block::solid()),
+                        r if r < fill + 2 => chunk.set_block([x, y, z], Block::light_source()),
+                        _ => (),
+                    }
+                }
+            }
+        }
+        chunk
+    }
+
+    #[test]
+    fn same_coverage_on_random_chunks() {
+        for smooth in [false, true] {
+            for (seed, fill) in [(1, 5), (2, 30), (3, 60), (4, 90), (5, 100)] {
+                let chunk = lit(random_chunk(seed, fill));
+
+                let naive = render(&chunk, Mesher::Naive, smooth);
+                let greedy = render(&chunk, Mesher::Greedy, smooth);
+
+                assert_eq!(coverage(&naive), coverage(&greedy), "seed {}", seed);
+                assert!(greedy.quad_count() <= naive.quad_count());
+            }
+        }
+    }
+
+    #[test]
+    fn solid_chunk_is_six_quads() {
+        let chunk = random_chunk(0, 100);
+
+        let greedy = render(&chunk, Mesher::Greedy, false);
+
+        assert_eq!(greedy.quad_count(), 6);
+        assert_eq!(coverage(&greedy).len(), 6 * 16 * 16);
+    }
+
+    #[test]
+    fn no_faces_between_solid_chunks() {
+        let (below, above) = (random_chunk(0, 100), random_chunk(0, 100));
+
+        for mesher in [Mesher::Naive, Mesher::Greedy] {
+            let config = MeshConfig {
+                mesher,
+                ..Default::default()
+            };
+            let mut neighbours = [None; 6];
+            neighbours[AdjacentDirection::YPos as usize] = Some(&above);
+            let data = SurroundingChunks::with_missing_as_sky(neighbours, |s| {
+                below.get_render_data(s, Vector3::new(0.0, 0.0, 0.0), config)
+            });
+
+            let cells = coverage(&data);
+            assert!(
+                cells.iter().all(|([_, y, _], ..)| *y != 16),
+                "faces between chunks"
+            );
+            assert_eq!(cells.len(), 5 * 16 * 16);
+        }
+    }
+
+    #[test]
+    fn grass_faces_use_their_textures() {
+        let mut chunk = Chunk::default();
+        chunk.set_block([5isize, 5, 5], Block::grass());
+        chunk.set_block([7isize, 5, 5], Block::dirt());
+
+        for mesher in [Mesher::Naive, Mesher::Greedy] {
+            let data = render(&chunk, mesher, false);
+
+            let textures: HashSet<_> = coverage(&data)
+                .into_iter()
+                .map(|([x, y, _], normal, _, texture)| {
+                    let face = if normal[1] != 0 && y == 6 {
+                        "top"
+                    } else {
+                        "other"
+                    };
+                    (x, face, crate::model::block::TEXTURES[texture as usize])
+                })
+                .collect();
+
+            assert!(textures.contains(&(5, "top", "grass_top")));
+            assert!(textures.contains(&(5, "other", "grass_side")));
+            assert!(textures.contains(&(5, "other", "dirt")));
+            assert!(textures.contains(&(7, "top", "dirt")));
+            assert!(!textures.contains(&(7, "other", "grass_side")));
+        }
+    }
+
+    #[test]
+    fn merged_faces_tile_texture() {
+        let mut chunk = Chunk::default();
+        for x in 0..3 {
+            chunk.set_block([x, 0usize, 0], Block::solid());
+        }
+
+        let greedy = render(&chunk, Mesher::Greedy, false);
+
+        // The long sides are 3 blocks wide, the ends are 1 block wide
+        assert_eq!(greedy.quad_count(), 6);
+        for q in 0..6 {
+            let vs = &greedy.vertices[q * 4..q * 4 + 4];
+            let uvs = &greedy.tex_coords[q * 4..q * 4 + 4];
+            for i in 0..4 {
+                let j = (i + 1) % 4;
+                let length = (vs[j] - vs[i]).magnitude();
+                let uv_length =
+                    ((uvs[j][0] - uvs[i][0]).powi(2) + (uvs[j][1] - uvs[i][1]).powi(2)).sqrt();
+                assert_eq!(length, uv_length, "one texture repeat per block");
+            }
+        }
+    }
+
+    /// Light of the top face of the block at `loc`.
+    fn top(chunk: &Chunk, loc: [isize; 3], smooth: bool) -> FaceLight {
+        SurroundingChunks::with_missing_as_sky([None; 6], |s| {
+            face_light(chunk, s, loc.into(), 5, smooth).unwrap()
+        })
+    }
+
+    /// Whether the top face vertex is on the positive side along X and Z.
+    fn top_corners() -> [(bool, bool); 4] {
+        c::BLOCK_FACES[5].map(|p| (p.x > 0.5, p.z > 0.5))
+    }
+
+    #[test]
+    fn open_face_is_unoccluded() {
+        let mut chunk = Chunk::default();
+        chunk.set_block([5isize, 5, 5], Block::solid());
+        let chunk = lit(chunk);
+
+        assert_eq!(top(&chunk, [5, 5, 5], true), FaceLight::flat(15));
+    }
+
+    #[test]
+    fn occlusion_from_sides_and_corners() {
+        let mut chunk = Chunk::default();
+        chunk.set_block([5isize, 5, 5], Block::solid());
+        // Above the +X and +Z edges of the top face
+        chunk.set_block([6isize, 6, 5], Block::solid());
+        chunk.set_block([5isize, 6, 6], Block::solid());
+
+        let light = top(&chunk, [5, 5, 5], true);
+
+        for (i, corner) in top_corners().into_iter().enumerate() {
+            let expected = match corner {
+                // Both sides are blocked
+                (true, true) => 0,
+                // One side
+                (true, false) | (false, true) => 2,
+                // Nothing around
+                (false, false) => 3,
+            };
+            assert_eq!(light.occlusion[i], expected, "corner {:?}", corner);
+        }
+    }
+
+    #[test]
+    fn diagonal_alone_occludes() {
+        let mut chunk = Chunk::default();
+        chunk.set_block([5isize, 5, 5], Block::solid());
+        chunk.set_block([6isize, 6, 6], Block::solid());
+
+        let light = top(&chunk, [5, 5, 5], true);
+
+        for (i, corner) in top_corners().into_iter().enumerate() {
+            let expected = if corner == (true, true) { 2 } else { 3 };
+            assert_eq!(light.occlusion[i], expected, "corner {:?}", corner);
+        }
+    }
+
+    #[test]
+    fn smooth_light_blends_neighbours() {
+        let (dark, sky) = (ChunkEmpty::new(0), ChunkEmpty::new(15));
+
+        // Dark chunk next to open sky on the +X side
+        let mut chunk = Chunk::default();
+        chunk.set_block([15isize, 0, 5], Block::solid());
+        chunk.recalculate_light(SurroundingChunks::new([&dark; 6]));
+
+        let mut around: [&dyn ChunkAdjacent; 6] = [&dark; 6];
+        around[AdjacentDirection::XPos as usize] = &sky;
+        let around = SurroundingChunks::new(around);
+        let loc = [15isize, 0, 5].into();
+
+        let flat = face_light(&chunk, around, loc, 5, false).unwrap();
+        let smooth = face_light(&chunk, around, loc, 5, true).unwrap();
+
+        assert_eq!(flat, FaceLight::flat(0));
+        for (i, (x, _)) in top_corners().into_iter().enumerate() {
+            // Half of the blocks around the vertex see the sky
+            let expected = if x { 8 } else { 0 };
+            assert_eq!(smooth.lights[i], expected);
+        }
+    }
+
+    #[test]
+    fn quads_split_along_brighter_diagonal() {
+        let mut data = RenderData::default();
+        let corners = c::BLOCK_FACES[5];
+
+        data.push_quad(corners, TEX_CORNERS, 0, FaceLight::flat(15));
+        let mut dark = FaceLight::flat(15);
+        dark.occlusion[0] = 1;
+        data.push_quad(corners, TEX_CORNERS, 0, dark);
+
+        assert_eq!(data.indices[..6], [0, 1, 2, 0, 2, 3]);
+        // The dark vertex only belongs to one triangle
+        assert_eq!(data.indices[6..].iter().filter(|&&i| i == 4).count(), 1);
+    }
+
+    #[test]
+    fn flat_lighting_has_no_occlusion() {
+        let chunk = lit(random_chunk(6, 40));
+
+        let data = render(&chunk, Mesher::Greedy, false);
+
+        assert!(data.occlusion.iter().all(|&o| o == FaceLight::OPEN));
+        for q in 0..data.quad_count() {
+            let lights = &data.lights[q * 4..q * 4 + 4];
+            assert!(lights.iter().all(|&l| l == lights[0]));
+        }
+    }
+
+    fn water_chunk() -> Chunk {
+        let mut chunk = Chunk::default();
+        // A pool of water two blocks deep behind a pane of glass
+        for x in 4..8usize {
+            for z in 4..8 {
+                chunk.set_block([x, 0, z], Block::water());
+                chunk.set_block([x, 1, z], Block::water());
+            }
+            chunk.set_block([x, 1, 3], Block::glass());
+        }
+        lit(chunk)
+    }
+
+    #[test]
+    fn translucent_blocks_are_not_opaque_geometry() {
+        let chunk = water_chunk();
+
+        assert_eq!(render(&chunk, Mesher::Greedy, true).quad_count(), 0);
+        assert_eq!(render(&chunk, Mesher::Naive, true).quad_count(), 0);
+    }
+
+    #[test]
+    fn translucent_faces_between_equal_blocks_are_hidden() {
+        let chunk = water_chunk();
+        let data = SurroundingChunks::with_missing_as_sky([None; 6], |around| {
+            translucent(&chunk, around, true)
+        });
+
+        // Both are boxes: water 4x2x4 and glass 4x1x1. Faces between the two
+        // kinds are kept on both sides.
+        let water = 2 * 4 * 4 + 4 * 2 * 4;
+        let glass = 4 * 4 + 2;
+        let textures = |name| {
+            let texture = TEXTURES.iter().position(|&t| t == name).unwrap() as u32;
+            data.textures.iter().filter(|&&t| t == texture).count() / 4
+        };
+        assert_eq!(textures("water"), water);
+        assert_eq!(textures("glass"), glass);
+        assert_eq!(data.quad_count(), water + glass);
+    }
+
+    #[test]
+    fn falling_blocks_are_whole_cubes() {
+        let mut region = Region::default();
+        let mut falling = FallingBlock::new(Block::grass(), [3, 4, 5]);
+        falling.position.y -= 0.25;
+        region.recalculate_chunk_light([0, 0, 0]);
+
+        let data = super::falling([&falling], &region);
+        assert_eq!(data.quad_count(), 6);
+        assert!(data.lights.iter().all(|&l| l == c::LIGHT_MAX as u8));
+        let lowest = data.vertices.iter().map(|v| v.y).fold(f32::MAX, f32::min);
+        assert_eq!(lowest, 3.75);
+        assert_eq!(
+            TEXTURES[data.textures[c::FACE_TOP * 4] as usize],
+            "grass_top"
+        );
+    }
+
+    #[test]
+    fn sorted_back_to_front() {
+        let mut data = RenderData::default();
+        for z in [2.0, 5.0, 1.0, 4.0] {
+            let corners = c::BLOCK_FACES[0].map(|p| p + Vector3::new(0.0, 0.0, z));
+            data.push_quad(corners, TEX_CORNERS, 0, FaceLight::flat(15));
+        }
+
+        let first_vertex_z = |indices: &[usize]| -> Vec<f32> {
+            indices
+                .chunks(6)
+                .map(|quad| data.vertices[quad[0]].z)
+                .collect()
+        };
+
+        let eye = Point3::new(0.5, 0.5, -3.0);
+        assert_eq!(
+            first_vertex_z(&data.back_to_front(eye)),
+            [5.0, 4.0, 2.0, 1.0]
+        );
+        let eye = Point3::new(0.5, 0.5, 10.0);
+        assert_eq!(
+            first_vertex_z(&data.back_to_front(eye)),
+            [1.0, 2.0, 4.0, 5.0]
+        );
+        // Every triangle is kept whole
+        let sorted = data.back_to_front(eye);
+        let mut triangles: Vec<_> = sorted.chunks(3).collect();
+        let mut expected: Vec<_> = data.indices.chunks(3).collect();
+        triangles.sort();
+        expected.sort();
+        assert_eq!(triangles, expected);
+    }
+}
@@ -1,8 +1,25 @@
+pub mod atmosphere;
 pub mod block;
 pub mod chunk;
+pub mod chunk_manager;
 pub mod consts;
 pub mod effect;
+pub mod falling;
+pub mod fluid;
+pub mod frustum;
 mod game_model;
+pub mod generator;
+pub mod lod;
+pub mod mesh;
+pub mod raster;
+pub mod raycast;
 pub mod region;
+pub mod settings;
+pub mod storage;
+#[cfg(test)]
+mod test_util;
 pub mod types;
+pub mod updates;
+pub mod visibility;
+pub mod workers;
 pub use game_model::*;
@@ -0,0 +1,343 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use cgmath::{Matrix4, Vector2, Vector4};
+
+use super::{mesh::RenderData, settings::Settings, Camera};
+
+/// Light every face gets, like the daylight ambient in the fragment shader.
+const AMBIENT: f32 = 0.2;
+
+/// A software rasterizer for meshes, drawing what the renderer would draw
+/// without textures, fog and smooth shading. It needs no device, so it is a
+/// reference for meshing and lighting in tests.
+///
+/// Triangles are depth tested, back faces culled and each triangle is
+/// shaded flat with the average of its vertex lights.
+pub struct Raster {
+    width: u32,
+    height: u32,
+    /// Brightness of each pixel, rows from the top
+    pixels: Vec<u8>,
+    /// Depth of each pixel, from 0 at the near plane to 1 at the far one
+    depth: Vec<f32>,
+}
+
+impl Raster {
+    /// A black image, as far away as can be.
+    pub fn new(width: u32, height: u32) -> Self {
+        let size = width as usize * height as usize;
+        Self {
+            width,
+            height,
+            pixels: vec![0; size],
+            depth: vec![1.0; size],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Brightness of the pixel `x` from the left and `y` from the top.
+    pub fn pixel(&self, x: u32, y: u32) -> u8 {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Draw `data`, in block coordinates, as seen by `camera`. Uses the same
+    /// matrices as the renderer with `settings`.
+    pub fn draw(&mut self, data: &RenderData, camera: &Camera, settings: &Settings) {
+        let aspect_ratio = self.width as f32 / self.height as f32;
+        let matrix = settings.projection_matrix(aspect_ratio) * camera.view_matrix();
+        self.draw_with(data, matrix);
+    }
+
+    /// Draw `data` with `matrix` from its coordinates to clip space.
+    pub fn draw_with(&mut self, data: &RenderData, matrix: Matrix4<f32>) {
+        for triangle in data.indices.chunks_exact(3) {
+            let shade = triangle
+                .iter()
+                .map(|&i| {
+                    let light = data.lights[i] as f32 / 15.0;
+                    let occlusion = 0.4 + 0.2 * data.occlusion[i] as f32;
+                    ((AMBIENT + light) * occlusion).min(1.0)
+                })
+                .sum::<f32>()
+                / 3.0;
+
+            let clip = [0, 1, 2].map(|v| matrix * data.vertices[triangle[v]].to_homogeneous());
+            let polygon = clip_near(clip);
+            for i in 1..polygon.len().saturating_sub(1) {
+                let corners = [polygon[0], polygon[i], polygon[i + 1]];
+                self.fill(corners.map(|v| self.to_screen(v)), shade);
+            }
+        }
+    }
+
+    /// Pixel coordinates and depth of a point in clip space, like the
+    /// Vulkan viewport transform.
+    fn to_screen(&self, v: Vector4<f32>) -> (Vector2<f32>, f32) {
+        let ndc = v / v.w;
+        let x = (ndc.x + 1.0) / 2.0 * self.width as f32;
+        let y = (ndc.y + 1.0) / 2.0 * self.height as f32;
+        (Vector2::new(x, y), ndc.z)
+    }
+
+    /// Fill the pixels whose centres are in the triangle, unless something
+    /// nearer is there already.
+    fn fill(&mut self, corners: [(Vector2<f32>, f32); 3], shade: f32) {
+        let [(a, da), (b, db), (c, dc)] = corners;
+
+        // Counter-clockwise on screen with Y down, as Vulkan sees it
+        let area = edge(a, b, c);
+        if area >= 0.0 {
+            return;
+        }
+
+        let min = |v: [f32; 3]| v.into_iter().fold(f32::INFINITY, f32::min);
+        let max = |v: [f32; 3]| v.into_iter().fold(f32::NEG_INFINITY, f32::max);
+        let x0 = min([a.x, b.x, c.x]).floor().max(0.0) as u32;
+        let y0 = min([a.y, b.y, c.y]).floor().max(0.0) as u32;
+        let x1 = (max([a.x, b.x, c.x]).ceil() as u32).min(self.width);
+        let y1 = (max([a.y, b.y, c.y]).ceil() as u32).min(self.height);
+
+        let value = (shade * 255.0).round() as u8;
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let p = Vector2::new(x as f32 + 0.5, y as f32 + 0.5);
+                let weights = [edge(b, c, p), edge(c, a, p), edge(a, b, p)].map(|w| w / area);
+                if weights.iter().any(|&w| w < 0.0) {
+                    continue;
+                }
+
+                let depth = weights[0] * da + weights[1] * db + weights[2] * dc;
+                let i = (y * self.width + x) as usize;
+                if (0.0..=1.0).contains(&depth) && depth < self.depth[i] {
+                    self.depth[i] = depth;
+                    self.pixels[i] = value;
+                }
+            }
+        }
+    }
+
+    /// Encode as an 8 bit greyscale PNG.
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, self.width, self.height);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&self.pixels).unwrap();
+        writer.finish().unwrap();
+        bytes
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&self.to_png())
+    }
+}
+
+/// Twice the signed area of the triangle `abc`.
+fn edge(a: Vector2<f32>, b: Vector2<f32>, c: Vector2<f32>) -> f32 {
+    (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+}
+
+/// The part of a triangle in clip space in front of the near plane, where
+/// `z >= 0` as on Vulkan. A polygon of up to four corners.
+fn clip_near(triangle: [Vector4<f32>; 3]) -> Vec<Vector4<f32>> {
+    let mut polygon = Vec::with_capacity(4);
+    for i in 0..3 {
+        let (a, b) = (triangle[i], triangle[(i + 1) % 3]);
+        if a.z >= 0.0 {
+            polygon.push(a);
+        }
+        if (a.z >= 0.0) != (b.z >= 0.0) {
+            let t = a.z / (a.z - b.z);
+            polygon.push(a + (b - a) * t);
+        }
+    }
+    polygon
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Point3, Rad};
+
+    use super::*;
+    use crate::model::{
+        block::Block,
+        chunk::Chunk,
+        generator::{ChunkGenerator, DemoGenerator},
+        mesh::Mesher,
+        test_util::{lit, render},
+    };
+
+    const SNAPSHOTS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/res/snapshots");
+
+    /// Compare with `res/snapshots/{name}.png`. With `UPDATE_SNAPSHOTS` set,
+    /// the snapshot is written instead.
+    fn assert_snapshot(name: &str, raster: &Raster) {
+        let path = format!("{}/{}.png", SNAPSHOTS, name);
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            raster.save(&path).unwrap();
+            return;
+        }
+
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut expected = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut expected).unwrap();
+
+        assert_eq!([info.width, info.height], [raster.width, raster.height]);
+        let different = expected
+            .iter()
+            .zip(raster.pixels())
+            .filter(|(a, b)| a != b)
+            .count();
+        assert!(
+            different == 0,
+            "{} pixels differ from {}, rerun with UPDATE_SNAPSHOTS=1 if that is intended",
+            different,
+            path
+        );
+    }
+
+    /// In front of the demo chunk, looking down at the smiley face.
+    fn demo_camera() -> Camera {
+        Camera {
+            position: Point3::new(3.0, 4.0, -2.0),
+            pitch: Rad(0.45),
+            yaw: Rad(0.0),
+        }
+    }
+
+    /// A square over the middle of the screen, already in clip space.
+    /// Front facing unless `reversed`.
+    fn square(data: &mut RenderData, depth: f32, light: u8, reversed: bool) {
+        let i = data.vertices.len();
+        // Counter-clockwise on screen
+        for [x, y] in [[-0.5, -0.5], [-0.5, 0.5], [0.5, 0.5], [0.5, -0.5]] {
+            data.vertices.push(Point3::new(x, y, depth));
+            data.lights.push(light);
+            data.occlusion.push(3);
+        }
+        let mut indices = [i, i + 1, i + 2, i, i + 2, i + 3];
+        if reversed {
+            indices.reverse();
+        }
+        data.indices.extend(indices);
+    }
+
+    #[test]
+    fn nearer_faces_cover_further_ones() {
+        let mut data = RenderData::default();
+        square(&mut data, 0.5, 15, false);
+        square(&mut data, 0.2, 0, false);
+        square(&mut data, 0.8, 15, false);
+        // Facing away from the camera
+        square(&mut data, 0.1, 15, true);
+
+        let mut raster = Raster::new(8, 8);
+        raster.draw_with(&data, Matrix4::from_scale(1.0));
+
+        let dark = (AMBIENT * 255.0).round() as u8;
+        assert_eq!(raster.pixel(4, 4), dark);
+        assert_eq!(raster.depth[4 * 8 + 4], 0.2);
+        assert_eq!(raster.pixel(0, 0), 0);
+        assert_eq!(raster.pixel(7, 7), 0);
+    }
+
+    #[test]
+    fn blocks_show_their_near_faces() {
+        let mut chunk = Chunk::default();
+        chunk.set_block([4isize, 4, 8], Block::solid());
+        let data = render(&lit(chunk), Mesher::Greedy, true);
+
+        // Looking at it along Z
+        let camera = Camera {
+            position: Point3::new(2.25, 2.25, 0.0),
+            ..Default::default()
+        };
+        let mut raster = Raster::new(64, 64);
+        raster.draw(&data, &camera, &Settings::default());
+
+        let matrix = Settings::default().projection_matrix(1.0) * camera.view_matrix();
+        let near = matrix * Vector4::new(4.5, 4.5, 8.0, 1.0);
+        assert!((raster.depth[32 * 64 + 32] - near.z / near.w).abs() < 1e-4);
+        assert!(raster.pixel(32, 32) > 0);
+        assert_eq!(raster.pixel(0, 0), 0);
+    }
+
+    #[test]
+    fn narrower_fov_magnifies() {
+        let mut chunk = Chunk::default();
+        chunk.set_block([4isize, 4, 8], Block::solid());
+        let data = render(&lit(chunk), Mesher::Greedy, true);
+        let camera = Camera {
+            position: Point3::new(2.25, 2.25, 0.0),
+            ..Default::default()
+        };
+
+        let drawn = |fov| {
+            let mut settings = Settings::default();
+            settings.fov = fov;
+            let mut raster = Raster::new(64, 64);
+            raster.draw(&data, &camera, &settings);
+            raster.pixels().iter().filter(|&&p| p > 0).count()
+        };
+        assert!(drawn(45.0) > drawn(90.0));
+    }
+
+    #[test]
+    fn blocks_behind_the_camera_are_not_drawn() {
+        let mut chunk = Chunk::default();
+        chunk.set_block([4isize, 4, 0], Block::solid());
+        let data = render(&lit(chunk), Mesher::Greedy, true);
+
+        let camera = Camera {
+            position: Point3::new(2.25, 2.25, 2.0),
+            ..Default::default()
+        };
+        let mut raster = Raster::new(16, 16);
+        raster.draw(&data, &camera, &Settings::default());
+
+        assert!(raster.pixels().iter().all(|&p| p == 0));
+    }
+
+    #[test]
+    fn demo_chunk() {
+        let chunk = lit(DemoGenerator.generate([1, 1, 1]));
+        let mut raster = Raster::new(320, 240);
+        raster.draw(
+            &render(&chunk, Mesher::Greedy, true),
+            &demo_camera(),
+            &Settings::default(),
+        );
+
+        assert_snapshot("demo_chunk", &raster);
+    }
+
+    #[test]
+    fn demo_chunk_naive_flat() {
+        let chunk = lit(DemoGenerator.generate([1, 1, 1]));
+        let mut raster = Raster::new(320, 240);
+        raster.draw(
+            &render(&chunk, Mesher::Naive, false),
+            &demo_camera(),
+            &Settings::default(),
+        );
+
+        assert_snapshot("demo_chunk_naive_flat", &raster);
+    }
+}
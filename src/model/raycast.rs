@@ -0,0 +1,157 @@
+use cgmath::{InnerSpace, Point3, Vector3};
+
+use super::{block::BlockKind, region::Region};
+
+/// How far the camera reaches, in blocks.
+pub const REACH: f64 = 16.0;
+
+/// A block found by [`cast`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hit {
+    pub location: [usize; 3],
+    /// The block the ray came from, in front of the face that was hit. Its
+    /// light is the light of that face. `None` when the ray started inside
+    /// of the hit block or came from outside of the region.
+    pub before: Option<[usize; 3]>,
+}
+
+/// Find the first block that is not air on the ray from `origin` along
+/// `direction`, in block coordinates, no further than `reach` blocks.
+///
+/// Every block the ray touches is checked, so it does not slip through
+/// between blocks that only share an edge.
+pub fn cast(
+    region: &Region,
+    origin: Point3<f64>,
+    direction: Vector3<f64>,
+    reach: f64,
+) -> Option<Hit> {
+    let direction = direction.normalize();
+    let origin = [origin.x, origin.y, origin.z];
+    let direction = [direction.x, direction.y, direction.z];
+
+    let mut cell = origin.map(|v| v.floor() as isize);
+    let step = direction.map(|d| if d > 0.0 { 1 } else { -1 });
+    // Distance along the ray to the next boundary on each axis, and between
+    // boundaries
+    let mut t_max = [0, 1, 2].map(|i| {
+        let d = direction[i];
+        if d > 0.0 {
+            (cell[i] as f64 + 1.0 - origin[i]) / d
+        } else if d < 0.0 {
+            (origin[i] - cell[i] as f64) / -d
+        } else {
+            f64::INFINITY
+        }
+    });
+    let t_delta = direction.map(|d| 1.0 / d.abs());
+
+    let mut before = None;
+    loop {
+        let location = location(cell);
+        if let Some(loc) = location {
+            if region.get_block(loc).unwrap().kind != BlockKind::Air {
+                return Some(Hit {
+                    location: loc,
+                    before,
+                });
+            }
+        }
+        before = location;
+
+        let axis = (0..3)
+            .min_by(|&a, &b| t_max[a].total_cmp(&t_max[b]))
+            .unwrap();
+        if t_max[axis] > reach {
+            return None;
+        }
+        cell[axis] += step[axis];
+        t_max[axis] += t_delta[axis];
+    }
+}
+
+/// Location of the block `cell`, if it is inside of the region.
+fn location(cell: [isize; 3]) -> Option<[usize; 3]> {
+    let [x, y, z] = cell.map(|v| usize::try_from(v).ok());
+    let loc = [x?, y?, z?];
+    Region::split_location(loc).map(|_| loc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::block::Block;
+
+    fn region_with(blocks: &[[usize; 3]]) -> Region {
+        let mut region = Region::default();
+        for &loc in blocks {
+            region.set_block(loc, Block::solid());
+        }
+        region
+    }
+
+    #[test]
+    fn hits_the_first_block() {
+        let region = region_with(&[[8, 5, 5], [12, 5, 5]]);
+
+        let hit = cast(
+            &region,
+            Point3::new(2.5, 5.5, 5.5),
+            Vector3::new(1.0, 0.0, 0.0),
+            REACH,
+        );
+        assert_eq!(
+            hit,
+            Some(Hit {
+                location: [8, 5, 5],
+                before: Some([7, 5, 5]),
+            })
+        );
+
+        // Looking the other way
+        let hit = cast(
+            &region,
+            Point3::new(20.5, 5.5, 5.5),
+            Vector3::new(-1.0, 0.0, 0.0),
+            REACH,
+        );
+        assert_eq!(hit.unwrap().location, [12, 5, 5]);
+    }
+
+    #[test]
+    fn does_not_slip_between_diagonal_blocks() {
+        // Two blocks sharing only an edge, with the ray through the edge
+        let region = region_with(&[[6, 5, 5], [5, 6, 5]]);
+
+        let hit = cast(
+            &region,
+            Point3::new(3.0, 3.0, 5.5),
+            Vector3::new(1.0, 1.0, 0.0),
+            REACH,
+        );
+        assert!(hit.is_some());
+    }
+
+    #[test]
+    fn reach_and_edges() {
+        let region = region_with(&[[30, 5, 5], [0, 5, 5]]);
+        let x = Vector3::new(1.0, 0.0, 0.0);
+
+        assert_eq!(cast(&region, Point3::new(10.5, 5.5, 5.5), x, REACH), None);
+        assert!(cast(&region, Point3::new(10.5, 5.5, 5.5), x, 20.0).is_some());
+
+        // Starting inside of a block
+        let hit = cast(&region, Point3::new(30.2, 5.5, 5.5), x, REACH);
+        assert_eq!(hit.unwrap().before, None);
+
+        // Coming from outside of the region
+        let hit = cast(&region, Point3::new(-3.0, 5.5, 5.5), x, REACH);
+        assert_eq!(
+            hit,
+            Some(Hit {
+                location: [0, 5, 5],
+                before: None,
+            })
+        );
+    }
+}
@@ -1,8 +1,11 @@
 use tracing::instrument;
 
 use super::{
-    chunk::{Chunk, SurroundingChunks},
+    block::Block,
+    chunk::{AdjacentDirection, Chunk, SurroundingChunks},
     consts as c,
+    types as t,
+    visibility::Connectivity,
 };
 
 
@@ -15,41 +18,157 @@ impl Region {
         &self.chunks[loc.into()]
     }
 
-    pub fn set_chunk(&mut self, loc: impl Into<[usize; 3]>, chunk: Chunk) {
-        self.chunks[loc.into()] = chunk;
+    pub fn set_chunk(&mut self, loc: impl Into<[usize; 3]>, mut chunk: Chunk) {
+        let place = &mut self.chunks[loc.into()];
+        chunk.succeed(place);
+        *place = chunk;
     }
 
-    #[instrument(skip_all)]
-    pub fn recalculate_chunk_light(&mut self, loc: impl Into<[usize; 3]>) {
-        let loc = loc.into();
+    /// Split a location in the region into chunk location and location inside
+    /// of that chunk. Returns `None` for locations outside of the region.
+    pub fn split_location(loc: [usize; 3]) -> Option<([usize; 3], t::PointIntLocal)> {
+        let [x, y, z] = loc;
+        if x >= c::REGION_X_BLOCKS || y >= c::REGION_Y_BLOCKS || z >= c::REGION_Z_BLOCKS {
+            return None;
+        }
+
+        let chunk = [
+            x / c::CHUNK_X_BLOCKS,
+            y / c::CHUNK_Y_BLOCKS,
+            z / c::CHUNK_Z_BLOCKS,
+        ];
+        let local = [
+            x % c::CHUNK_X_BLOCKS,
+            y % c::CHUNK_Y_BLOCKS,
+            z % c::CHUNK_Z_BLOCKS,
+        ];
 
-        if loc[0] == 0
-            || loc[0] >= c::REGION_X_CHUNKS
-            || loc[1] == 0
-            || loc[1] >= c::REGION_Y_CHUNKS
-            || loc[2] == 0
-            || loc[2] >= c::REGION_Z_CHUNKS
+        Some((chunk, local.into()))
+    }
+
+    /// Location of the block `offset` away from `loc`, if it is inside of the
+    /// region.
+    pub fn offset_location(loc: [usize; 3], offset: [isize; 3]) -> Option<[usize; 3]> {
+        let [x, y, z] = loc;
+        let [dx, dy, dz] = offset;
+        let loc = [
+            x.checked_add_signed(dx)?,
+            y.checked_add_signed(dy)?,
+            z.checked_add_signed(dz)?,
+        ];
+        Self::split_location(loc).map(|_| loc)
+    }
+
+    /// Get a block by its location in the region.
+    pub fn get_block(&self, loc: [usize; 3]) -> Option<&Block> {
+        let (chunk, local) = Self::split_location(loc)?;
+        Some(self.get_chunk(chunk).get_block(local))
+    }
+
+    /// Set a block by its location in the region.
+    /// Returns `false` if the location is outside of the region.
+    pub fn set_block(&mut self, loc: [usize; 3], block: Block) -> bool {
+        match Self::split_location(loc) {
+            Some((chunk, local)) => {
+                self.chunks[chunk].set_block(local, block);
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn iter_chunks(&self) -> impl Iterator<Item = ([usize; 3], &Chunk)> {
+        self.chunks
+            .indexed_iter()
+            .map(|((x, y, z), chunk)| ([x, y, z], chunk))
+    }
+
+    /// Chunk location in the region, if `loc` is inside of it.
+    pub fn chunk_location(loc: [isize; 3]) -> Option<[usize; 3]> {
+        let [x, y, z] = loc.map(|v| usize::try_from(v).ok());
+        let loc = [x?, y?, z?];
+
+        if loc[0] < c::REGION_X_CHUNKS && loc[1] < c::REGION_Y_CHUNKS && loc[2] < c::REGION_Z_CHUNKS
         {
-            panic!(
-                "Can't recalculate light for a chunk on a face of the region: {:?}",
-                loc
-            );
+            Some(loc)
+        } else {
+            None
         }
+    }
 
-        let surrounding = SurroundingChunks::new([
-            // TODO: actually calculate
-            self.get_chunk([2, 1, 1]),
-            self.get_chunk([0, 1, 1]),
-            self.get_chunk([1, 2, 1]),
-            self.get_chunk([1, 0, 1]),
-            self.get_chunk([1, 1, 2]),
-            self.get_chunk([1, 1, 0]),
-        ]);
+    /// Location of the chunk `offset` away from `loc`, if it is inside of
+    /// the region.
+    pub fn offset_chunk_location(loc: [usize; 3], offset: [isize; 3]) -> Option<[usize; 3]> {
+        let [x, y, z] = loc;
+        let [dx, dy, dz] = offset;
+        Self::chunk_location([x as isize + dx, y as isize + dy, z as isize + dz])
+    }
+
+    /// Get the chunk next to `loc` in `direction`, if it is inside of the
+    /// region.
+    pub fn get_adjacent_chunk(
+        &self,
+        loc: [usize; 3],
+        direction: AdjacentDirection,
+    ) -> Option<&Chunk> {
+        let loc = Self::offset_chunk_location(loc, direction.offset())?;
+        Some(self.get_chunk(loc))
+    }
+
+    /// Get the chunks around `loc` in the order of [`AdjacentDirection::ALL`].
+    pub fn get_adjacent_chunks(&self, loc: [usize; 3]) -> [Option<&Chunk>; 6] {
+        AdjacentDirection::ALL.map(|direction| self.get_adjacent_chunk(loc, direction))
+    }
+
+    /// Call `f` with the chunks around `loc`.
+    pub fn with_surrounding<R>(
+        &self,
+        loc: [usize; 3],
+        f: impl FnOnce(SurroundingChunks) -> R,
+    ) -> R {
+        SurroundingChunks::with_missing_as_sky(self.get_adjacent_chunks(loc), f)
+    }
+
+    #[instrument(skip_all)]
+    pub fn recalculate_chunk_light(&mut self, loc: impl Into<[usize; 3]>) {
+        let loc = loc.into();
 
         let mut updated = self.get_chunk(loc).clone();
-        updated.recalculate_light(surrounding);
+        self.with_surrounding(loc, |surrounding| updated.recalculate_light(surrounding));
+
+        self.update_light(loc, &updated);
+    }
+
+    /// Recalculate light after the block at `loc` changed: in its chunk, then
+    /// in the chunks it borders on, which get light through it.
+    pub fn relight_around(&mut self, loc: [usize; 3]) {
+        let Some((chunk, _)) = Self::split_location(loc) else {
+            return;
+        };
+        self.recalculate_chunk_light(chunk);
+
+        for direction in AdjacentDirection::ALL {
+            let Some(neighbour) = Self::offset_location(loc, direction.offset()) else {
+                continue;
+            };
+            let (neighbour_chunk, _) = Self::split_location(neighbour).unwrap();
+            if neighbour_chunk != chunk {
+                self.recalculate_chunk_light(neighbour_chunk);
+            }
+        }
+    }
+
+    /// Take the light data from a copy of the chunk at `loc` that was lit
+    /// elsewhere. Unlike `set_chunk`, this is not a change to the chunk.
+    /// Returns whether the light changed.
+    pub fn update_light(&mut self, loc: [usize; 3], lit: &Chunk) -> bool {
+        self.chunks[loc].copy_light_from(lit)
+    }
 
-        self.set_chunk(loc, updated);
+    /// Store face connectivity computed elsewhere for the chunk at `loc`.
+    /// Like light, this is not a change to the chunk.
+    pub fn update_connectivity(&mut self, loc: [usize; 3], connectivity: Connectivity) {
+        self.chunks[loc].set_connectivity(connectivity);
     }
 }
 
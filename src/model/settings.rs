@@ -0,0 +1,412 @@
+use std::{
+    env,
+    fmt,
+    fs,
+    io::{self, ErrorKind},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use cgmath::{Deg, Matrix4};
+use tracing::{instrument, warn};
+
+/// Name of the settings file in the config directory.
+pub const FILE_NAME: &str = "settings.txt";
+
+/// Allowed vertical fields of view, in degrees.
+pub const FOV_RANGE: RangeInclusive<f32> = 30.0..=150.0;
+
+/// What the player can tune, saved between runs.
+///
+/// Saved as text with a `<key> = <value>` line per setting. Empty lines and
+/// lines starting with `#` are skipped. Keys this version does not know are
+/// kept and written back, so settings of newer versions survive older ones.
+///
+/// Distances are in camera units. A block is
+/// [`BLOCK_SIZE`](super::consts::BLOCK_SIZE) of them, so a unit is two blocks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    /// Vertical field of view, in degrees
+    pub fov: f32,
+    /// Distance to the near clipping plane, in camera units
+    pub near: f32,
+    /// Distance to the far clipping plane, in camera units
+    pub far: f32,
+    /// Camera rotation per pixel of mouse movement, in degrees
+    pub mouse_sensitivity: f64,
+    /// Camera movement per tick while a movement key is held, in camera units
+    pub movement_speed: f64,
+    /// Size of the inside of the window, in pixels
+    pub window_size: [u32; 2],
+    pub window_mode: WindowMode,
+    /// Lines with unknown keys, in order
+    unknown: Vec<(String, String)>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            fov: 90.0,
+            near: 0.01,
+            far: 100.0,
+            mouse_sensitivity: 1.0,
+            movement_speed: 0.05,
+            window_size: [1920, 1080],
+            window_mode: WindowMode::Windowed,
+            unknown: Vec::new(),
+        }
+    }
+}
+
+/// How the window covers the screen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowMode {
+    /// A window of [`Settings::window_size`] that can be resized
+    Windowed,
+    /// A window covering its monitor, keeping the monitor's video mode
+    Borderless,
+    /// The whole monitor, switched to its best video mode
+    Exclusive,
+}
+
+impl WindowMode {
+    /// The mode after this one, to go through all of them with one key.
+    pub fn next(self) -> Self {
+        match self {
+            Self::Windowed => Self::Borderless,
+            Self::Borderless => Self::Exclusive,
+            Self::Exclusive => Self::Windowed,
+        }
+    }
+}
+
+impl fmt::Display for WindowMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Windowed => "windowed",
+            Self::Borderless => "borderless",
+            Self::Exclusive => "exclusive",
+        })
+    }
+}
+
+impl FromStr for WindowMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "windowed" => Ok(Self::Windowed),
+            "borderless" => Ok(Self::Borderless),
+            "exclusive" => Ok(Self::Exclusive),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SettingsError {
+    /// A line, counted from 1, that is not a `<key> = <value>` pair
+    Malformed(usize),
+    /// The value of the setting can't be read or is out of range
+    Invalid(&'static str),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed(line) => write!(f, "malformed setting on line {}", line),
+            Self::Invalid(key) => write!(f, "invalid value of {}", key),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl Settings {
+    /// Where the settings are kept: `tekutonu/settings.txt` in the user's
+    /// config directory. `None` if there is no such directory.
+    pub fn default_path() -> Option<PathBuf> {
+        let var = |name| {
+            env::var_os(name)
+                .filter(|v| !v.is_empty())
+                .map(PathBuf::from)
+        };
+
+        let config = if cfg!(windows) {
+            var("APPDATA")
+        } else if cfg!(target_os = "macos") {
+            var("HOME").map(|home| home.join("Library/Application Support"))
+        } else {
+            var("XDG_CONFIG_HOME")
+                .filter(|path| path.is_absolute())
+                .or_else(|| var("HOME").map(|home| home.join(".config")))
+        };
+
+        Some(config?.join("tekutonu").join(FILE_NAME))
+    }
+
+    /// Read the settings at `path`. A missing file gives the defaults, and
+    /// settings that can't be used are replaced with their defaults.
+    #[instrument]
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+
+        let (settings, errors) = Self::parse(&text);
+        for e in errors {
+            warn!("{}: {}, using the default", path.display(), e);
+        }
+        Ok(settings)
+    }
+
+    /// Write the settings to `path`, creating its directory if needed.
+    #[instrument(skip(self))]
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.serialize())
+    }
+
+    /// Read settings from text, with the defaults in place of missing and
+    /// unusable ones. Also returns what was wrong with the text.
+    pub fn parse(text: &str) -> (Self, Vec<SettingsError>) {
+        let mut settings = Self::default();
+        let mut errors = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                errors.push(SettingsError::Malformed(i + 1));
+                continue;
+            };
+            if let Err(e) = settings.set(key.trim(), value.trim()) {
+                errors.push(e);
+            }
+        }
+
+        // Checked once all are read, as some depend on each other
+        let defaults = Self::default();
+        while let Err(e) = settings.validate() {
+            let SettingsError::Invalid(key) = e else {
+                unreachable!()
+            };
+            settings.reset(key, &defaults);
+            errors.push(e);
+        }
+
+        (settings, errors)
+    }
+
+    /// Set the setting `key` from its text. Unknown keys are kept as they
+    /// are. Values are not checked against their ranges.
+    fn set(&mut self, key: &str, value: &str) -> Result<(), SettingsError> {
+        fn parse<T: std::str::FromStr>(key: &'static str, value: &str) -> Result<T, SettingsError> {
+            value.parse().map_err(|_| SettingsError::Invalid(key))
+        }
+
+        match key {
+            "fov" => self.fov = parse("fov", value)?,
+            "near" => self.near = parse("near", value)?,
+            "far" => self.far = parse("far", value)?,
+            "mouse_sensitivity" => self.mouse_sensitivity = parse("mouse_sensitivity", value)?,
+            "movement_speed" => self.movement_speed = parse("movement_speed", value)?,
+            "window_size" => {
+                let invalid = SettingsError::Invalid("window_size");
+                let Some((width, height)) = value.split_once(' ') else {
+                    return Err(invalid);
+                };
+                self.window_size = [
+                    parse("window_size", width.trim())?,
+                    parse("window_size", height.trim())?,
+                ];
+            },
+            "window_mode" => self.window_mode = parse("window_mode", value)?,
+            _ => self.unknown.push((key.to_owned(), value.to_owned())),
+        }
+        Ok(())
+    }
+
+    /// Set the setting `key` to its value in `defaults`.
+    fn reset(&mut self, key: &str, defaults: &Self) {
+        match key {
+            "fov" => self.fov = defaults.fov,
+            "near" => self.near = defaults.near,
+            "far" => self.far = defaults.far,
+            "mouse_sensitivity" => self.mouse_sensitivity = defaults.mouse_sensitivity,
+            "movement_speed" => self.movement_speed = defaults.movement_speed,
+            "window_size" => self.window_size = defaults.window_size,
+            _ => unreachable!("unknown setting {}", key),
+        }
+    }
+
+    /// Check that every setting is in its range. The error names the first
+    /// one that is not.
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        let checks = [
+            ("fov", FOV_RANGE.contains(&self.fov)),
+            ("near", self.near > 0.0 && self.near <= 1.0),
+            ("far", self.far > self.near && self.far <= 1000.0),
+            (
+                "mouse_sensitivity",
+                self.mouse_sensitivity > 0.0 && self.mouse_sensitivity <= 10.0,
+            ),
+            (
+                "movement_speed",
+                self.movement_speed > 0.0 && self.movement_speed <= 10.0,
+            ),
+            (
+                "window_size",
+                self.window_size.iter().all(|&v| (1..=16384).contains(&v)),
+            ),
+        ];
+
+        match checks.into_iter().find(|(_, valid)| !valid) {
+            Some((key, _)) => Err(SettingsError::Invalid(key)),
+            None => Ok(()),
+        }
+    }
+
+    /// The text [`Settings::parse`] reads.
+    pub fn serialize(&self) -> String {
+        let [width, height] = self.window_size;
+        let mut text = format!(
+            "# tekutonu settings\n\
+             fov = {}\n\
+             near = {}\n\
+             far = {}\n\
+             mouse_sensitivity = {}\n\
+             movement_speed = {}\n\
+             window_size = {} {}\n\
+             window_mode = {}\n",
+            self.fov,
+            self.near,
+            self.far,
+            self.mouse_sensitivity,
+            self.movement_speed,
+            width,
+            height,
+            self.window_mode,
+        );
+        for (key, value) in &self.unknown {
+            text.push_str(&format!("{} = {}\n", key, value));
+        }
+        text
+    }
+
+    /// Perspective projection from view space to clip space.
+    pub fn projection_matrix(&self, aspect_ratio: f32) -> Matrix4<f32> {
+        cgmath::perspective(Deg(self.fov), aspect_ratio, self.near, self.far)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let settings = Settings {
+            fov: 70.0,
+            near: 0.05,
+            far: 250.0,
+            mouse_sensitivity: 0.5,
+            movement_speed: 0.2,
+            window_size: [800, 600],
+            window_mode: WindowMode::Exclusive,
+            unknown: Vec::new(),
+        };
+
+        let (parsed, errors) = Settings::parse(&settings.serialize());
+        assert_eq!(errors, vec![]);
+        assert_eq!(parsed, settings);
+
+        assert_eq!(Settings::parse(""), (Settings::default(), vec![]));
+    }
+
+    #[test]
+    fn unknown_keys_are_kept() {
+        let (settings, errors) = Settings::parse("fov = 80\nvsync = on\n# comment\nfov_scale = 2");
+        assert_eq!(errors, vec![]);
+        assert_eq!(settings.fov, 80.0);
+
+        let text = settings.serialize();
+        assert!(text.contains("vsync = on\nfov_scale = 2\n"), "{}", text);
+        assert_eq!(Settings::parse(&text).0, settings);
+    }
+
+    #[test]
+    fn invalid_settings_are_replaced() {
+        let defaults = Settings::default();
+        let (settings, errors) = Settings::parse(
+            "fov = wide\n\
+             mouse_sensitivity = -1\n\
+             movement_speed\n\
+             window_size = 800\n\
+             window_mode = maximized\n\
+             near = 0.5\n",
+        );
+
+        assert_eq!(
+            errors,
+            vec![
+                SettingsError::Invalid("fov"),
+                SettingsError::Malformed(3),
+                SettingsError::Invalid("window_size"),
+                SettingsError::Invalid("window_mode"),
+                SettingsError::Invalid("mouse_sensitivity"),
+            ]
+        );
+        assert_eq!(
+            settings,
+            Settings {
+                near: 0.5,
+                ..defaults
+            }
+        );
+    }
+
+    #[test]
+    fn window_modes() {
+        let mut mode = WindowMode::Windowed;
+        for _ in 0..3 {
+            assert_eq!(mode.to_string().parse(), Ok(mode));
+            mode = mode.next();
+        }
+        assert_eq!(mode, WindowMode::Windowed);
+    }
+
+    #[test]
+    fn far_plane_beyond_the_near_one() {
+        let (settings, errors) = Settings::parse("near = 0.5\nfar = 0.2");
+
+        assert_eq!(errors, vec![SettingsError::Invalid("far")]);
+        assert_eq!(settings.far, Settings::default().far);
+        assert_eq!(settings.validate(), Ok(()));
+    }
+
+    #[test]
+    fn saving_and_loading() {
+        let dir = env::temp_dir().join(format!("tekutonu-settings-{}", std::process::id()));
+        let path = dir.join("nested").join(FILE_NAME);
+
+        // Missing files give the defaults
+        assert_eq!(Settings::load(&path).unwrap(), Settings::default());
+
+        let settings = Settings {
+            fov: 100.0,
+            ..Default::default()
+        };
+        settings.save(&path).unwrap();
+        assert_eq!(Settings::load(&path).unwrap(), settings);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
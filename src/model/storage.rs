@@ -0,0 +1,195 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::PathBuf,
+};
+
+use tracing::instrument;
+
+use super::{chunk::Chunk, consts as c, region::Region};
+
+/// Bumped whenever the on-disk chunk layout changes.
+const FORMAT_VERSION: u8 = 1;
+
+/// Run-length encode `data` as `(run length, value)` byte pairs.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut iter = data.iter().peekable();
+
+    while let Some(&value) = iter.next() {
+        let mut run: u8 = 1;
+        while run < u8::MAX && iter.peek() == Some(&&value) {
+            iter.next();
+            run += 1;
+        }
+        out.extend([run, value]);
+    }
+
+    out
+}
+
+/// Inverse of [`compress`]. Returns `None` if the data is malformed or would
+/// be longer than `max_len`, before allocating more than that.
+pub fn decompress(data: &[u8], max_len: usize) -> Option<Vec<u8>> {
+    let pairs = data.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return None;
+    }
+
+    let mut out = Vec::new();
+    for pair in pairs {
+        let [run, value] = [pair[0], pair[1]];
+        if run == 0 || out.len() + run as usize > max_len {
+            return None;
+        }
+        out.resize(out.len() + run as usize, value);
+    }
+
+    Some(out)
+}
+
+/// Serialize and compress a chunk, prefixed with the format version.
+pub fn encode_chunk(chunk: &Chunk) -> Vec<u8> {
+    let mut out = vec![FORMAT_VERSION];
+    out.extend(compress(&chunk.to_bytes()));
+    out
+}
+
+/// Inverse of [`encode_chunk`].
+pub fn decode_chunk(data: &[u8]) -> Option<Chunk> {
+    match data.split_first() {
+        Some((&FORMAT_VERSION, rest)) => Chunk::from_bytes(&decompress(rest, Chunk::BYTES)?),
+        _ => None,
+    }
+}
+
+
+/// A world saved as a directory with one file per non-empty chunk.
+pub struct WorldStorage {
+    path: PathBuf,
+}
+
+impl WorldStorage {
+    #[instrument]
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    fn chunk_path(&self, [x, y, z]: [usize; 3]) -> PathBuf {
+        self.path.join("chunks").join(format!("{x}.{y}.{z}.chunk"))
+    }
+
+    fn time_path(&self) -> PathBuf {
+        self.path.join("time")
+    }
+
+    fn invalid(what: impl std::fmt::Display) -> io::Error {
+        io::Error::new(ErrorKind::InvalidData, what.to_string())
+    }
+
+    /// Load a single chunk. Returns `None` if it was never saved.
+    pub fn load_chunk(&self, loc: [usize; 3]) -> io::Result<Option<Chunk>> {
+        let path = self.chunk_path(loc);
+        match fs::read(&path) {
+            Ok(data) => decode_chunk(&data)
+                .map(Some)
+                .ok_or_else(|| Self::invalid(format!("Corrupted chunk: {}", path.display()))),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Save a single chunk. Empty chunks are removed from disk instead.
+    pub fn save_chunk(&self, loc: [usize; 3], chunk: &Chunk) -> io::Result<()> {
+        let path = self.chunk_path(loc);
+
+        if chunk.is_empty() {
+            return match fs::remove_file(path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            };
+        }
+
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, encode_chunk(chunk))
+    }
+
+    /// Load the whole region along with the world time.
+    /// Returns `None` if there is no world saved at the path.
+    #[instrument(skip(self))]
+    pub fn load_region(&self) -> io::Result<Option<(Region, u64)>> {
+        let time = match fs::read(self.time_path()) {
+            Ok(data) => u64::from_le_bytes(
+                data.try_into()
+                    .map_err(|_| Self::invalid("Corrupted world time"))?,
+            ),
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let mut region = Region::default();
+
+        for x in 0..c::REGION_X_CHUNKS {
+            for y in 0..c::REGION_Y_CHUNKS {
+                for z in 0..c::REGION_Z_CHUNKS {
+                    if let Some(chunk) = self.load_chunk([x, y, z])? {
+                        region.set_chunk([x, y, z], chunk);
+                    }
+                }
+            }
+        }
+
+        Ok(Some((region, time)))
+    }
+
+    /// Save the whole region along with the world time.
+    #[instrument(skip_all)]
+    pub fn save_region(&self, region: &Region, time: u64) -> io::Result<()> {
+        fs::create_dir_all(&self.path)?;
+
+        for (loc, chunk) in region.iter_chunks() {
+            self.save_chunk(loc, chunk)?;
+        }
+
+        fs::write(self.time_path(), time.to_le_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::block::Block;
+
+    #[test]
+    fn compression_roundtrip() {
+        let data = [[0; 300].as_slice(), &[1, 2, 2, 3], &[7; 10]].concat();
+
+        let compressed = compress(&data);
+
+        assert!(compressed.len() < data.len());
+        assert_eq!(decompress(&compressed, data.len()).unwrap(), data);
+        assert!(decompress(&compressed, data.len() - 1).is_none());
+    }
+
+    #[test]
+    fn chunk_roundtrip() {
+        let mut chunk = Chunk::default();
+        chunk.set_block([1isize, 2, 3], Block::solid());
+        chunk.set_block([4isize, 5, 6], Block::light_source());
+
+        let decoded = decode_chunk(&encode_chunk(&chunk)).unwrap();
+
+        assert_eq!(decoded.to_bytes(), chunk.to_bytes());
+        assert_eq!(
+            decoded.get_block([4isize, 5, 6]).kind,
+            Block::light_source().kind
+        );
+    }
+
+    #[test]
+    fn chunk_corrupted() {
+        assert!(decode_chunk(&[]).is_none());
+        assert!(decode_chunk(&[FORMAT_VERSION, 1, 0]).is_none());
+        assert!(decode_chunk(&[FORMAT_VERSION + 1]).is_none());
+    }
+}
@@ -0,0 +1,69 @@
+//! Fixtures shared by the tests of the model.
+
+use cgmath::Vector3;
+
+use super::{
+    block::Block,
+    chunk::{Chunk, SurroundingChunks},
+    consts as c,
+    mesh::{MeshConfig, Mesher, RenderData},
+};
+
+/// A chunk with every block set to `block`.
+pub fn filled(block: Block) -> Chunk {
+    let mut chunk = Chunk::default();
+    for x in 0..c::CHUNK_X_BLOCKS {
+        for y in 0..c::CHUNK_Y_BLOCKS {
+            for z in 0..c::CHUNK_Z_BLOCKS {
+                chunk.set_block([x, y, z], block);
+            }
+        }
+    }
+    chunk
+}
+
+/// `chunk` with its light calculated, as if alone under the open sky.
+pub fn lit(mut chunk: Chunk) -> Chunk {
+    SurroundingChunks::with_missing_as_sky([None; 6], |s| chunk.recalculate_light(s));
+    chunk
+}
+
+/// Mesh `chunk` at the origin, as if alone under the open sky.
+pub fn render(chunk: &Chunk, mesher: Mesher, smooth_lighting: bool) -> RenderData {
+    let config = MeshConfig {
+        mesher,
+        smooth_lighting,
+    };
+    SurroundingChunks::with_missing_as_sky([None; 6], |s| {
+        chunk.get_render_data(s, Vector3::new(0.0, 0.0, 0.0), config)
+    })
+}
+
+/// A deterministic sequence of pseudo-random numbers, the same for the same
+/// seed.
+pub struct Sequence(u64);
+
+impl Sequence {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// The next number, of 31 bits.
+    pub fn number(&mut self) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+
+    /// The next number below `max`.
+    pub fn below(&mut self, max: usize) -> usize {
+        self.number() as usize % max
+    }
+
+    /// The next number from 0 up to 1.
+    pub fn unit(&mut self) -> f32 {
+        (self.number() >> 7) as f32 / (1 << 24) as f32
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use super::{
+    block::{Block, BlockKind},
+    consts as c,
+    falling::{self, FallingBlock},
+    fluid,
+    region::Region,
+};
+
+/// Random ticks each non-empty chunk gets per simulation tick.
+pub const RANDOM_TICKS_PER_CHUNK: usize = 3;
+
+/// Why a block is being updated.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BlockUpdate {
+    /// The block itself was set
+    Changed,
+    /// One of the six blocks next to it was set
+    Neighbour,
+    /// The update was requested with [`UpdateContext::schedule`]
+    Scheduled,
+    /// The block was picked at random from its chunk
+    Random,
+}
+
+/// Updates of blocks over time, the backbone of everything in the world that
+/// changes on its own.
+///
+/// Each tick runs, in this order:
+/// - scheduled updates due on the tick, in the order of their locations
+/// - random ticks, [`RANDOM_TICKS_PER_CHUNK`] for each non-empty chunk in the
+///   order of the chunks. Unloaded chunks are empty, so they get none.
+/// - notifications of changed blocks, in the order the blocks changed,
+///   including the changes made by the notifications themselves
+///
+/// Random locations come from a generator seeded with the tick, so the same
+/// world and the same changes always end up the same.
+#[derive(Debug)]
+pub struct BlockUpdates {
+    /// Locations to update, by tick
+    scheduled: BTreeMap<u64, BTreeSet<[usize; 3]>>,
+    /// Notifications not delivered yet
+    pending: VecDeque<([usize; 3], BlockUpdate)>,
+    /// Blocks that started to fall, not taken yet
+    spawned: Vec<FallingBlock>,
+    pub random_ticks_per_chunk: usize,
+}
+
+impl Default for BlockUpdates {
+    fn default() -> Self {
+        Self {
+            scheduled: BTreeMap::new(),
+            pending: VecDeque::new(),
+            spawned: Vec::new(),
+            random_ticks_per_chunk: RANDOM_TICKS_PER_CHUNK,
+        }
+    }
+}
+
+impl BlockUpdates {
+    /// Notify the block at `loc` and its neighbours that it was set. They are
+    /// updated on the next tick.
+    pub fn block_changed(&mut self, loc: [usize; 3]) {
+        self.pending.push_back((loc, BlockUpdate::Changed));
+        for adjacent in c::ADJACENCY {
+            if let Some(neighbour) = Region::offset_location(loc, adjacent.0) {
+                self.pending.push_back((neighbour, BlockUpdate::Neighbour));
+            }
+        }
+    }
+
+    /// Request an update of the block at `loc` on tick `at`.
+    pub fn schedule(&mut self, loc: [usize; 3], at: u64) {
+        self.scheduled.entry(at).or_default().insert(loc);
+    }
+
+    /// Take the blocks that started to fall in the updates so far.
+    pub fn take_spawned(&mut self) -> Vec<FallingBlock> {
+        std::mem::take(&mut self.spawned)
+    }
+
+    /// Whether there are no updates scheduled or pending. Random ticks don't
+    /// count.
+    pub fn is_idle(&self) -> bool {
+        self.scheduled.is_empty() && self.pending.is_empty()
+    }
+
+    /// Run the updates of tick `now`, and any scheduled ones that were missed
+    /// before. Returns the locations of the blocks that changed.
+    pub fn tick(&mut self, region: &mut Region, now: u64) -> Vec<[usize; 3]> {
+        let mut ctx = UpdateContext::new(region, self, now);
+
+        while let Some(entry) = ctx.updates.scheduled.first_entry() {
+            if *entry.key() > now {
+                break;
+            }
+            for loc in entry.remove() {
+                update(&mut ctx, loc, BlockUpdate::Scheduled);
+            }
+        }
+
+        for loc in ctx.random_locations() {
+            update(&mut ctx, loc, BlockUpdate::Random);
+        }
+
+        while let Some((loc, cause)) = ctx.updates.pending.pop_front() {
+            update(&mut ctx, loc, cause);
+        }
+
+        ctx.changed
+    }
+}
+
+/// What block behaviours can do during an update.
+pub struct UpdateContext<'a> {
+    region: &'a mut Region,
+    updates: &'a mut BlockUpdates,
+    now: u64,
+    changed: Vec<[usize; 3]>,
+    /// State of the random generator, seeded with the tick
+    random: u64,
+}
+
+impl<'a> UpdateContext<'a> {
+    fn new(region: &'a mut Region, updates: &'a mut BlockUpdates, now: u64) -> Self {
+        // Mix the tick, so that random numbers of consecutive ticks differ
+        let mut random = now.wrapping_add(0x9e3779b97f4a7c15);
+        random = (random ^ (random >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        random = (random ^ (random >> 27)).wrapping_mul(0x94d049bb133111eb);
+
+        Self {
+            region,
+            updates,
+            now,
+            changed: Vec::new(),
+            random: random ^ (random >> 31),
+        }
+    }
+
+    pub fn region(&self) -> &Region {
+        self.region
+    }
+
+    /// The tick being run.
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Set a block and notify it and its neighbours. Returns `false` if the
+    /// location is outside of the region.
+    pub fn set_block(&mut self, loc: [usize; 3], block: Block) -> bool {
+        if !self.region.set_block(loc, block) {
+            return false;
+        }
+        self.updates.block_changed(loc);
+        self.changed.push(loc);
+        true
+    }
+
+    /// Request an update of the block at `loc` in `delay` ticks.
+    pub fn schedule(&mut self, loc: [usize; 3], delay: u64) {
+        self.updates.schedule(loc, self.now + delay.max(1));
+    }
+
+    /// A random number, the same for the same tick and the same calls before.
+    pub fn random(&mut self) -> usize {
+        self.random = self
+            .random
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.random >> 33) as usize
+    }
+
+    /// Hand over a block that left the world to fall.
+    pub fn spawn(&mut self, falling: FallingBlock) {
+        self.updates.spawned.push(falling);
+    }
+
+    /// Random locations inside of the non-empty chunks.
+    fn random_locations(&mut self) -> Vec<[usize; 3]> {
+        let chunks: Vec<_> = self
+            .region
+            .iter_chunks()
+            .filter(|(_, c)| !c.is_empty())
+            .map(|(loc, _)| loc)
+            .collect();
+
+        let mut locations = Vec::new();
+        for chunk in chunks {
+            for _ in 0..self.updates.random_ticks_per_chunk {
+                let r = self.random();
+                locations.push([
+                    chunk[0] * c::CHUNK_X_BLOCKS + r % c::CHUNK_X_BLOCKS,
+                    chunk[1] * c::CHUNK_Y_BLOCKS + r / c::CHUNK_X_BLOCKS % c::CHUNK_Y_BLOCKS,
+                    chunk[2] * c::CHUNK_Z_BLOCKS
+                        + r / (c::CHUNK_X_BLOCKS * c::CHUNK_Y_BLOCKS) % c::CHUNK_Z_BLOCKS,
+                ]);
+            }
+        }
+        locations
+    }
+}
+
+/// Let the block at `loc` react to `cause`.
+fn update(ctx: &mut UpdateContext, loc: [usize; 3], cause: BlockUpdate) {
+    let Some(block) = ctx.region().get_block(loc) else {
+        return;
+    };
+
+    match block.kind {
+        BlockKind::Fluid { fluid, .. } => fluid::update(ctx, loc, fluid, cause),
+        kind if kind.has_gravity() => falling::update(ctx, loc, cause),
+        _ => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scheduled_updates_run_on_time() {
+        let mut region = Region::default();
+        let mut updates = BlockUpdates::default();
+
+        updates.schedule([5, 5, 5], 3);
+        updates.schedule([6, 5, 5], 3);
+        updates.schedule([5, 5, 5], 4);
+        for now in 1..3 {
+            updates.tick(&mut region, now);
+        }
+        assert_eq!(updates.scheduled.len(), 2);
+        assert_eq!(updates.scheduled[&3].len(), 2);
+
+        updates.tick(&mut region, 3);
+        assert!(!updates.is_idle());
+        // Missed updates still run
+        updates.tick(&mut region, 10);
+        assert!(updates.is_idle());
+    }
+
+    #[test]
+    fn neighbours_are_notified() {
+        let mut region = Region::default();
+        let mut updates = BlockUpdates::default();
+
+        updates.block_changed([5, 5, 5]);
+        let notified: Vec<_> = updates.pending.iter().copied().collect();
+        assert_eq!(notified[0], ([5, 5, 5], BlockUpdate::Changed));
+        assert_eq!(notified.len(), 7);
+        for adjacent in c::ADJACENCY {
+            let loc = Region::offset_location([5, 5, 5], adjacent.0).unwrap();
+            assert!(notified.contains(&(loc, BlockUpdate::Neighbour)));
+        }
+
+        // On the edge of the region some neighbours are missing
+        updates.tick(&mut region, 1);
+        updates.block_changed([0, 0, 0]);
+        assert_eq!(updates.pending.len(), 4);
+    }
+
+    #[test]
+    fn random_ticks_stay_in_non_empty_chunks() {
+        let mut region = Region::default();
+        let mut updates = BlockUpdates::default();
+        region.set_block([20, 40, 60], Block::solid());
+        region.set_block([100, 0, 0], Block::solid());
+
+        let mut ctx = UpdateContext::new(&mut region, &mut updates, 7);
+        let locations = ctx.random_locations();
+        assert_eq!(locations.len(), 2 * RANDOM_TICKS_PER_CHUNK);
+        for loc in locations {
+            let (chunk, _) = Region::split_location(loc).unwrap();
+            assert!(chunk == [1, 2, 3] || chunk == [6, 0, 0], "{:?}", loc);
+        }
+    }
+
+    #[test]
+    fn deterministic() {
+        let run = |now| {
+            let mut region = Region::default();
+            let mut updates = BlockUpdates::default();
+            region.set_block([20, 40, 60], Block::solid());
+            UpdateContext::new(&mut region, &mut updates, now).random_locations()
+        };
+
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+    }
+}
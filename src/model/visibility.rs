@@ -0,0 +1,248 @@
+use std::collections::{HashSet, VecDeque};
+
+use super::{chunk::Chunk, consts as c, region::Region, types as t};
+
+/// Index of the opposite face, indexed like `ADJACENCY`
+const OPPOSITE: [usize; 6] = [2, 3, 0, 1, 5, 4];
+
+
+/// Which faces of a chunk can be seen from each other through transparent
+/// blocks. Faces are indexed like `ADJACENCY`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Connectivity([u8; 6]);
+
+impl Connectivity {
+    /// Every face connected to every other face
+    pub const ALL: Self = Self([0b11_1111; 6]);
+    pub const NONE: Self = Self([0; 6]);
+
+    pub fn connects(&self, a: usize, b: usize) -> bool {
+        self.0[a] & (1 << b) != 0
+    }
+
+    /// Flood fill the transparent blocks of the chunk, connecting the faces
+    /// that each filled area touches.
+    pub fn of(chunk: &Chunk) -> Self {
+        if chunk.is_empty() {
+            return Self::ALL;
+        }
+
+        let mut visited = [[[false; c::CHUNK_Z_BLOCKS]; c::CHUNK_Y_BLOCKS]; c::CHUNK_X_BLOCKS];
+        let mut connectivity = Self::NONE;
+        let mut queue = VecDeque::new();
+
+        for x in 0..c::CHUNK_X_BLOCKS {
+            for y in 0..c::CHUNK_Y_BLOCKS {
+                for z in 0..c::CHUNK_Z_BLOCKS {
+                    if visited[x][y][z] || !chunk.get_block([x, y, z]).is_transparent() {
+                        continue;
+                    }
+
+                    visited[x][y][z] = true;
+                    queue.push_back(t::PointIntLocal::from([x, y, z]));
+
+                    // Faces touched by this area
+                    let mut faces = 0;
+                    while let Some(loc) = queue.pop_front() {
+                        for (i, adjacent) in c::ADJACENCY.iter().enumerate() {
+                            let next = loc + adjacent;
+                            if !next.is_in_chunk() {
+                                faces |= 1 << i;
+                                continue;
+                            }
+
+                            let seen = &mut visited[next.ux()][next.uy()][next.uz()];
+                            if !*seen && chunk.get_block(next).is_transparent() {
+                                *seen = true;
+                                queue.push_back(next);
+                            }
+                        }
+                    }
+
+                    for (i, connected) in connectivity.0.iter_mut().enumerate() {
+                        if faces & (1 << i) != 0 {
+                            *connected |= faces;
+                        }
+                    }
+                }
+            }
+        }
+
+        connectivity
+    }
+}
+
+impl Default for Connectivity {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+
+/// Chunks that may be visible from the chunk at `start`.
+///
+/// Walks from chunk to chunk, leaving each one only through faces connected
+/// to the face it was entered through, and never turning back against a
+/// direction already taken. Chunks for which `in_view` is false are not
+/// entered.
+pub fn visible_chunks(
+    region: &Region,
+    start: [usize; 3],
+    in_view: impl Fn([usize; 3]) -> bool,
+) -> HashSet<[usize; 3]> {
+    let mut visible = HashSet::from([start]);
+    // Chunk, the face it was entered through and directions taken so far
+    let mut queue = VecDeque::from([(start, None, 0u8)]);
+
+    while let Some((loc, entered, directions)) = queue.pop_front() {
+        let connectivity = region.get_chunk(loc).connectivity();
+
+        for (i, adjacent) in c::ADJACENCY.iter().enumerate() {
+            if directions & (1 << OPPOSITE[i]) != 0 {
+                continue;
+            }
+            if let Some(entered) = entered {
+                if !connectivity.connects(entered, i) {
+                    continue;
+                }
+            }
+
+            let Some(next) = Region::offset_chunk_location(loc, adjacent.0) else {
+                continue;
+            };
+            if visible.contains(&next) || !in_view(next) {
+                continue;
+            }
+
+            visible.insert(next);
+            queue.push_back((next, Some(OPPOSITE[i]), directions | (1 << i)));
+        }
+    }
+
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{block::Block, test_util::filled};
+
+    /// Faces indexed like `ADJACENCY`
+    const FRONT: usize = 0;
+    const LEFT: usize = 1;
+    const BACK: usize = 2;
+    const RIGHT: usize = 3;
+    const BOTTOM: usize = 4;
+    const TOP: usize = 5;
+
+    fn solid() -> Chunk {
+        filled(Block::solid())
+    }
+
+    #[test]
+    fn empty_and_solid() {
+        assert_eq!(Connectivity::of(&Chunk::default()), Connectivity::ALL);
+        assert_eq!(Connectivity::of(&solid()), Connectivity::NONE);
+    }
+
+    #[test]
+    fn tunnel_connects_its_ends() {
+        // Straight tunnel along X
+        let mut chunk = solid();
+        for x in 0..c::CHUNK_X_BLOCKS {
+            chunk.set_block([x, 8, 8], Block::air());
+        }
+
+        let connectivity = Connectivity::of(&chunk);
+
+        assert!(connectivity.connects(LEFT, RIGHT));
+        assert!(connectivity.connects(RIGHT, LEFT));
+        assert!(!connectivity.connects(LEFT, TOP));
+        assert!(!connectivity.connects(FRONT, BACK));
+        assert!(!connectivity.connects(BOTTOM, TOP));
+    }
+
+    #[test]
+    fn separate_caves_do_not_connect() {
+        // A wall splits the chunk along Y into a lower and an upper cave
+        let mut chunk = Chunk::default();
+        for x in 0..c::CHUNK_X_BLOCKS {
+            for z in 0..c::CHUNK_Z_BLOCKS {
+                chunk.set_block([x, 8, z], Block::solid());
+            }
+        }
+
+        let connectivity = Connectivity::of(&chunk);
+
+        assert!(!connectivity.connects(BOTTOM, TOP));
+        assert!(connectivity.connects(BOTTOM, LEFT));
+        assert!(connectivity.connects(TOP, LEFT));
+        // Both caves touch the sides
+        assert!(connectivity.connects(LEFT, RIGHT));
+    }
+
+    /// Region where every chunk has the connectivity it would have after
+    /// meshing.
+    fn connected(mut region: Region) -> Region {
+        let locations: Vec<_> = region.iter_chunks().map(|(loc, _)| loc).collect();
+        for loc in locations {
+            let connectivity = Connectivity::of(region.get_chunk(loc));
+            region.update_connectivity(loc, connectivity);
+        }
+        region
+    }
+
+    #[test]
+    fn walls_hide_chunks_behind_them() {
+        let mut region = Region::default();
+        // A solid wall of chunks at z = 3
+        for x in 0..c::REGION_X_CHUNKS {
+            for y in 0..c::REGION_Y_CHUNKS {
+                region.set_chunk([x, y, 3], solid());
+            }
+        }
+        let region = connected(region);
+
+        let visible = visible_chunks(&region, [5, 5, 1], |_| true);
+
+        assert!(visible.contains(&[5, 5, 2]));
+        // The wall itself may be visible, but nothing behind it
+        assert!(visible.contains(&[5, 5, 3]));
+        assert!(!visible.contains(&[5, 5, 4]));
+        assert!(!visible.contains(&[0, 0, 15]));
+    }
+
+    #[test]
+    fn tunnels_lead_through_walls() {
+        let mut region = Region::default();
+        for x in 0..c::REGION_X_CHUNKS {
+            for y in 0..c::REGION_Y_CHUNKS {
+                region.set_chunk([x, y, 3], solid());
+            }
+        }
+        // Tunnel along Z through the wall chunk in front of the camera
+        let mut tunnel = solid();
+        for z in 0..c::CHUNK_Z_BLOCKS {
+            tunnel.set_block([8, 8, z], Block::air());
+        }
+        region.set_chunk([5, 5, 3], tunnel);
+        let region = connected(region);
+
+        let visible = visible_chunks(&region, [5, 5, 1], |_| true);
+
+        assert!(visible.contains(&[5, 5, 4]));
+        assert!(visible.contains(&[5, 5, 15]));
+    }
+
+    #[test]
+    fn does_not_turn_back() {
+        let region = connected(Region::default());
+
+        let visible = visible_chunks(&region, [5, 5, 5], |loc| loc != [5, 5, 6]);
+
+        assert!(visible.contains(&[6, 5, 7]));
+        assert!(visible.contains(&[0, 0, 15]));
+        // Going around [5, 5, 6] would mean turning back towards X = 5
+        assert!(!visible.contains(&[5, 5, 7]));
+    }
+}
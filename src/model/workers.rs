@@ -0,0 +1,501 @@
+use std::{
+    collections::HashMap,
+    sync::{mpsc, Arc, Mutex},
+    thread::{self, JoinHandle},
+};
+
+use cgmath::Vector3;
+use tracing::instrument;
+
+use super::{
+    chunk::{AdjacentDirection, Chunk, SurroundingChunks},
+    consts as c,
+    lod::{self, Downsampled, Lod},
+    mesh::{MeshConfig, RenderData},
+    region::Region,
+    visibility::Connectivity,
+};
+
+
+/// Geometry of a single chunk, in block coordinates of the region.
+pub struct ChunkMesh {
+    pub location: [usize; 3],
+    /// Version of the chunk the mesh was built from
+    pub version: u64,
+    pub lod: Lod,
+    pub data: RenderData,
+    /// Faces of translucent blocks, empty below full detail
+    pub translucent: RenderData,
+}
+
+
+/// Level of detail a chunk is meshed at, and which of its neighbours (in the
+/// order of [`AdjacentDirection::ALL`]) are meshed at other levels.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Detail {
+    lod: Lod,
+    seams: [bool; 6],
+}
+
+impl Detail {
+    fn of(location: [usize; 3], lod_of: &impl Fn([usize; 3]) -> Lod) -> Self {
+        let lod = lod_of(location);
+        let seams = AdjacentDirection::ALL.map(|d| {
+            Region::offset_chunk_location(location, d.offset()).is_some_and(|n| lod_of(n) != lod)
+        });
+        Self { lod, seams }
+    }
+}
+
+
+/// What a chunk was submitted with. Its mesh depends on the chunk itself and
+/// on the blocks and light at the borders of its neighbours, so a change to
+/// any of them needs a new mesh.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Submission {
+    version: u64,
+    detail: Detail,
+    /// Version and light revision of each neighbour, in the order of
+    /// [`AdjacentDirection::ALL`]
+    neighbours: [Option<(u64, u64)>; 6],
+}
+
+
+/// Everything needed to light and mesh a chunk without touching the region.
+struct ChunkJob {
+    location: [usize; 3],
+    config: MeshConfig,
+    submission: Submission,
+    chunk: Chunk,
+    neighbours: [Option<Arc<Chunk>>; 6],
+}
+
+impl ChunkJob {
+    fn run(mut self) -> ChunkJobResult {
+        let neighbours = std::array::from_fn(|i| self.neighbours[i].as_deref());
+        let Detail { lod, seams } = self.submission.detail;
+        let (data, translucent) =
+            SurroundingChunks::with_missing_as_sky(neighbours, |surrounding| {
+                self.chunk.recalculate_light(surrounding);
+                let offset =
+                    Vector3::from(self.location.map(|v| (v * c::CHUNK_EDGE_BLOCKS) as f32));
+                if lod == Lod::Full {
+                    return lod::with_seams(surrounding, seams, |surrounding| {
+                        (
+                            self.chunk.get_render_data(surrounding, offset, self.config),
+                            self.chunk.get_translucent_render_data(
+                                surrounding,
+                                offset,
+                                self.config,
+                            ),
+                        )
+                    });
+                }
+
+                // Neighbours are only needed for their light and opacity, so
+                // they are not relit
+                let around = neighbours.map(|n| n.map(|n| Downsampled::of(n, lod)));
+                let data = lod::mesh(
+                    &Downsampled::of(&self.chunk, lod),
+                    std::array::from_fn(|i| around[i].as_ref()),
+                    seams,
+                    offset,
+                );
+                (data, RenderData::default())
+            });
+
+        ChunkJobResult {
+            mesh: ChunkMesh {
+                location: self.location,
+                version: self.chunk.version(),
+                lod,
+                data,
+                translucent,
+            },
+            submission: self.submission,
+            connectivity: Connectivity::of(&self.chunk),
+            lit: self.chunk,
+        }
+    }
+}
+
+struct ChunkJobResult {
+    mesh: ChunkMesh,
+    submission: Submission,
+    /// The chunk with recalculated light
+    lit: Chunk,
+    connectivity: Connectivity,
+}
+
+
+/// A pool of threads that light and mesh chunks in the background.
+///
+/// Jobs work on snapshots of chunks. Results are only applied if the chunk
+/// has not changed since its snapshot was taken, so edits made while a job is
+/// running are never overwritten.
+pub struct ChunkWorkers {
+    config: MeshConfig,
+    jobs: Option<mpsc::Sender<ChunkJob>>,
+    results: mpsc::Receiver<ChunkJobResult>,
+    threads: Vec<JoinHandle<()>>,
+
+    /// Latest submission of each chunk
+    submitted: HashMap<[usize; 3], Submission>,
+    /// How many times the light of each chunk changed when results were
+    /// applied
+    light_revisions: HashMap<[usize; 3], u64>,
+    /// Number of jobs that were submitted but not collected yet
+    in_flight: usize,
+}
+
+impl ChunkWorkers {
+    pub fn new(threads: usize, config: MeshConfig) -> Self {
+        let (jobs, jobs_rx) = mpsc::channel::<ChunkJob>();
+        let (results_tx, results) = mpsc::channel();
+        let jobs_rx = Arc::new(Mutex::new(jobs_rx));
+
+        let threads = (0..threads.max(1))
+            .map(|i| {
+                let jobs = jobs_rx.clone();
+                let results = results_tx.clone();
+                thread::Builder::new()
+                    .name(format!("chunk-worker-{}", i))
+                    .spawn(move || loop {
+                        // The lock is released before running the job
+                        let job = jobs.lock().unwrap().recv();
+                        match job {
+                            Ok(job) => {
+                                if results.send(job.run()).is_err() {
+                                    break;
+                                }
+                            },
+                            Err(_) => break,
+                        }
+                    })
+                    .unwrap()
+            })
+            .collect();
+
+        Self {
+            config,
+            jobs: Some(jobs),
+            results,
+            threads,
+            submitted: HashMap::new(),
+            light_revisions: HashMap::new(),
+            in_flight: 0,
+        }
+    }
+
+    /// Number of jobs that were submitted but not collected yet.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+
+    /// Snapshot a chunk with its neighbours and queue it for processing at
+    /// full detail.
+    pub fn submit(&mut self, region: &Region, location: [usize; 3]) {
+        self.submit_with(region, location, Detail::default());
+    }
+
+    /// What `location` would be submitted with at `detail`.
+    fn submission(&self, region: &Region, location: [usize; 3], detail: Detail) -> Submission {
+        let neighbours = AdjacentDirection::ALL.map(|d| {
+            let neighbour = Region::offset_chunk_location(location, d.offset())?;
+            let light = self.light_revisions.get(&neighbour).copied().unwrap_or(0);
+            Some((region.get_chunk(neighbour).version(), light))
+        });
+
+        Submission {
+            version: region.get_chunk(location).version(),
+            detail,
+            neighbours,
+        }
+    }
+
+    fn submit_with(&mut self, region: &Region, location: [usize; 3], detail: Detail) {
+        let chunk = region.get_chunk(location);
+        let neighbours = region
+            .get_adjacent_chunks(location)
+            .map(|n| n.map(|n| Arc::new(n.clone())));
+
+        let submission = self.submission(region, location, detail);
+        self.submitted.insert(location, submission);
+        self.in_flight += 1;
+
+        self.jobs
+            .as_ref()
+            .unwrap()
+            .send(ChunkJob {
+                location,
+                config: self.config,
+                submission,
+                chunk: chunk.clone(),
+                neighbours,
+            })
+            .expect("Chunk workers have stopped");
+    }
+
+    /// Submit every chunk that changed since it was last submitted, at full
+    /// detail. Chunks that were always empty are skipped.
+    pub fn submit_changed(&mut self, region: &Region) {
+        self.submit_changed_with(region, |_| Lod::Full);
+    }
+
+    /// Like [`ChunkWorkers::submit_changed`], with the level of detail of
+    /// each chunk given by `lod_of`. Chunks are also resubmitted when their
+    /// level or that of a neighbour changes, and when the blocks or light of
+    /// a neighbour change.
+    #[instrument(skip_all)]
+    pub fn submit_changed_with(&mut self, region: &Region, lod_of: impl Fn([usize; 3]) -> Lod) {
+        let changed: Vec<_> = region
+            .iter_chunks()
+            .map(|(loc, chunk)| (loc, chunk, Detail::of(loc, &lod_of)))
+            .filter(|(loc, chunk, detail)| match self.submitted.get(loc) {
+                Some(submitted) => *submitted != self.submission(region, *loc, *detail),
+                None => !chunk.is_empty(),
+            })
+            .map(|(loc, _, detail)| (loc, detail))
+            .collect();
+
+        for (loc, detail) in changed {
+            self.submit_with(region, loc, detail);
+        }
+    }
+
+    fn apply(&mut self, result: ChunkJobResult, region: &mut Region) -> Option<ChunkMesh> {
+        self.in_flight -= 1;
+
+        let location = result.mesh.location;
+        if region.get_chunk(location).version() != result.mesh.version {
+            // The chunk was changed while the job was running
+            return None;
+        }
+
+        if region.update_light(location, &result.lit) {
+            // Meshes of the neighbours use the light at the border
+            *self.light_revisions.entry(location).or_default() += 1;
+        }
+        region.update_connectivity(location, result.connectivity);
+
+        if self.submitted.get(&location) != Some(&result.submission) {
+            // The chunk was resubmitted since, e.g. at another level of detail
+            return None;
+        }
+        Some(result.mesh)
+    }
+
+    /// Apply finished jobs to the region without blocking.
+    ///
+    /// Returns meshes of chunks that are up to date with the region.
+    #[instrument(skip_all)]
+    pub fn collect(&mut self, region: &mut Region) -> Vec<ChunkMesh> {
+        let results: Vec<_> = self.results.try_iter().collect();
+        results
+            .into_iter()
+            .filter_map(|result| self.apply(result, region))
+            .collect()
+    }
+
+    /// Like [`ChunkWorkers::collect`], but wait for all submitted jobs to
+    /// finish first.
+    pub fn collect_all(&mut self, region: &mut Region) -> Vec<ChunkMesh> {
+        let mut meshes = Vec::new();
+        while self.in_flight > 0 {
+            let result = self.results.recv().expect("Chunk workers have stopped");
+            meshes.extend(self.apply(result, region));
+        }
+        meshes
+    }
+}
+
+impl Drop for ChunkWorkers {
+    fn drop(&mut self) {
+        // Closing the channel stops the threads
+        self.jobs = None;
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{block::Block, test_util::Sequence};
+
+    const LOC: [usize; 3] = [1, 1, 1];
+
+    fn region_with_roof() -> Region {
+        let mut region = Region::default();
+        for x in 16..32 {
+            for z in 16..32 {
+                region.set_block([x, 31, z], Block::solid());
+            }
+        }
+        region
+    }
+
+    #[test]
+    fn lights_and_meshes() {
+        let mut region = region_with_roof();
+        let mut workers = ChunkWorkers::new(2, MeshConfig::default());
+
+        workers.submit_changed(&region);
+        let meshes = workers.collect_all(&mut region);
+
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].location, LOC);
+        assert!(!meshes[0].data.indices.is_empty());
+        // The roof spans blocks 16..32 and sits at the top of the chunk
+        assert!(meshes[0].data.vertices.iter().all(|v| {
+            (16.0..=32.0).contains(&v.x)
+                && (31.0..=32.0).contains(&v.y)
+                && (16.0..=32.0).contains(&v.z)
+        }));
+        // Sky light only comes from the sides under the roof
+        assert_eq!(region.get_chunk(LOC).get_light_sky([8isize, 14, 8]), 7);
+
+        // Nothing changed, nothing to do
+        workers.submit_changed(&region);
+        assert_eq!(workers.in_flight(), 0);
+    }
+
+    #[test]
+    fn remeshes_when_detail_changes() {
+        let mut region = region_with_roof();
+        let mut workers = ChunkWorkers::new(2, MeshConfig::default());
+
+        workers.submit_changed(&region);
+        let full = workers.collect_all(&mut region);
+        assert_eq!(full[0].lod, Lod::Full);
+
+        workers.submit_changed_with(&region, |_| Lod::Quarter);
+        let quarter = workers.collect_all(&mut region);
+        assert_eq!(quarter.len(), 1);
+        assert_eq!(quarter[0].lod, Lod::Quarter);
+        assert_eq!(quarter[0].version, full[0].version);
+        // The roof is a layer of single blocks, too thin for a whole cell
+        assert_eq!(quarter[0].data.quad_count(), 0);
+
+        // A neighbour changing its level opens a seam
+        workers.submit_changed_with(&region, |loc| {
+            if loc == [2, 1, 1] {
+                Lod::Full
+            } else {
+                Lod::Quarter
+            }
+        });
+        assert_eq!(workers.in_flight(), 1);
+        workers.collect_all(&mut region);
+
+        workers.submit_changed_with(&region, |loc| {
+            if loc == [2, 1, 1] {
+                Lod::Full
+            } else {
+                Lod::Quarter
+            }
+        });
+        assert_eq!(workers.in_flight(), 0);
+    }
+
+    /// Submit and collect until nothing changes anymore.
+    fn settle(workers: &mut ChunkWorkers, region: &mut Region) -> Vec<ChunkMesh> {
+        let mut meshes = Vec::new();
+        for _ in 0..32 {
+            workers.submit_changed(region);
+            if workers.in_flight() == 0 {
+                return meshes;
+            }
+            meshes.extend(workers.collect_all(region));
+        }
+        panic!("Meshing does not settle");
+    }
+
+    #[test]
+    fn remeshes_next_to_changed_chunks() {
+        let mut region = region_with_roof();
+        region.set_block([8, 20, 20], Block::solid());
+        let mut workers = ChunkWorkers::new(2, MeshConfig::default());
+        settle(&mut workers, &mut region);
+
+        // On the border with the roofed chunk
+        region.set_block([15, 20, 20], Block::solid());
+        let meshes = settle(&mut workers, &mut region);
+
+        let mut remeshed: Vec<_> = meshes.iter().map(|m| m.location).collect();
+        remeshed.sort_unstable();
+        remeshed.dedup();
+        assert_eq!(remeshed, vec![[0, 1, 1], LOC]);
+    }
+
+    #[test]
+    fn stale_results_are_dropped() {
+        let mut region = region_with_roof();
+        let mut workers = ChunkWorkers::new(1, MeshConfig::default());
+
+        workers.submit(&region, LOC);
+        // Edit while the job might be running
+        region.set_block([20, 20, 20], Block::light_source());
+        let edited = region.get_chunk(LOC).to_bytes();
+
+        let meshes = workers.collect_all(&mut region);
+        assert!(meshes.is_empty(), "mesh of the old version is not returned");
+        assert_eq!(
+            region.get_chunk(LOC).to_bytes(),
+            edited,
+            "old light is not applied"
+        );
+
+        workers.submit_changed(&region);
+        let meshes = workers.collect_all(&mut region);
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].version, region.get_chunk(LOC).version());
+        assert_eq!(
+            region.get_chunk(LOC).get_light_local([4isize, 4, 4]),
+            15,
+            "the new light source is lit"
+        );
+    }
+
+    #[test]
+    fn concurrent_edits_are_never_overwritten() {
+        let mut region = region_with_roof();
+        let mut workers = ChunkWorkers::new(4, MeshConfig::default());
+
+        // A simple deterministic sequence of edits
+        let mut sequence = Sequence::new(12345);
+
+        for _ in 0..200 {
+            let loc = [
+                16 + sequence.below(16),
+                16 + sequence.below(15),
+                16 + sequence.below(16),
+            ];
+            let block = match sequence.below(3) {
+                0 => Block::air(),
+                1 => Block::solid(),
+                _ => Block::light_source(),
+            };
+            region.set_block(loc, block);
+
+            workers.submit_changed(&region);
+            for mesh in workers.collect(&mut region) {
+                assert_eq!(mesh.version, region.get_chunk(mesh.location).version());
+            }
+        }
+
+        let blocks_before = region.get_chunk(LOC).to_bytes()[..2 * 4096].to_vec();
+        workers.collect_all(&mut region);
+        workers.submit_changed(&region);
+        workers.collect_all(&mut region);
+
+        // Blocks are exactly as edited and light matches the final blocks
+        let chunk = region.get_chunk(LOC);
+        assert_eq!(chunk.to_bytes()[..2 * 4096], blocks_before);
+
+        let mut expected = chunk.clone();
+        region.with_surrounding(LOC, |s| expected.recalculate_light(s));
+        assert_eq!(chunk.to_bytes(), expected.to_bytes());
+    }
+}
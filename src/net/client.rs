@@ -0,0 +1,161 @@
+use std::{
+    net::{TcpStream, ToSocketAddrs},
+    time::Instant,
+};
+
+use cgmath::Point3;
+use tracing::{info, instrument};
+
+use super::{
+    connection::Connection,
+    protocol::{Message, PROTOCOL_VERSION},
+    ProtocolError,
+    TIMEOUT,
+};
+use crate::model::{block::Block, chunk::Chunk, region::Region, Camera, GameModel};
+
+
+/// Client side of a connection to a dedicated server.
+///
+/// Keeps the local [`GameModel`] in sync with what the server sends.
+pub struct NetClient {
+    connection: Connection,
+    welcomed: bool,
+    last_received: Instant,
+    last_position: Option<Point3<f64>>,
+}
+
+impl NetClient {
+    #[instrument(skip_all)]
+    pub fn connect(address: impl ToSocketAddrs) -> Result<Self, ProtocolError> {
+        let stream = TcpStream::connect(address)?;
+        let mut connection = Connection::new(stream)?;
+        info!("Connected to {}", connection.address());
+
+        connection.send(&Message::Hello {
+            version: PROTOCOL_VERSION,
+        });
+        connection.flush()?;
+
+        Ok(Self {
+            connection,
+            welcomed: false,
+            last_received: Instant::now(),
+            last_position: None,
+        })
+    }
+
+    /// Whether the server has accepted our handshake.
+    pub fn is_welcomed(&self) -> bool {
+        self.welcomed
+    }
+
+    /// Tell the server where the camera is, if it has moved.
+    pub fn send_position(&mut self, camera: &Camera) {
+        if self.last_position == Some(camera.position) {
+            return;
+        }
+        self.last_position = Some(camera.position);
+
+        self.connection.send(&Message::PlayerPosition {
+            position: camera.position,
+            pitch: camera.pitch,
+            yaw: camera.yaw,
+        });
+    }
+
+    /// Ask the server to change a block. The change is applied locally once
+    /// the server confirms it.
+    pub fn send_block_change(&mut self, location: [usize; 3], block: Block) {
+        self.connection
+            .send(&Message::BlockChange { location, block });
+    }
+
+    /// Exchange messages with the server and apply whatever it sent to `game`.
+    pub fn poll(&mut self, game: &mut GameModel) -> Result<(), ProtocolError> {
+        let messages = self.connection.receive()?;
+
+        if !messages.is_empty() {
+            self.last_received = Instant::now();
+        } else if self.last_received.elapsed() > TIMEOUT {
+            return Err(ProtocolError::TimedOut);
+        }
+
+        for message in messages {
+            match message {
+                Message::Welcome { time } => {
+                    self.welcomed = true;
+                    game.time = time;
+                },
+                Message::Disconnect { reason } => return Err(ProtocolError::Kicked(reason)),
+                Message::KeepAlive { id } => self.connection.send(&Message::KeepAlive { id }),
+                Message::ChunkData { location, chunk } => {
+                    if Region::chunk_location(location.map(|v| v as isize)).is_none() {
+                        return Err(ProtocolError::Malformed("chunk outside of the world"));
+                    }
+                    game.world.set_chunk(location, *chunk);
+                },
+                Message::UnloadChunk { location } => {
+                    if Region::chunk_location(location.map(|v| v as isize)).is_none() {
+                        return Err(ProtocolError::Malformed("chunk outside of the world"));
+                    }
+                    game.world.set_chunk(location, Chunk::default());
+                },
+                Message::BlockChange { location, block } => {
+                    if Region::split_location(location).is_none() {
+                        return Err(ProtocolError::Malformed("block outside of the world"));
+                    }
+                    game.world.set_block(location, block);
+                },
+                Message::Hello { .. } | Message::PlayerPosition { .. } => {
+                    return Err(ProtocolError::Malformed(
+                        "unexpected message from the server",
+                    ));
+                },
+            }
+        }
+
+        self.connection.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread, time::Duration};
+
+    use super::*;
+    use crate::model::consts as c;
+
+    /// Poll a client that gets `message` from the server until it fails.
+    fn poll_error(message: Message) -> ProtocolError {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let mut client = NetClient::connect(listener.local_addr().unwrap()).unwrap();
+        let mut server = Connection::new(listener.accept().unwrap().0).unwrap();
+        server.send(&message);
+        server.flush().unwrap();
+
+        let mut game = GameModel::new(Region::default(), 0);
+        for _ in 0..500 {
+            if let Err(e) = client.poll(&mut game) {
+                return e;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        panic!("The client accepted the message");
+    }
+
+    #[test]
+    fn rejects_locations_outside_of_the_world() {
+        let error = poll_error(Message::ChunkData {
+            location: [c::REGION_X_CHUNKS, 0, 0],
+            chunk: Box::default(),
+        });
+        assert!(matches!(error, ProtocolError::Malformed(_)), "{}", error);
+
+        let error = poll_error(Message::BlockChange {
+            location: [0, c::REGION_Y_BLOCKS, 0],
+            block: Block::solid(),
+        });
+        assert!(matches!(error, ProtocolError::Malformed(_)), "{}", error);
+    }
+}
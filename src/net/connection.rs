@@ -0,0 +1,92 @@
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpStream},
+};
+
+use super::{protocol::Message, ProtocolError};
+
+
+/// A non-blocking framed connection to the other side.
+pub struct Connection {
+    stream: TcpStream,
+    address: SocketAddr,
+    read_buf: Vec<u8>,
+    write_buf: Vec<u8>,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        let address = stream.peer_addr()?;
+
+        Ok(Self {
+            stream,
+            address,
+            read_buf: Vec::new(),
+            write_buf: Vec::new(),
+        })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Queue a message to be sent on the next [`Connection::flush`].
+    pub fn send(&mut self, message: &Message) {
+        self.write_buf.extend(message.encode());
+    }
+
+    /// Number of bytes queued but not yet sent.
+    pub fn pending(&self) -> usize {
+        self.write_buf.len()
+    }
+
+    /// Send as much of the queued data as the socket accepts without blocking.
+    pub fn flush(&mut self) -> Result<(), ProtocolError> {
+        while !self.write_buf.is_empty() {
+            match self.stream.write(&self.write_buf) {
+                Ok(0) => return Err(ProtocolError::Disconnected),
+                Ok(n) => {
+                    self.write_buf.drain(..n);
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read everything available without blocking and decode complete
+    /// messages.
+    pub fn receive(&mut self) -> Result<Vec<Message>, ProtocolError> {
+        let mut buf = [0; 4096];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err(ProtocolError::Disconnected),
+                Ok(n) => self.read_buf.extend(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let mut messages = Vec::new();
+        let mut consumed = 0;
+        while let Some((message, len)) = Message::decode(&self.read_buf[consumed..])? {
+            messages.push(message);
+            consumed += len;
+        }
+        self.read_buf.drain(..consumed);
+
+        Ok(messages)
+    }
+
+    /// Tell the other side why we are leaving. Best effort.
+    pub fn disconnect(mut self, reason: &str) {
+        self.send(&Message::Disconnect {
+            reason: reason.into(),
+        });
+        let _ = self.flush();
+    }
+}
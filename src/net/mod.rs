@@ -0,0 +1,54 @@
+use std::{fmt, io, time::Duration};
+
+pub mod client;
+pub mod connection;
+pub mod protocol;
+
+/// Connections with no messages for this long are dropped.
+pub const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often the server checks that a client is still there.
+pub const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(5);
+
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    Io(io::Error),
+    /// The other side closed the connection
+    Disconnected,
+    /// The other side sent something we can't understand
+    Malformed(&'static str),
+    VersionMismatch {
+        ours: u16,
+        theirs: u16,
+    },
+    /// The other side did not send anything for too long
+    TimedOut,
+    /// The other side closed the connection and told us why
+    Kicked(String),
+}
+
+impl From<io::Error> for ProtocolError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {}", e),
+            Self::Disconnected => write!(f, "connection closed"),
+            Self::Malformed(what) => write!(f, "malformed message: {}", what),
+            Self::VersionMismatch { ours, theirs } => write!(
+                f,
+                "protocol version mismatch: ours is {}, theirs is {}",
+                ours, theirs
+            ),
+            Self::TimedOut => write!(f, "timed out"),
+            Self::Kicked(reason) => write!(f, "disconnected: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
@@ -0,0 +1,346 @@
+use cgmath::{Point3, Rad};
+
+use super::ProtocolError;
+use crate::model::{block::Block, chunk::Chunk, storage};
+
+/// Bumped on any incompatible change to the messages below.
+pub const PROTOCOL_VERSION: u16 = 2;
+
+/// Frames larger than this are rejected to avoid allocating garbage sizes.
+pub const MAX_FRAME_BYTES: usize = 1 << 20;
+
+
+/// Everything the client and the server say to each other.
+///
+/// On the wire, every message is a frame: a little-endian `u32` length of the
+/// rest of the frame, a tag byte and the message-specific payload.
+#[derive(Clone)]
+pub enum Message {
+    /// Client -> server, must be the first message.
+    Hello { version: u16 },
+    /// Server -> client, the handshake succeeded.
+    Welcome { time: u64 },
+    /// Either way, the connection is about to be closed.
+    Disconnect { reason: String },
+    /// Either way. The receiver replies with the same id.
+    KeepAlive { id: u64 },
+    /// Client -> server, where the player (camera) is.
+    PlayerPosition {
+        position: Point3<f64>,
+        pitch: Rad<f64>,
+        yaw: Rad<f64>,
+    },
+    /// Server -> client, full contents of a chunk.
+    ChunkData {
+        location: [usize; 3],
+        chunk: Box<Chunk>,
+    },
+    /// Client -> server: the player changed a block.
+    /// Server -> client: a block was changed by someone.
+    BlockChange { location: [usize; 3], block: Block },
+    /// Server -> client, the chunk left the view distance and is no longer
+    /// kept up to date.
+    UnloadChunk { location: [usize; 3] },
+}
+
+impl Message {
+    const TAG_HELLO: u8 = 0;
+    const TAG_WELCOME: u8 = 1;
+    const TAG_DISCONNECT: u8 = 2;
+    const TAG_KEEP_ALIVE: u8 = 3;
+    const TAG_PLAYER_POSITION: u8 = 4;
+    const TAG_CHUNK_DATA: u8 = 5;
+    const TAG_BLOCK_CHANGE: u8 = 6;
+    const TAG_UNLOAD_CHUNK: u8 = 7;
+
+    /// Encode the message into a frame, including the length prefix.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut w = Writer(vec![0; 4]);
+
+        match self {
+            Self::Hello { version } => {
+                w.u8(Self::TAG_HELLO);
+                w.u16(*version);
+            },
+            Self::Welcome { time } => {
+                w.u8(Self::TAG_WELCOME);
+                w.u64(*time);
+            },
+            Self::Disconnect { reason } => {
+                w.u8(Self::TAG_DISCONNECT);
+                w.bytes(reason.as_bytes());
+            },
+            Self::KeepAlive { id } => {
+                w.u8(Self::TAG_KEEP_ALIVE);
+                w.u64(*id);
+            },
+            Self::PlayerPosition {
+                position,
+                pitch,
+                yaw,
+            } => {
+                w.u8(Self::TAG_PLAYER_POSITION);
+                for v in [position.x, position.y, position.z, pitch.0, yaw.0] {
+                    w.f64(v);
+                }
+            },
+            Self::ChunkData { location, chunk } => {
+                w.u8(Self::TAG_CHUNK_DATA);
+                w.location(*location);
+                w.bytes(&storage::encode_chunk(chunk));
+            },
+            Self::BlockChange { location, block } => {
+                w.u8(Self::TAG_BLOCK_CHANGE);
+                w.location(*location);
+                w.bytes(&block.encode());
+            },
+            Self::UnloadChunk { location } => {
+                w.u8(Self::TAG_UNLOAD_CHUNK);
+                w.location(*location);
+            },
+        }
+
+        let mut frame = w.0;
+        let len = (frame.len() - 4) as u32;
+        frame[..4].copy_from_slice(&len.to_le_bytes());
+        frame
+    }
+
+    /// Try to decode one frame from the start of `data`.
+    ///
+    /// Returns the message and the number of bytes consumed, or `None` if more
+    /// data is needed to complete the frame.
+    pub fn decode(data: &[u8]) -> Result<Option<(Self, usize)>, ProtocolError> {
+        let Some(len) = data.get(..4) else {
+            return Ok(None);
+        };
+        let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+        if len > MAX_FRAME_BYTES {
+            return Err(ProtocolError::Malformed("frame is too large"));
+        }
+        let Some(frame) = data.get(4..4 + len) else {
+            return Ok(None);
+        };
+
+        let mut r = Reader(frame);
+        let message = match r.u8()? {
+            Self::TAG_HELLO => Self::Hello { version: r.u16()? },
+            Self::TAG_WELCOME => Self::Welcome { time: r.u64()? },
+            Self::TAG_DISCONNECT => Self::Disconnect {
+                reason: String::from_utf8_lossy(r.rest()).into_owned(),
+            },
+            Self::TAG_KEEP_ALIVE => Self::KeepAlive { id: r.u64()? },
+            Self::TAG_PLAYER_POSITION => Self::PlayerPosition {
+                position: Point3::new(r.f64()?, r.f64()?, r.f64()?),
+                pitch: Rad(r.f64()?),
+                yaw: Rad(r.f64()?),
+            },
+            Self::TAG_CHUNK_DATA => Self::ChunkData {
+                location: r.location()?,
+                chunk: Box::new(
+                    storage::decode_chunk(r.rest())
+                        .ok_or(ProtocolError::Malformed("invalid chunk data"))?,
+                ),
+            },
+            Self::TAG_BLOCK_CHANGE => Self::BlockChange {
+                location: r.location()?,
+                block: Block::decode([r.u8()?, r.u8()?])
+                    .ok_or(ProtocolError::Malformed("invalid block"))?,
+            },
+            Self::TAG_UNLOAD_CHUNK => Self::UnloadChunk {
+                location: r.location()?,
+            },
+            _ => return Err(ProtocolError::Malformed("unknown message tag")),
+        };
+
+        if !r.0.is_empty() {
+            return Err(ProtocolError::Malformed("trailing bytes in a frame"));
+        }
+
+        Ok(Some((message, 4 + len)))
+    }
+}
+
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+
+    fn u16(&mut self, v: u16) {
+        self.0.extend(v.to_le_bytes());
+    }
+
+    fn u64(&mut self, v: u64) {
+        self.0.extend(v.to_le_bytes());
+    }
+
+    fn f64(&mut self, v: f64) {
+        self.0.extend(v.to_le_bytes());
+    }
+
+    fn location(&mut self, loc: [usize; 3]) {
+        for v in loc {
+            self.u16(u16::try_from(v).expect("Location does not fit the protocol"));
+        }
+    }
+
+    fn bytes(&mut self, v: &[u8]) {
+        self.0.extend(v);
+    }
+}
+
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], ProtocolError> {
+        if self.0.len() < N {
+            return Err(ProtocolError::Malformed("frame is too short"));
+        }
+        let (head, tail) = self.0.split_at(N);
+        self.0 = tail;
+        Ok(head.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, ProtocolError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ProtocolError> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, ProtocolError> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    fn f64(&mut self) -> Result<f64, ProtocolError> {
+        Ok(f64::from_le_bytes(self.take()?))
+    }
+
+    fn location(&mut self) -> Result<[usize; 3], ProtocolError> {
+        Ok([
+            self.u16()? as usize,
+            self.u16()? as usize,
+            self.u16()? as usize,
+        ])
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(message: Message) -> Message {
+        let frame = message.encode();
+        let (decoded, consumed) = Message::decode(&frame).unwrap().unwrap();
+        assert_eq!(consumed, frame.len());
+        decoded
+    }
+
+    #[test]
+    fn messages_roundtrip() {
+        assert!(matches!(
+            roundtrip(Message::Hello { version: 7 }),
+            Message::Hello { version: 7 }
+        ));
+        assert!(matches!(
+            roundtrip(Message::Disconnect {
+                reason: "bye".into()
+            }),
+            Message::Disconnect { reason } if reason == "bye"
+        ));
+        assert!(matches!(
+            roundtrip(Message::PlayerPosition {
+                position: Point3::new(1.0, 2.0, 3.0),
+                pitch: Rad(0.5),
+                yaw: Rad(-0.5),
+            }),
+            Message::PlayerPosition { position, pitch: Rad(p), yaw: Rad(y) }
+                if position == Point3::new(1.0, 2.0, 3.0) && p == 0.5 && y == -0.5
+        ));
+        assert!(matches!(
+            roundtrip(Message::BlockChange {
+                location: [300, 2, 1],
+                block: Block::light_source(),
+            }),
+            Message::BlockChange { location: [300, 2, 1], block }
+                if block.kind == Block::light_source().kind
+        ));
+        assert!(matches!(
+            roundtrip(Message::UnloadChunk {
+                location: [3, 2, 1]
+            }),
+            Message::UnloadChunk {
+                location: [3, 2, 1]
+            }
+        ));
+    }
+
+    #[test]
+    fn chunk_is_compressed() {
+        let mut chunk = Chunk::default();
+        chunk.set_block([1isize, 2, 3], Block::solid());
+        let message = Message::ChunkData {
+            location: [1, 2, 3],
+            chunk: Box::new(chunk),
+        };
+
+        let frame = message.encode();
+
+        assert!(frame.len() < Chunk::BYTES / 10);
+        match roundtrip(message) {
+            Message::ChunkData { location, chunk } => {
+                assert_eq!(location, [1, 2, 3]);
+                assert_eq!(chunk.get_block([1isize, 2, 3]).kind, Block::solid().kind);
+            },
+            _ => panic!("wrong message type"),
+        }
+    }
+
+    #[test]
+    fn oversized_chunk_data() {
+        let mut frame = Message::ChunkData {
+            location: [1, 2, 3],
+            chunk: Box::default(),
+        }
+        .encode();
+
+        // Runs of zeros adding up to more than a chunk, in a small frame
+        frame.truncate(4 + 1 + 6 + 1);
+        for _ in 0..Chunk::BYTES / 255 + 1 {
+            frame.extend([255, 0]);
+        }
+        let len = (frame.len() - 4) as u32;
+        frame[..4].copy_from_slice(&len.to_le_bytes());
+
+        assert!(frame.len() < MAX_FRAME_BYTES);
+        assert!(Message::decode(&frame).is_err());
+    }
+
+    #[test]
+    fn partial_frames() {
+        let frame = Message::KeepAlive { id: 42 }.encode();
+
+        for end in 0..frame.len() {
+            assert!(Message::decode(&frame[..end]).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn malformed_frames() {
+        let mut frame = Message::KeepAlive { id: 42 }.encode();
+        frame[4] = 0xFF;
+        assert!(Message::decode(&frame).is_err());
+
+        let huge = (MAX_FRAME_BYTES as u32 + 1).to_le_bytes();
+        assert!(Message::decode(&huge).is_err());
+    }
+}
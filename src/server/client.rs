@@ -0,0 +1,164 @@
+use std::{collections::HashSet, time::Instant};
+
+use crate::{
+    model::{region::Region, Camera},
+    net::{
+        connection::Connection,
+        protocol::{Message, PROTOCOL_VERSION},
+        ProtocolError,
+        KEEP_ALIVE_INTERVAL,
+        TIMEOUT,
+    },
+};
+
+/// Stop streaming chunks to a client that has this much data queued.
+const MAX_PENDING_BYTES: usize = 1 << 18;
+
+
+/// Server side of a connection to a player.
+pub(super) struct RemoteClient {
+    pub connection: Connection,
+    welcomed: bool,
+    /// Chunk the player's camera is in, once known
+    center: Option<[isize; 3]>,
+    /// Chunks the client has received and keeps up to date
+    sent_chunks: HashSet<[usize; 3]>,
+    last_received: Instant,
+    last_keep_alive: Instant,
+    keep_alive_id: u64,
+}
+
+impl RemoteClient {
+    pub fn new(connection: Connection) -> Self {
+        Self {
+            connection,
+            welcomed: false,
+            center: None,
+            sent_chunks: HashSet::new(),
+            last_received: Instant::now(),
+            last_keep_alive: Instant::now(),
+            keep_alive_id: 0,
+        }
+    }
+
+    /// Whether the client knows about the contents of a chunk.
+    pub fn has_chunk(&self, loc: [usize; 3]) -> bool {
+        self.sent_chunks.contains(&loc)
+    }
+
+    /// Process incoming messages. Block changes requested by the player are
+    /// returned so that the server can apply and broadcast them.
+    pub fn receive(&mut self, time: u64) -> Result<Vec<Message>, ProtocolError> {
+        let messages = self.connection.receive()?;
+
+        if !messages.is_empty() {
+            self.last_received = Instant::now();
+        } else if self.last_received.elapsed() > TIMEOUT {
+            return Err(ProtocolError::TimedOut);
+        }
+
+        let mut changes = Vec::new();
+
+        for message in messages {
+            match message {
+                Message::Hello { version } if !self.welcomed => {
+                    if version != PROTOCOL_VERSION {
+                        return Err(ProtocolError::VersionMismatch {
+                            ours: PROTOCOL_VERSION,
+                            theirs: version,
+                        });
+                    }
+                    self.welcomed = true;
+                    self.connection.send(&Message::Welcome { time });
+                },
+                _ if !self.welcomed => {
+                    return Err(ProtocolError::Malformed("expected a handshake"));
+                },
+                Message::KeepAlive { .. } => (),
+                Message::PlayerPosition {
+                    position,
+                    pitch,
+                    yaw,
+                } => {
+                    let camera = Camera {
+                        position,
+                        pitch,
+                        yaw,
+                    };
+                    self.center = Some(camera.get_chunk_location());
+                },
+                Message::BlockChange { .. } => changes.push(message),
+                Message::Disconnect { reason } => return Err(ProtocolError::Kicked(reason)),
+                Message::Hello { .. }
+                | Message::Welcome { .. }
+                | Message::ChunkData { .. }
+                | Message::UnloadChunk { .. } => {
+                    return Err(ProtocolError::Malformed(
+                        "unexpected message from the client",
+                    ));
+                },
+            }
+        }
+
+        Ok(changes)
+    }
+
+    /// Send chunks around the player's camera that the client does not have
+    /// yet, nearest first, without flooding the connection. Chunks that left
+    /// the view distance are unloaded on the client, and sent again if they
+    /// come back.
+    pub fn stream_chunks(&mut self, world: &Region, view_distance: usize, max_chunks: usize) {
+        let Some(center) = self.center else {
+            return;
+        };
+
+        let r = view_distance as isize;
+        let connection = &mut self.connection;
+        self.sent_chunks.retain(|&location| {
+            let near = (0..3).all(|a| (location[a] as isize - center[a]).abs() <= r);
+            if !near {
+                connection.send(&Message::UnloadChunk { location });
+            }
+            near
+        });
+
+        let mut missing = Vec::new();
+        for dx in -r..=r {
+            for dy in -r..=r {
+                for dz in -r..=r {
+                    let loc = [center[0] + dx, center[1] + dy, center[2] + dz];
+                    let Some(loc) = Region::chunk_location(loc) else {
+                        continue;
+                    };
+                    if !self.sent_chunks.contains(&loc) {
+                        missing.push((dx * dx + dy * dy + dz * dz, loc));
+                    }
+                }
+            }
+        }
+        missing.sort_unstable();
+
+        for (_, location) in missing.into_iter().take(max_chunks) {
+            if self.connection.pending() > MAX_PENDING_BYTES {
+                break;
+            }
+            self.connection.send(&Message::ChunkData {
+                location,
+                chunk: Box::new(world.get_chunk(location).clone()),
+            });
+            self.sent_chunks.insert(location);
+        }
+    }
+
+    /// Ping the client every now and then so that both sides notice dead
+    /// connections.
+    pub fn keep_alive(&mut self) {
+        if self.welcomed && self.last_keep_alive.elapsed() > KEEP_ALIVE_INTERVAL {
+            self.keep_alive_id += 1;
+            self.connection.send(&Message::KeepAlive {
+                id: self.keep_alive_id,
+            });
+            self.last_keep_alive = Instant::now();
+        }
+    }
+}
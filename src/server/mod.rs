@@ -0,0 +1,521 @@
+use std::{
+    io::{self, ErrorKind},
+    net::{SocketAddr, TcpListener},
+    path::PathBuf,
+    sync::mpsc::{Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use tracing::{info, instrument, warn};
+
+use self::client::RemoteClient;
+use crate::{
+    model::{region::Region, storage::WorldStorage, GameModel},
+    net::{connection::Connection, protocol::Message},
+};
+
+mod client;
+
+
+pub struct ServerConfig {
+    /// Directory the world is loaded from and saved to
+    pub world: PathBuf,
+    pub address: SocketAddr,
+    /// Simulation ticks per second, at least 1
+    pub tick_rate: u32,
+    /// Number of ticks between automatic saves
+    pub autosave_interval: u64,
+    /// Chunks within this many chunks from a player are sent to them
+    pub view_distance: usize,
+    /// Maximum number of chunks sent to a player each tick
+    pub chunks_per_tick: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            world: "world/".into(),
+            address: ([0, 0, 0, 0], 25800).into(),
+            tick_rate: 20,
+            autosave_interval: 20 * 60 * 5,
+            view_distance: 4,
+            chunks_per_tick: 8,
+        }
+    }
+}
+
+
+/// Commands that can be sent to a running server, e.g. from the console.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ServerCommand {
+    Save,
+    Stop,
+}
+
+
+/// A dedicated server: simulates the world with no graphics attached.
+pub struct Server {
+    config: ServerConfig,
+    storage: WorldStorage,
+    game: GameModel,
+    listener: TcpListener,
+    clients: Vec<RemoteClient>,
+    ticks_since_save: u64,
+}
+
+impl Server {
+    #[instrument(skip_all)]
+    pub fn new(config: ServerConfig) -> io::Result<Self> {
+        if config.tick_rate == 0 {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "the tick rate must be at least 1",
+            ));
+        }
+
+        let storage = WorldStorage::new(config.world.clone());
+
+        let game = match storage.load_region()? {
+            Some((world, time)) => {
+                info!("Loaded world from {}", config.world.display());
+                GameModel::new(world, time)
+            },
+            None => {
+                info!("Creating a new world in {}", config.world.display());
+                GameModel::default()
+            },
+        };
+
+        let listener = TcpListener::bind(config.address)?;
+        listener.set_nonblocking(true)?;
+        info!("Listening on {}", listener.local_addr()?);
+
+        Ok(Self {
+            config,
+            storage,
+            game,
+            listener,
+            clients: Vec::new(),
+            ticks_since_save: 0,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn game(&self) -> &GameModel {
+        &self.game
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    fn accept_clients(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, address)) => match Connection::new(stream) {
+                    Ok(connection) => {
+                        info!("Client connected: {}", address);
+                        self.clients.push(RemoteClient::new(connection));
+                    },
+                    Err(e) => warn!("Rejecting {}: {}", address, e),
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("Failed to accept a connection: {}", e);
+                    break;
+                },
+            }
+        }
+    }
+
+    fn poll_clients(&mut self) {
+        let mut changes = Vec::new();
+
+        let clients = std::mem::take(&mut self.clients);
+        for mut client in clients {
+            match client.receive(self.game.time) {
+                Ok(c) => {
+                    changes.extend(c);
+                    self.clients.push(client);
+                },
+                Err(e) => {
+                    info!(
+                        "Client disconnected: {}: {}",
+                        client.connection.address(),
+                        e
+                    );
+                    client.connection.disconnect(&e.to_string());
+                },
+            }
+        }
+
+        for change in changes {
+            if let Message::BlockChange { location, block } = change {
+                if self.game.set_block(location, block) {
+                    self.broadcast_block_change(location, &change);
+                }
+            }
+        }
+    }
+
+    /// Notify everyone who has the chunk with the changed block.
+    fn broadcast_block_change(&mut self, location: [usize; 3], change: &Message) {
+        let Some((chunk, _)) = Region::split_location(location) else {
+            return;
+        };
+
+        for client in &mut self.clients {
+            if client.has_chunk(chunk) {
+                client.connection.send(change);
+            }
+        }
+    }
+
+    fn update_clients(&mut self) {
+        let Self {
+            config,
+            game,
+            clients,
+            ..
+        } = self;
+
+        clients.retain_mut(|client| {
+            client.stream_chunks(&game.world, config.view_distance, config.chunks_per_tick);
+            client.keep_alive();
+
+            match client.connection.flush() {
+                Ok(()) => true,
+                Err(e) => {
+                    info!(
+                        "Client disconnected: {}: {}",
+                        client.connection.address(),
+                        e
+                    );
+                    false
+                },
+            }
+        });
+    }
+
+    /// Run a single simulation tick.
+    pub fn tick(&mut self) {
+        self.accept_clients();
+        self.poll_clients();
+
+        self.game.tick();
+        for location in std::mem::take(&mut self.game.changed_blocks) {
+            let block = *self.game.world.get_block(location).unwrap();
+            self.broadcast_block_change(location, &Message::BlockChange { location, block });
+        }
+
+        self.update_clients();
+
+        self.ticks_since_save += 1;
+        if self.ticks_since_save >= self.config.autosave_interval {
+            if let Err(e) = self.save() {
+                warn!("Autosave failed: {}", e);
+            }
+        }
+    }
+
+    #[instrument(skip_all)]
+    pub fn save(&mut self) -> io::Result<()> {
+        self.ticks_since_save = 0;
+        self.storage.save_region(&self.game.world, self.game.time)?;
+        info!("Saved the world at tick {}", self.game.time);
+        Ok(())
+    }
+
+    /// Tick at the configured rate until a [`ServerCommand::Stop`] arrives.
+    /// Closing the command channel does not stop the server, so it keeps
+    /// running without a console. The world is saved before returning.
+    pub fn run(&mut self, commands: Receiver<ServerCommand>) -> io::Result<()> {
+        let tick_duration = Duration::from_secs(1) / self.config.tick_rate;
+        let mut next_tick = Instant::now();
+
+        loop {
+            match commands.try_recv() {
+                Ok(ServerCommand::Save) => {
+                    if let Err(e) = self.save() {
+                        warn!("Save failed: {}", e);
+                    }
+                },
+                Ok(ServerCommand::Stop) => break,
+                Err(TryRecvError::Empty | TryRecvError::Disconnected) => (),
+            }
+
+            self.tick();
+
+            next_tick += tick_duration;
+            let now = Instant::now();
+            if next_tick > now {
+                thread::sleep(next_tick - now);
+            } else {
+                // Don't try to catch up, just start counting from now
+                warn!(
+                    "Server is overloaded, tick took {:?}",
+                    now - next_tick + tick_duration
+                );
+                next_tick = now;
+            }
+        }
+
+        self.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, net::TcpStream, sync::mpsc};
+
+    use super::*;
+    use crate::{
+        model::{block::Block, chunk::Chunk, Camera},
+        net::{client::NetClient, protocol::PROTOCOL_VERSION, ProtocolError},
+    };
+
+    fn temp_config(name: &str) -> ServerConfig {
+        let world = env::temp_dir().join(format!("tekutonu-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&world);
+
+        ServerConfig {
+            world,
+            address: ([127, 0, 0, 1], 0).into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn world_persists() {
+        let mut server = Server::new(temp_config("persist")).unwrap();
+        let mut chunk = Chunk::default();
+        chunk.set_block([1isize, 1, 1], Block::solid());
+        server.game.world.set_chunk([2, 3, 4], chunk);
+        server.tick();
+        server.save().unwrap();
+
+        let config = ServerConfig {
+            world: server.config.world.clone(),
+            address: ([127, 0, 0, 1], 0).into(),
+            ..Default::default()
+        };
+        let reloaded = Server::new(config).unwrap();
+
+        assert_eq!(reloaded.game().time, 1);
+        assert_eq!(
+            reloaded
+                .game()
+                .world
+                .get_chunk([2, 3, 4])
+                .get_block([1isize, 1, 1])
+                .kind,
+            Block::solid().kind
+        );
+
+        std::fs::remove_dir_all(&server.config.world).unwrap();
+    }
+
+    #[test]
+    fn rejects_zero_tick_rate() {
+        let config = ServerConfig {
+            tick_rate: 0,
+            ..temp_config("tick-rate")
+        };
+
+        let error = Server::new(config).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn accepts_clients() {
+        let mut server = Server::new(temp_config("accept")).unwrap();
+        let stream = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+
+        // Connections are picked up on the next tick
+        for _ in 0..100 {
+            server.tick();
+            if server.client_count() == 1 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(server.client_count(), 1);
+
+        drop(stream);
+        for _ in 0..100 {
+            server.tick();
+            if server.client_count() == 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(server.client_count(), 0);
+    }
+
+    #[test]
+    fn stops_on_command() {
+        let mut server = Server::new(temp_config("stop")).unwrap();
+        let (commands, commands_rx) = mpsc::channel();
+        commands.send(ServerCommand::Stop).unwrap();
+
+        server.run(commands_rx).unwrap();
+
+        assert!(server.config.world.join("time").exists());
+        std::fs::remove_dir_all(&server.config.world).unwrap();
+    }
+
+    /// Tick the server and poll the clients until `done` or a timeout.
+    fn exchange(
+        server: &mut Server,
+        clients: &mut [(&mut NetClient, &mut GameModel)],
+        done: impl Fn(&[(&mut NetClient, &mut GameModel)]) -> bool,
+    ) {
+        for _ in 0..500 {
+            server.tick();
+            for (client, game) in clients.iter_mut() {
+                client.send_position(&game.camera);
+                client.poll(game).unwrap();
+            }
+            if done(clients) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        panic!("Timed out waiting for the server");
+    }
+
+    #[test]
+    fn streams_chunks_and_block_changes() {
+        let mut server = Server::new(ServerConfig {
+            view_distance: 1,
+            ..temp_config("stream")
+        })
+        .unwrap();
+        let address = server.local_addr().unwrap();
+
+        let mut alice = NetClient::connect(address).unwrap();
+        let mut alice_game = GameModel::new(Region::default(), 0);
+        let mut bob = NetClient::connect(address).unwrap();
+        let mut bob_game = GameModel::new(Region::default(), 0);
+
+        // Both cameras are in the demo chunk [1, 1, 1]
+        exchange(
+            &mut server,
+            &mut [(&mut alice, &mut alice_game), (&mut bob, &mut bob_game)],
+            |clients| {
+                clients.iter().all(|(client, game)| {
+                    client.is_welcomed()
+                        && game
+                            .world
+                            .get_chunk([1, 1, 1])
+                            .get_block([0isize, 0, 0])
+                            .kind
+                            == Block::solid().kind
+                })
+            },
+        );
+
+        // Chunks outside of the view distance are not sent
+        assert!(alice_game.world.get_chunk([2, 2, 2]).is_empty());
+        assert!(server.game().world.get_chunk([2, 2, 2]).is_empty());
+
+        alice.send_block_change([20, 20, 20], Block::light_source());
+        exchange(
+            &mut server,
+            &mut [(&mut alice, &mut alice_game), (&mut bob, &mut bob_game)],
+            |clients| {
+                clients.iter().all(|(_, game)| {
+                    game.world.get_block([20, 20, 20]).unwrap().kind == Block::light_source().kind
+                })
+            },
+        );
+        assert_eq!(
+            server.game().world.get_block([20, 20, 20]).unwrap().kind,
+            Block::light_source().kind
+        );
+    }
+
+    #[test]
+    fn broadcasts_flowing_fluids() {
+        let mut server = Server::new(ServerConfig {
+            view_distance: 1,
+            ..temp_config("fluids")
+        })
+        .unwrap();
+        let mut client = NetClient::connect(server.local_addr().unwrap()).unwrap();
+        let mut game = GameModel::new(Region::default(), 0);
+
+        client.send_block_change([20, 20, 20], Block::water());
+        // The water falls down on the server and the client sees it
+        exchange(&mut server, &mut [(&mut client, &mut game)], |clients| {
+            let block = clients[0].1.world.get_block([20, 19, 20]).unwrap();
+            block.kind.fluid().is_some()
+        });
+    }
+
+    #[test]
+    fn streams_around_camera() {
+        let mut server = Server::new(ServerConfig {
+            view_distance: 0,
+            ..temp_config("camera")
+        })
+        .unwrap();
+
+        let mut client = NetClient::connect(server.local_addr().unwrap()).unwrap();
+        let mut game = GameModel::new(Region::default(), 0);
+        game.camera = Camera {
+            // At block (40, 40, 40), in chunk [2, 2, 2] like block (33, 34, 35)
+            position: cgmath::Point3::new(20.0, 20.0, 20.0),
+            ..Default::default()
+        };
+        server.game.world.set_block([33, 34, 35], Block::solid());
+
+        // Only the chunk with the camera is sent
+        let has_block = |clients: &[(&mut NetClient, &mut GameModel)]| {
+            clients[0].1.world.get_block([33, 34, 35]).unwrap().kind == Block::solid().kind
+        };
+        exchange(&mut server, &mut [(&mut client, &mut game)], has_block);
+        assert!(game.world.get_chunk([1, 1, 1]).is_empty());
+
+        // Moving to chunk [3, 2, 2] unloads it on the client
+        game.camera.position.x = 28.0;
+        exchange(&mut server, &mut [(&mut client, &mut game)], |clients| {
+            !has_block(clients)
+        });
+        assert!(!server.clients[0].has_chunk([2, 2, 2]));
+
+        // And coming back sends it again
+        game.camera.position.x = 20.0;
+        exchange(&mut server, &mut [(&mut client, &mut game)], has_block);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut server = Server::new(temp_config("version")).unwrap();
+        let mut connection =
+            Connection::new(TcpStream::connect(server.local_addr().unwrap()).unwrap()).unwrap();
+        connection.send(&Message::Hello {
+            version: PROTOCOL_VERSION + 1,
+        });
+        connection.flush().unwrap();
+
+        for _ in 0..500 {
+            server.tick();
+            match connection.receive() {
+                Ok(messages) if messages.is_empty() => (),
+                Ok(messages) => {
+                    assert!(matches!(messages[0], Message::Disconnect { .. }));
+                    return;
+                },
+                Err(ProtocolError::Disconnected) => return,
+                Err(e) => panic!("Unexpected error: {}", e),
+            }
+            thread::sleep(Duration::from_millis(2));
+        }
+        panic!("Server did not reject the client");
+    }
+}
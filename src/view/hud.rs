@@ -0,0 +1,149 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use super::{
+    renderer::{Overlay, OverlayQuad, RenderStats, Text},
+    text::Font,
+};
+use crate::model::{region::Region, GameModel};
+
+/// Frames the frame time is averaged over.
+const FRAMES_AVERAGED: usize = 60;
+/// Size of a pixel of the font, in screen pixels.
+const SCALE: f32 = 3.0;
+/// Space between the text and the edges of its background.
+const PADDING: f32 = 2.0 * SCALE;
+
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const BACKGROUND_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.5];
+const CROSSHAIR_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.8];
+
+/// Durations of the last frames.
+#[derive(Default)]
+pub struct FrameTimes {
+    last: Option<Instant>,
+    times: VecDeque<Duration>,
+}
+
+impl FrameTimes {
+    /// Note that a frame is being drawn.
+    pub fn frame(&mut self) {
+        let now = Instant::now();
+        if let Some(last) = self.last.replace(now) {
+            if self.times.len() == FRAMES_AVERAGED {
+                self.times.pop_front();
+            }
+            self.times.push_back(now - last);
+        }
+    }
+
+    /// Average duration of the last frames.
+    pub fn average(&self) -> Duration {
+        if self.times.is_empty() {
+            return Duration::ZERO;
+        }
+        self.times.iter().sum::<Duration>() / self.times.len() as u32
+    }
+}
+
+/// What the debug overlay shows: the camera like its `Debug` output, frame
+/// times and the block the camera looks at.
+pub fn debug_lines(game: &GameModel, frames: &FrameTimes, stats: RenderStats) -> Vec<String> {
+    let camera = &game.camera;
+    let p = camera.position;
+    let l = camera.get_look();
+    let [cx, cy, cz] = camera.get_chunk_location();
+
+    let frame = frames.average();
+    let fps = if frame.is_zero() {
+        0.0
+    } else {
+        1.0 / frame.as_secs_f64()
+    };
+    let loaded = match &game.chunks {
+        Some(chunks) => chunks.loaded_count().to_string(),
+        None => "-".to_string(),
+    };
+
+    let mut lines = vec![
+        format!("position: {:.2} {:.2} {:.2}", p.x, p.y, p.z),
+        format!("look: {:.2} {:.2} {:.2}", l.x, l.y, l.z),
+        format!(
+            "yaw: {:.3} rad  pitch: {:.3} rad",
+            camera.yaw.0, camera.pitch.0
+        ),
+        format!("chunk: {} {} {}", cx, cy, cz),
+        format!(
+            "frame: {:.1} ms  fps: {:.0}",
+            frame.as_secs_f64() * 1000.0,
+            fps
+        ),
+        format!(
+            "chunks: {} loaded  {} meshed  {} drawn",
+            loaded, stats.chunks, stats.drawn
+        ),
+    ];
+
+    match game.target() {
+        Some(hit) => {
+            let [x, y, z] = hit.location;
+            let block = game.world.get_block(hit.location).unwrap();
+            lines.push(format!("target: {:?} at {} {} {}", block.kind, x, y, z));
+
+            // The light of the face looked at
+            lines.push(match hit.before {
+                Some(before) => {
+                    let (chunk, local) = Region::split_location(before).unwrap();
+                    let chunk = game.world.get_chunk(chunk);
+                    format!(
+                        "light: sky {}  block {}",
+                        chunk.get_light_sky(local),
+                        chunk.get_light_local(local)
+                    )
+                },
+                None => "light: -".to_string(),
+            });
+        },
+        None => lines.push("target: none".to_string()),
+    }
+
+    lines
+}
+
+/// The debug overlay on a screen of `extent` pixels: `lines` in `font` over
+/// a dark background in the top left corner, and a crosshair in the middle.
+pub fn debug_overlay(lines: &[String], extent: [u32; 2], font: &Font) -> Overlay {
+    let mut overlay = Overlay::default();
+
+    let text = lines.join("\n");
+    let size = font.layout(&text, None).size;
+    overlay.quads.push(OverlayQuad {
+        position: [0.0, 0.0],
+        size: size.map(|v| v as f32 * SCALE + 2.0 * PADDING),
+        color: BACKGROUND_COLOR,
+    });
+    overlay.text.push(Text {
+        position: [PADDING, PADDING],
+        text,
+        scale: SCALE,
+        color: TEXT_COLOR,
+        wrap: None,
+    });
+
+    let [cx, cy] = extent.map(|v| (v / 2) as f32);
+    let (long, thick) = (8.0 * SCALE, SCALE);
+    overlay.quads.push(OverlayQuad {
+        position: [cx - long / 2.0, cy - thick / 2.0],
+        size: [long, thick],
+        color: CROSSHAIR_COLOR,
+    });
+    overlay.quads.push(OverlayQuad {
+        position: [cx - thick / 2.0, cy - long / 2.0],
+        size: [thick, long],
+        color: CROSSHAIR_COLOR,
+    });
+
+    overlay
+}
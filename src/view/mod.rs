@@ -1,59 +1,129 @@
-use std::time::Instant;
+use std::{
+    path::{Path, PathBuf},
+    time::{Instant, SystemTime},
+};
 
-use renderer::Renderer;
+use renderer::{Overlay, Renderer, Screenshot};
 use winit::{
     event::{DeviceEvent, Event, KeyboardInput, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
 };
 
-use self::texture::TextureLoader;
-use crate::{controller::GameInput, model::GameModel};
+use self::{hud::FrameTimes, texture::TextureLoader};
+use crate::{
+    controller::GameInput,
+    model::{
+        block,
+        lod::LodConfig,
+        mesh::{self, MeshConfig},
+        settings::Settings,
+        workers::ChunkWorkers,
+        GameModel,
+    },
+    net::client::NetClient,
+};
 
+pub mod hud;
 pub mod renderer;
+pub mod text;
 pub mod texture;
 
+/// Name of the block texture array in the renderer's texture cache
+const BLOCK_TEXTURES: &str = "blocks";
+
 pub struct GameView {
     renderer: Renderer,
     loader_tex: TextureLoader,
+    /// Where changed settings are saved, if anywhere
+    settings_path: Option<PathBuf>,
 
     event_loop: EventLoop<()>,
 }
 
 impl GameView {
-    pub fn new(renderer: Renderer, loader_tex: TextureLoader, event_loop: EventLoop<()>) -> Self {
+    pub fn new(
+        renderer: Renderer,
+        loader_tex: TextureLoader,
+        settings_path: Option<PathBuf>,
+        event_loop: EventLoop<()>,
+    ) -> Self {
         Self {
             renderer,
             loader_tex,
+            settings_path,
             event_loop,
         }
     }
 
-    pub fn run(self, mut game: GameModel, mut input: GameInput) {
+    /// Run the game loop. With `net`, the world is kept in sync with a server.
+    pub fn run(self, mut game: GameModel, mut input: GameInput, mut net: Option<NetClient>) {
         let Self {
             mut renderer,
             loader_tex,
+            settings_path,
             event_loop,
         } = self;
 
+        renderer.apply_settings(&game.settings);
+        input.apply_settings(&game.settings);
+
         renderer.set_cursor_hidden(true);
         renderer.set_cursor_locked(true).unwrap();
 
-        let texture = loader_tex.load("tex.png");
+        renderer.set_texture(
+            BLOCK_TEXTURES,
+            &loader_tex.load_array("blocks", &block::TEXTURES),
+        );
+        let (font, atlas) = loader_tex.load_font("hud");
+        renderer.set_font(font, &atlas);
+
+        // Leave one core for the event loop
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get() - 1);
+        let mut workers = ChunkWorkers::new(threads, MeshConfig::default());
+        let lods = LodConfig::default();
 
         let mut last_tick = Instant::now();
+        let mut frames = FrameTimes::default();
 
         event_loop.run(move |event, _, control_flow| {
             if last_tick.elapsed().as_millis() > 16 {
                 if let Some(effect) = input.tick() {
                     game.apply_effect(effect);
                 }
+                if net.is_some() {
+                    game.follow();
+                } else {
+                    game.tick();
+                }
+
+                let camera = game.camera.get_chunk_location();
+                workers.submit_changed_with(&game.world, |loc| lods.select(camera, loc));
+                for mesh in workers.collect(&mut game.world) {
+                    renderer.set_chunk_mesh(mesh);
+                }
+                renderer.set_entity_mesh(&mesh::falling(game.falling.iter(), &game.world));
+                if let Some(client) = &mut net {
+                    client.send_position(&game.camera);
+                    if let Err(e) = client.poll(&mut game) {
+                        println!("Lost connection to the server: {}", e);
+                        control_flow.set_exit();
+                    }
+                }
                 last_tick = Instant::now();
             }
 
+            if let Some(settings) = game.take_changed_settings() {
+                renderer.apply_settings(settings);
+                input.apply_settings(settings);
+                if let Some(path) = &settings_path {
+                    save_settings(settings, path);
+                }
+            }
+
             match event {
                 Event::WindowEvent { event, .. } => match event {
                     WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-                    WindowEvent::Resized(_) => renderer.schedule_recreate_swapchain(),
+                    WindowEvent::Resized(size) => renderer.resize(size),
                     WindowEvent::KeyboardInput {
                         input:
                             KeyboardInput {
@@ -77,11 +147,82 @@ impl GameView {
                     game.apply_effect(effect);
                 },
                 Event::RedrawEventsCleared => {
-                    let data = renderer.make_draw_data(&game);
-                    renderer.draw(&data, &texture);
+                    frames.frame();
+                    let overlay = if input.shows_hud() {
+                        let lines = hud::debug_lines(&game, &frames, renderer.stats());
+                        hud::debug_overlay(&lines, renderer.extent(), renderer.font().unwrap())
+                    } else {
+                        Overlay::default()
+                    };
+
+                    let data = renderer.make_draw_data(&game, &overlay);
+                    if input.take_screenshot() {
+                        save_screenshot(&renderer.capture(&data, BLOCK_TEXTURES));
+                    }
+                    renderer.draw(&data, BLOCK_TEXTURES);
+                },
+                Event::LoopDestroyed => {
+                    if let Some(chunks) = &game.chunks {
+                        if let Err(e) = chunks.save_all(&game.world) {
+                            println!("Failed to save the world: {}", e);
+                        }
+                    }
                 },
                 _ => (),
             }
         });
     }
 }
+
+/// Draw `game` once, offscreen, after meshing all of its chunks, with the
+/// debug overlay if `hud`. The same world always gives the same image, so it
+/// can be compared against a reference.
+pub fn capture_world(
+    renderer: &mut Renderer,
+    loader_tex: &TextureLoader,
+    game: &mut GameModel,
+    hud: bool,
+) -> Screenshot {
+    renderer.set_texture(
+        BLOCK_TEXTURES,
+        &loader_tex.load_array("blocks", &block::TEXTURES),
+    );
+
+    let mut workers = ChunkWorkers::new(1, MeshConfig::default());
+    workers.submit_changed(&game.world);
+    for mesh in workers.collect_all(&mut game.world) {
+        renderer.set_chunk_mesh(mesh);
+    }
+    renderer.set_entity_mesh(&mesh::falling(game.falling.iter(), &game.world));
+
+    let overlay = if hud {
+        let (font, atlas) = loader_tex.load_font("hud");
+        renderer.set_font(font, &atlas);
+        let lines = hud::debug_lines(game, &FrameTimes::default(), renderer.stats());
+        hud::debug_overlay(&lines, renderer.extent(), renderer.font().unwrap())
+    } else {
+        Overlay::default()
+    };
+
+    let data = renderer.make_draw_data(game, &overlay);
+    renderer.capture(&data, BLOCK_TEXTURES)
+}
+
+fn save_settings(settings: &Settings, path: &Path) {
+    if let Err(e) = settings.save(path) {
+        println!("Failed to save the settings to {}: {}", path.display(), e);
+    }
+}
+
+/// Save to `screenshot-<seconds since epoch>.png` in the working directory.
+fn save_screenshot(screenshot: &Screenshot) {
+    let seconds = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let path = format!("screenshot-{}.png", seconds);
+
+    match screenshot.save(&path) {
+        Ok(()) => println!("Saved a screenshot to {}", path),
+        Err(e) => println!("Failed to save a screenshot: {}", e),
+    }
+}
@@ -24,13 +24,54 @@ impl From<Point3<f32>> for Vertex {
 #[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
 pub struct Light {
     pub v_light: f32,
+    pub v_occlusion: f32,
 }
-impl_vertex!(Light, v_light);
+impl_vertex!(Light, v_light, v_occlusion);
 
-impl From<u8> for Light {
-    fn from(light: u8) -> Self {
+impl From<(u8, u8)> for Light {
+    /// From light level and ambient occlusion
+    fn from((light, occlusion): (u8, u8)) -> Self {
         Self {
             v_light: (light as f32) / 15.,
+            v_occlusion: 0.4 + 0.2 * (occlusion as f32),
         }
     }
 }
+
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct TexCoord {
+    pub v_tex_coords: [f32; 2],
+    pub v_tex_layer: u32,
+}
+impl_vertex!(TexCoord, v_tex_coords, v_tex_layer);
+
+impl From<([f32; 2], u32)> for TexCoord {
+    /// From texture coordinates and texture array layer
+    fn from((v_tex_coords, v_tex_layer): ([f32; 2], u32)) -> Self {
+        Self {
+            v_tex_coords,
+            v_tex_layer,
+        }
+    }
+}
+
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct OverlayVertex {
+    pub o_position: [f32; 2],
+    pub o_color: [f32; 4],
+}
+impl_vertex!(OverlayVertex, o_position, o_color);
+
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+pub struct TextVertex {
+    pub t_position: [f32; 2],
+    pub t_tex_coords: [f32; 2],
+    pub t_color: [f32; 4],
+}
+impl_vertex!(TextVertex, t_position, t_tex_coords, t_color);
@@ -14,13 +14,15 @@ use vulkano::{
 };
 
 
+/// Pick a device and a graphics queue. Without a `surface` nothing is
+/// presented, so no swapchain support is needed.
 pub fn choose_device_and_queue(
     instance: Arc<Instance>,
-    surface: Arc<Surface>,
+    surface: Option<&Surface>,
 ) -> (Arc<Device>, Vec<Arc<Queue>>) {
     // TODO: check out what other extensions are there
     let device_extensions = DeviceExtensions {
-        khr_swapchain: true,
+        khr_swapchain: surface.is_some(),
         ..DeviceExtensions::empty()
     };
 
@@ -37,7 +39,9 @@ pub fn choose_device_and_queue(
                     // Graphics operations
                     queue.queue_flags.graphics
                     // Supports presenting to out surface
-                    && pd.surface_support(i as u32, &surface).unwrap_or(false)
+                    && surface.is_none_or(|surface| {
+                        pd.surface_support(i as u32, surface).unwrap_or(false)
+                    })
                 })
                 .map(|i| (pd, i as u32))
         })
@@ -2,14 +2,14 @@ use std::sync::Arc;
 
 use vulkano::{
     format::Format,
-    image::{view::ImageView, AttachmentImage, ImageAccess, SwapchainImage},
+    image::{view::ImageView, AttachmentImage, ImageAccess},
     memory::allocator::StandardMemoryAllocator,
     pipeline::graphics::viewport::Viewport,
     render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass},
 };
 
-pub fn make_framebuffers(
-    images: &[Arc<SwapchainImage>],
+pub fn make_framebuffers<I: ImageAccess + 'static>(
+    images: &[Arc<I>],
     render_pass: Arc<RenderPass>,
     viewport: &mut Viewport,
     allocator: Arc<StandardMemoryAllocator>,
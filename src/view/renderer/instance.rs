@@ -21,3 +21,21 @@ pub fn make_instance() -> Arc<Instance> {
     )
     .unwrap()
 }
+
+/// An instance without the extensions for presenting to windows, for
+/// rendering offscreen where there is no display.
+#[instrument]
+pub fn make_headless_instance() -> Arc<Instance> {
+    use vulkano::{instance::InstanceCreateInfo, VulkanLibrary};
+
+    let library = VulkanLibrary::new().unwrap();
+
+    Instance::new(
+        library,
+        InstanceCreateInfo {
+            enumerate_portability: true,
+            ..Default::default()
+        },
+    )
+    .unwrap()
+}
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use vulkano::{
+    buffer::{BufferUsage, DeviceLocalBuffer},
+    memory::allocator::StandardMemoryAllocator,
+};
+
+use super::{
+    data::{Light, TexCoord, Vertex},
+    Acbb,
+};
+use crate::model::{lod::Lod, mesh::RenderData, workers::ChunkMesh};
+
+
+/// Vertex and index buffers of geometry in device-local memory.
+#[derive(Clone)]
+pub struct GpuGeometry {
+    pub vertices: Arc<DeviceLocalBuffer<[Vertex]>>,
+    pub lights: Arc<DeviceLocalBuffer<[Light]>>,
+    pub tex_coords: Arc<DeviceLocalBuffer<[TexCoord]>>,
+    pub indices: Arc<DeviceLocalBuffer<[u32]>>,
+}
+
+
+/// A chunk mesh on the device.
+#[derive(Clone)]
+pub struct GpuMesh {
+    /// Version of the chunk the mesh was built from
+    pub version: u64,
+    pub lod: Lod,
+    pub opaque: Option<GpuGeometry>,
+    /// Translucent faces are drawn with indices sorted every frame, so their
+    /// geometry is kept on the host as well
+    pub translucent: Option<(GpuGeometry, Arc<RenderData>)>,
+}
+
+fn upload<T, D>(
+    alloc: &StandardMemoryAllocator,
+    builder: &mut Acbb,
+    usage: BufferUsage,
+    data: D,
+) -> Arc<DeviceLocalBuffer<[T]>>
+where
+    T: bytemuck::Pod + Send + Sync,
+    D: IntoIterator<Item = T>,
+    D::IntoIter: ExactSizeIterator,
+{
+    let usage = BufferUsage {
+        transfer_dst: true,
+        ..usage
+    };
+    DeviceLocalBuffer::from_iter(alloc, data, usage, builder).unwrap()
+}
+
+impl GpuGeometry {
+    /// Record the upload of `data` into `builder`. Returns `None` if there is
+    /// nothing to draw.
+    pub fn upload(
+        alloc: &StandardMemoryAllocator,
+        builder: &mut Acbb,
+        data: &RenderData,
+    ) -> Option<Self> {
+        let RenderData {
+            vertices,
+            tex_coords,
+            textures,
+            lights,
+            occlusion,
+            indices,
+        } = data;
+        if indices.is_empty() {
+            return None;
+        }
+
+        let vertex = BufferUsage {
+            vertex_buffer: true,
+            ..BufferUsage::empty()
+        };
+        let index = BufferUsage {
+            index_buffer: true,
+            ..BufferUsage::empty()
+        };
+
+        Some(Self {
+            vertices: upload(
+                alloc,
+                builder,
+                vertex,
+                vertices.iter().copied().map(Vertex::from),
+            ),
+            lights: upload(
+                alloc,
+                builder,
+                vertex,
+                lights
+                    .iter()
+                    .zip(occlusion)
+                    .map(|(&light, &occlusion)| Light::from((light, occlusion))),
+            ),
+            tex_coords: upload(
+                alloc,
+                builder,
+                vertex,
+                tex_coords
+                    .iter()
+                    .zip(textures)
+                    .map(|(&coords, &layer)| TexCoord::from((coords, layer))),
+            ),
+            indices: upload(alloc, builder, index, indices.iter().map(|&i| i as u32)),
+        })
+    }
+}
+
+impl GpuMesh {
+    /// Record the upload of the mesh into `builder`. Returns `None` if there
+    /// is nothing to draw.
+    pub fn upload(
+        alloc: &StandardMemoryAllocator,
+        builder: &mut Acbb,
+        mesh: &ChunkMesh,
+    ) -> Option<Self> {
+        let opaque = GpuGeometry::upload(alloc, builder, &mesh.data);
+        let translucent = GpuGeometry::upload(alloc, builder, &mesh.translucent)
+            .map(|geometry| (geometry, Arc::new(mesh.translucent.clone())));
+        if opaque.is_none() && translucent.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            version: mesh.version,
+            lod: mesh.lod,
+            opaque,
+            translucent,
+        })
+    }
+}
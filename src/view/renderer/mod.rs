@@ -1,13 +1,11 @@
-use std::{f32::consts::FRAC_PI_2, sync::Arc};
+use std::{collections::HashMap, sync::Arc};
 
-use cgmath::{Matrix4, One, Rad, Vector3};
-use data::Vertex;
-use tracing::instrument;
+use cgmath::{Matrix4, MetricSpace, One, Point3, SquareMatrix, Vector3};
+use tracing::{debug, instrument};
 use vulkano::{
     buffer::{
-        cpu_pool::CpuBufferPoolSubbuffer,
+        cpu_pool::{CpuBufferPoolChunk, CpuBufferPoolSubbuffer},
         BufferUsage,
-        CpuAccessibleBuffer,
         CpuBufferPool,
         TypedBufferAccess,
     },
@@ -26,7 +24,12 @@ use vulkano::{
     },
     device::{Device, Queue},
     format::Format,
-    image::{view::ImageView, ImageDimensions, ImmutableImage, MipmapsCount},
+    image::{
+        view::{ImageView, ImageViewCreateInfo, ImageViewType},
+        ImageDimensions,
+        ImmutableImage,
+        MipmapsCount,
+    },
     instance::Instance,
     memory::allocator::{MemoryUsage, StandardMemoryAllocator},
     pipeline::{graphics::viewport::Viewport, GraphicsPipeline, Pipeline, PipelineBindPoint},
@@ -47,39 +50,122 @@ use winit::{
     dpi::{PhysicalSize, Size},
     error::ExternalError,
     event_loop::EventLoop,
-    window::{CursorGrabMode, Window},
+    window::{CursorGrabMode, Fullscreen, Window},
 };
 
-use self::data::Light;
-use super::texture::Texture;
-use crate::model::GameModel;
+pub use self::offscreen::Screenshot;
+use self::{
+    data::{OverlayVertex, TextVertex},
+    mesh::{GpuGeometry, GpuMesh},
+    offscreen::Offscreen,
+};
+use super::{text::Font, texture::Texture};
+use crate::model::{
+    atmosphere::{Atmosphere, Fog},
+    consts as c,
+    frustum::Frustum,
+    mesh::RenderData,
+    region::Region,
+    settings::{Settings, WindowMode},
+    visibility,
+    workers::ChunkMesh,
+    GameModel,
+};
 
 pub mod instance;
 
 mod data;
 mod device;
 mod framebuffer;
+mod mesh;
+mod offscreen;
 mod pipeline;
 mod render_pass;
 mod shaders;
 mod swapchain;
 
 
+/// The window frames are presented in.
+struct WindowTarget {
+    window: Arc<Window>,
+    swapchain: Arc<Swapchain>,
+    framebuffers: Vec<Arc<Framebuffer>>,
+}
+
+/// How winit should make the window cover the screen in `mode`. Exclusive
+/// fullscreen uses the largest video mode with the highest refresh rate of
+/// the monitor the window is on, or borderless if it has none.
+fn fullscreen(mode: WindowMode, window: &Window) -> Option<Fullscreen> {
+    let monitor = window.current_monitor();
+    match mode {
+        WindowMode::Windowed => None,
+        WindowMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+        WindowMode::Exclusive => {
+            let best = monitor.as_ref().and_then(|monitor| {
+                monitor.video_modes().max_by_key(|video| {
+                    let size = video.size();
+                    (size.width * size.height, video.refresh_rate_millihertz())
+                })
+            });
+            match best {
+                Some(video_mode) => Some(Fullscreen::Exclusive(video_mode)),
+                None => Some(Fullscreen::Borderless(monitor)),
+            }
+        },
+    }
+}
+
 pub struct Renderer {
     device: Arc<Device>,
     queues: Vec<Arc<Queue>>,
-    window: Arc<Window>,
-    swapchain: Arc<Swapchain>,
+    /// `None` when rendering headless, only into [`Renderer::capture`]
+    window: Option<WindowTarget>,
+    /// Format of the colour attachment
+    format: Format,
+    /// Size of the frames drawn
+    extent: [u32; 2],
+    /// Where captured frames are rendered, made on the first capture
+    offscreen: Option<Offscreen>,
+    /// Field of view and clipping planes come from here
+    settings: Settings,
     render_pass: Arc<RenderPass>,
     pipeline: Arc<GraphicsPipeline>,
+    /// Draws translucent geometry after everything else
+    pipeline_translucent: Arc<GraphicsPipeline>,
+    /// Draws the sky before anything else
+    pipeline_sky: Arc<GraphicsPipeline>,
+    /// Draws the overlay after everything else
+    pipeline_overlay: Arc<GraphicsPipeline>,
+    /// Draws text over the overlay
+    pipeline_text: Arc<GraphicsPipeline>,
     viewport: Viewport,
-    framebuffers: Vec<Arc<Framebuffer>>,
 
     alloc_memory: Arc<StandardMemoryAllocator>,
     alloc_ds: Arc<StandardDescriptorSetAllocator>,
     alloc_command: Arc<StandardCommandBufferAllocator>,
 
     pool_uniform: CpuBufferPool<shaders::vs::ty::Data>,
+    /// Indices of translucent faces, sorted every frame
+    pool_indices: CpuBufferPool<u32>,
+    pool_overlay: CpuBufferPool<OverlayVertex>,
+    pool_text: CpuBufferPool<TextVertex>,
+
+    /// Shared by all textures
+    sampler: Arc<Sampler>,
+    /// Uploaded textures by name, ready to be bound
+    textures: HashMap<String, Arc<PersistentDescriptorSet>>,
+    /// Font of the overlay text, with its atlas ready to be bound
+    font: Option<(Font, Arc<PersistentDescriptorSet>)>,
+
+    /// Device-local buffers of the latest chunk meshes
+    meshes: HashMap<[usize; 3], GpuMesh>,
+    /// Falling blocks, uploaded every tick
+    entities: Option<GpuGeometry>,
+    /// Uploads recorded since the previous frame
+    uploads: Option<Acbb>,
+    /// Chunk meshes uploaded since the previous frame
+    remeshed: usize,
+    stats: RenderStats,
 
     should_recreate_swapchain: bool,
     previous_frame_end: Option<Box<dyn GpuFuture>>,
@@ -88,42 +174,111 @@ pub struct Renderer {
 
 impl Renderer {
     #[instrument(skip_all)]
-    pub fn new(vk: Arc<Instance>, event_loop: &EventLoop<()>) -> Self {
+    pub fn new(vk: Arc<Instance>, event_loop: &EventLoop<()>, settings: &Settings) -> Self {
+        let [width, height] = settings.window_size;
         let window_builder = winit::window::WindowBuilder::new()
-            .with_resizable(false)
-            .with_inner_size(Size::Physical(PhysicalSize {
-                width: 1920,
-                height: 1080,
-            }))
-            // .with_fullscreen(Some(winit::window::Fullscreen::Borderless(None)))
+            .with_resizable(true)
+            .with_inner_size(Size::Physical(PhysicalSize { width, height }))
             .with_title("tekutonu");
 
         let window = Arc::new(window_builder.build(event_loop).unwrap());
+        // Needs the window to know which monitor to cover
+        window.set_fullscreen(fullscreen(settings.window_mode, &window));
         let surface = create_surface_from_winit(window.clone(), vk.clone()).unwrap();
 
-        let (device, queues) = device::choose_device_and_queue(vk, surface.clone());
+        let (device, queues) = device::choose_device_and_queue(vk, Some(&*surface));
 
         // Allocating color (image) buffers through creating a swapchain.
         let (swapchain, images) =
             swapchain::make_swapchain_and_images(device.clone(), window.clone(), surface);
 
+        let mut renderer = Self::with_device(
+            device,
+            queues,
+            swapchain.image_format(),
+            swapchain.image_extent(),
+        );
+        renderer.settings = settings.clone();
+
+        // render_pass only specifies the layout of framebuffers, we need to actually
+        // create them. We should create a separate framebuffer for every image.
+        let framebuffers = framebuffer::make_framebuffers(
+            &images,
+            renderer.render_pass.clone(),
+            &mut renderer.viewport,
+            renderer.alloc_memory.clone(),
+        );
+        renderer.window = Some(WindowTarget {
+            window,
+            swapchain,
+            framebuffers,
+        });
+
+        renderer
+    }
+
+    /// A renderer without a window, for frames of `extent` pixels that are
+    /// only ever captured. Needs no surface support, so it works on software
+    /// implementations like lavapipe.
+    #[instrument(skip(vk))]
+    pub fn headless(vk: Arc<Instance>, extent: [u32; 2]) -> Self {
+        let (device, queues) = device::choose_device_and_queue(vk, None);
+
+        let mut renderer = Self::with_device(device, queues, Format::R8G8B8A8_SRGB, extent);
+        renderer.viewport.dimensions = extent.map(|v| v as f32);
+        renderer
+    }
+
+    /// Everything but the window.
+    fn with_device(
+        device: Arc<Device>,
+        queues: Vec<Arc<Queue>>,
+        format: Format,
+        extent: [u32; 2],
+    ) -> Self {
         // Describe where the output of the graphics pipeline will go by creating a
         // RenderPass.
-        let render_pass = render_pass::make_render_pass(device.clone(), swapchain.clone());
+        let render_pass = render_pass::make_render_pass(device.clone(), format);
 
         // Loading shaders
         let vs = shaders::vs::load(device.clone()).unwrap();
         let fs = shaders::fs::load(device.clone()).unwrap();
 
         // Specify what we want the device to do
-        let pipeline = pipeline::make_pipeline(device.clone(), render_pass.clone(), vs, fs);
+        let pipeline = pipeline::make_pipeline(
+            device.clone(),
+            render_pass.clone(),
+            vs.clone(),
+            fs.clone(),
+            false,
+        );
+        let pipeline_translucent =
+            pipeline::make_pipeline(device.clone(), render_pass.clone(), vs, fs, true);
+        let pipeline_sky = pipeline::make_sky_pipeline(
+            device.clone(),
+            render_pass.clone(),
+            shaders::sky_vs::load(device.clone()).unwrap(),
+            shaders::sky_fs::load(device.clone()).unwrap(),
+        );
+        let pipeline_overlay = pipeline::make_overlay_pipeline::<OverlayVertex>(
+            device.clone(),
+            render_pass.clone(),
+            shaders::overlay_vs::load(device.clone()).unwrap(),
+            shaders::overlay_fs::load(device.clone()).unwrap(),
+        );
+        let pipeline_text = pipeline::make_overlay_pipeline::<TextVertex>(
+            device.clone(),
+            render_pass.clone(),
+            shaders::text_vs::load(device.clone()).unwrap(),
+            shaders::text_fs::load(device.clone()).unwrap(),
+        );
 
         // Dynamic viewports allow us to recreate just the viewport when the window is
         // resized.
         // Otherwise we would have to recreate the whole pipeline.
         // However, not using a dynamic viewport could allow the driver to optimize some
         // things at the cost of slower resizes.
-        let mut viewport = Viewport {
+        let viewport = Viewport {
             origin: [0.0, 0.0],
             dimensions: [0.0, 0.0],
             depth_range: 0.0..1.0,
@@ -144,16 +299,42 @@ impl Renderer {
             },
             MemoryUsage::Upload,
         );
-
-        // render_pass only specifies the layout of framebuffers, we need to actually
-        // create them. We should create a separate framebuffer for every image.
-        let framebuffers = framebuffer::make_framebuffers(
-            &images,
-            render_pass.clone(),
-            &mut viewport,
+        let pool_indices = CpuBufferPool::<u32>::new(
             alloc_memory.clone(),
+            BufferUsage {
+                index_buffer: true,
+                ..BufferUsage::empty()
+            },
+            MemoryUsage::Upload,
+        );
+        let pool_overlay = CpuBufferPool::<OverlayVertex>::new(
+            alloc_memory.clone(),
+            BufferUsage {
+                vertex_buffer: true,
+                ..BufferUsage::empty()
+            },
+            MemoryUsage::Upload,
+        );
+        let pool_text = CpuBufferPool::<TextVertex>::new(
+            alloc_memory.clone(),
+            BufferUsage {
+                vertex_buffer: true,
+                ..BufferUsage::empty()
+            },
+            MemoryUsage::Upload,
         );
 
+        let sampler = Sampler::new(
+            device.clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Nearest,
+                min_filter: Filter::Nearest,
+                address_mode: [SamplerAddressMode::Repeat; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
         let should_recreate_swapchain = false;
         let previous_frame_end = Some(sync::now(device.clone()).boxed());
 
@@ -162,18 +343,37 @@ impl Renderer {
         Self {
             device,
             queues,
-            window,
-            swapchain,
+            window: None,
+            format,
+            extent,
+            offscreen: None,
+            settings: Settings::default(),
             render_pass,
             pipeline,
+            pipeline_translucent,
+            pipeline_sky,
+            pipeline_overlay,
+            pipeline_text,
             viewport,
-            framebuffers,
 
             alloc_memory,
             alloc_ds,
             alloc_command,
 
             pool_uniform,
+            pool_indices,
+            pool_overlay,
+            pool_text,
+
+            sampler,
+            textures: HashMap::new(),
+            font: None,
+
+            meshes: HashMap::new(),
+            entities: None,
+            uploads: None,
+            remeshed: 0,
+            stats: RenderStats::default(),
 
             should_recreate_swapchain,
             previous_frame_end,
@@ -198,11 +398,11 @@ impl Renderer {
         &self,
         builder: &mut Acbb,
         texture: &Texture,
-    ) -> (Arc<ImageView<ImmutableImage>>, Arc<Sampler>) {
+    ) -> Arc<ImageView<ImmutableImage>> {
         let dimensions = ImageDimensions::Dim2d {
             width: texture.info.width,
             height: texture.info.height,
-            array_layers: 1,
+            array_layers: texture.layers,
         };
 
         let image = ImmutableImage::from_iter(
@@ -214,28 +414,96 @@ impl Renderer {
             builder,
         )
         .unwrap();
-        let texture = ImageView::new_default(image).unwrap();
 
-        let sampler = Sampler::new(
-            self.device.clone(),
-            SamplerCreateInfo {
-                mag_filter: Filter::Nearest,
-                min_filter: Filter::Nearest,
-                address_mode: [SamplerAddressMode::Repeat; 3],
-                ..Default::default()
-            },
+        // The shaders sample arrays, even of a single layer
+        let view_info = ImageViewCreateInfo {
+            view_type: ImageViewType::Dim2dArray,
+            ..ImageViewCreateInfo::from_image(&image)
+        };
+        ImageView::new(image, view_info).unwrap()
+    }
+
+    /// Upload `texture` under `name`, replacing any texture uploaded under the
+    /// same name before.
+    #[instrument(skip(self, texture))]
+    pub fn set_texture(&mut self, name: &str, texture: &Texture) {
+        let mut builder = self
+            .uploads
+            .take()
+            .unwrap_or_else(|| self.make_command_builder());
+        let view = self.make_texture(&mut builder, texture);
+
+        let layout = self.pipeline.layout().set_layouts().get(1).unwrap();
+        let descriptor_set = PersistentDescriptorSet::new(
+            &self.alloc_ds,
+            layout.clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                view,
+                self.sampler.clone(),
+            )],
+        )
+        .unwrap();
+
+        self.uploads = Some(builder);
+
+        self.textures.insert(name.to_owned(), descriptor_set);
+    }
+
+    /// Use `font` with its atlas `texture` for the overlay text.
+    #[instrument(skip_all)]
+    pub fn set_font(&mut self, font: Font, texture: &Texture) {
+        let mut builder = self
+            .uploads
+            .take()
+            .unwrap_or_else(|| self.make_command_builder());
+        let view = self.make_texture(&mut builder, texture);
+
+        let layout = self.pipeline_text.layout().set_layouts().get(0).unwrap();
+        let descriptor_set = PersistentDescriptorSet::new(
+            &self.alloc_ds,
+            layout.clone(),
+            [WriteDescriptorSet::image_view_sampler(
+                0,
+                view,
+                self.sampler.clone(),
+            )],
         )
         .unwrap();
 
-        (texture, sampler)
+        self.uploads = Some(builder);
+
+        self.font = Some((font, descriptor_set));
     }
 
+    pub fn font(&self) -> Option<&Font> {
+        self.font.as_ref().map(|(font, _)| font)
+    }
+
+    /// Submit the recorded uploads so that they finish before the next frame.
+    fn submit_uploads(&mut self) {
+        let Some(builder) = self.uploads.take() else {
+            return;
+        };
+
+        let future = self
+            .previous_frame_end
+            .take()
+            .unwrap()
+            .then_execute(self.queues[0].clone(), builder.build().unwrap())
+            .unwrap();
+        self.previous_frame_end = Some(future.boxed());
+    }
+
+    /// Record drawing `data` into `framebuffer`, then copying it out to
+    /// `readback` if given.
     fn build_command_buffer(
-        &mut self,
+        &self,
         mut builder: Acbb,
-        image_num: usize,
-        ds: Arc<PersistentDescriptorSet>,
+        framebuffer: Arc<Framebuffer>,
+        (ds, ds_sky): DescriptorSets,
         data: &DrawData,
+        readback: Option<&Offscreen>,
     ) -> PrimaryAutoCommandBuffer {
         builder
             .begin_render_pass(
@@ -243,28 +511,99 @@ impl Renderer {
                     // One item for each attachment in the render pass that have `LoadOp::Clear`
                     // (otherwise None)
                     clear_values: vec![
-                        Some([0.0, 0.0, 0.0, 1.0].into()), // Color
-                        Some(1f32.into()),                 // Depth
+                        Some(data.clear.into()), // Color
+                        Some(1f32.into()),       // Depth
                     ],
-                    ..RenderPassBeginInfo::framebuffer(self.framebuffers[image_num].clone())
+                    ..RenderPassBeginInfo::framebuffer(framebuffer)
                 },
                 SubpassContents::Inline,
             )
             .unwrap()
             .set_viewport(0, [self.viewport.clone()])
+            .bind_pipeline_graphics(self.pipeline_sky.clone())
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.pipeline_sky.layout().clone(),
+                0,
+                ds_sky,
+            )
+            .draw(3, 1, 0, 0)
+            .unwrap()
             .bind_pipeline_graphics(self.pipeline.clone())
             .bind_descriptor_sets(
                 PipelineBindPoint::Graphics,
                 self.pipeline.layout().clone(),
                 0,
+                ds.clone(),
+            );
+
+        for geometry in &data.opaque {
+            builder
+                .bind_vertex_buffers(
+                    0,
+                    (
+                        geometry.vertices.clone(),
+                        geometry.lights.clone(),
+                        geometry.tex_coords.clone(),
+                    ),
+                )
+                .bind_index_buffer(geometry.indices.clone())
+                .draw_indexed(geometry.indices.len() as u32, 1, 0, 0, 0)
+                .unwrap();
+        }
+
+        // Translucent geometry goes last, furthest first
+        builder
+            .bind_pipeline_graphics(self.pipeline_translucent.clone())
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.pipeline_translucent.layout().clone(),
+                0,
                 ds,
-            )
-            .bind_vertex_buffers(0, (data.vertices.clone(), data.lights.clone()))
-            .bind_index_buffer(data.indices.clone())
-            .draw_indexed(data.indices.len() as u32, 1, 0, 0, 0)
-            .unwrap()
-            .end_render_pass()
-            .unwrap();
+            );
+
+        for (geometry, indices) in &data.translucent {
+            builder
+                .bind_vertex_buffers(
+                    0,
+                    (
+                        geometry.vertices.clone(),
+                        geometry.lights.clone(),
+                        geometry.tex_coords.clone(),
+                    ),
+                )
+                .bind_index_buffer(indices.clone())
+                .draw_indexed(indices.len() as u32, 1, 0, 0, 0)
+                .unwrap();
+        }
+
+        if let Some((vertices, indices)) = &data.overlay {
+            builder
+                .bind_pipeline_graphics(self.pipeline_overlay.clone())
+                .bind_vertex_buffers(0, vertices.clone())
+                .bind_index_buffer(indices.clone())
+                .draw_indexed(indices.len() as u32, 1, 0, 0, 0)
+                .unwrap();
+        }
+        if let (Some((vertices, indices)), Some((_, atlas))) = (&data.text, &self.font) {
+            builder
+                .bind_pipeline_graphics(self.pipeline_text.clone())
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    self.pipeline_text.layout().clone(),
+                    0,
+                    atlas.clone(),
+                )
+                .bind_vertex_buffers(0, vertices.clone())
+                .bind_index_buffer(indices.clone())
+                .draw_indexed(indices.len() as u32, 1, 0, 0, 0)
+                .unwrap();
+        }
+
+        builder.end_render_pass().unwrap();
+        if let Some(offscreen) = readback {
+            offscreen.record_readback(&mut builder);
+        }
 
         // Finish building the command buffer by calling `build`.
         builder.build().unwrap()
@@ -273,11 +612,12 @@ impl Renderer {
     #[instrument(skip_all)]
     fn recreate_swapchain(
         &mut self,
+        swapchain: &Arc<Swapchain>,
         dimensions: PhysicalSize<u32>,
     ) -> Result<(Arc<Swapchain>, Vec<Arc<Framebuffer>>), ()> {
-        let (new_swapchain, new_images) = match self.swapchain.recreate(SwapchainCreateInfo {
+        let (new_swapchain, new_images) = match swapchain.recreate(SwapchainCreateInfo {
             image_extent: dimensions.into(),
-            ..self.swapchain.create_info()
+            ..swapchain.create_info()
         }) {
             Ok(r) => r,
             // Likely user resizing the window, just retry.
@@ -298,101 +638,131 @@ impl Renderer {
         Ok((new_swapchain, new_framebuffers))
     }
 
+    /// Projection and view matrices of the game camera.
+    fn camera_matrices(&self, game: &GameModel) -> (Matrix4<f32>, Matrix4<f32>) {
+        let aspect_ratio = self.extent[0] as f32 / self.extent[1] as f32;
+
+        (
+            self.settings.projection_matrix(aspect_ratio),
+            game.camera.view_matrix(),
+        )
+    }
+
     fn make_uniforms(
         &self,
         game: &GameModel,
     ) -> Arc<CpuBufferPoolSubbuffer<shaders::vs::ty::Data>> {
-        let camera = &game.camera;
-
-        let position = camera.position.map(|v| v as f32);
-        let direction = camera.get_look().map(|v| v as f32);
-
-        let aspect_ratio =
-            self.swapchain.image_extent()[0] as f32 / self.swapchain.image_extent()[1] as f32;
-
-        // Perspective projection matrix
-        let proj = cgmath::perspective(
-            // 90 degrees
-            Rad(FRAC_PI_2),
-            aspect_ratio,
-            0.01,
-            100.0,
-        );
-
-        let view = Matrix4::look_to_rh(position, direction, Vector3::new(0.0, -1.0, 0.0));
-
-        let scale = Matrix4::from_scale(0.5);
+        let (proj, view) = self.camera_matrices(game);
+        let sky = (proj * game.camera.rotation_matrix()).invert().unwrap();
+        let eye = game.camera.get_block_position().map(|v| v as f32);
+
+        let Atmosphere {
+            fog,
+            zenith,
+            horizon,
+            ambient,
+        } = game.atmosphere;
+        let fog = match fog {
+            Fog::Linear { start, end } => [0.0, start, end, 0.0],
+            Fog::Exponential { density } => [1.0, 0.0, 0.0, density],
+        };
+        let [zenith, horizon, ambient] = [zenith, horizon, ambient].map(|[r, g, b]| [r, g, b, 1.0]);
 
         let uniform_data = shaders::vs::ty::Data {
             world: Matrix4::one().into(),
-            view: (view * scale).into(),
+            view: view.into(),
             proj: proj.into(),
+            sky: sky.into(),
+            eye: eye.to_homogeneous().into(),
+            fog,
+            zenith,
+            horizon,
+            ambient,
         };
 
         self.pool_uniform.from_data(uniform_data).unwrap()
     }
 }
 
-type Vni = (
-    Arc<CpuAccessibleBuffer<[Vertex]>>,
-    Arc<CpuAccessibleBuffer<[Light]>>,
-    Arc<CpuAccessibleBuffer<[u16]>>,
-);
-
 impl Renderer {
-    fn make_vli(&self, game: &GameModel) -> Vni {
-        let (v, l, i) = game
-            .world
-            .get_chunk([1, 1, 1])
-            .get_render_data(Vector3::new(0.0, 0.0, 0.0));
+    /// Replace the mesh of a chunk with a newer one. The upload happens
+    /// before the next frame. Empty meshes release the buffers of the chunk.
+    pub fn set_chunk_mesh(&mut self, mesh: ChunkMesh) {
+        if let Some(cached) = self.meshes.get(&mesh.location) {
+            if (cached.version, cached.lod) == (mesh.version, mesh.lod)
+                || cached.version > mesh.version
+            {
+                return;
+            }
+        }
 
-        let v = CpuAccessibleBuffer::from_iter(
-            &self.alloc_memory,
-            BufferUsage {
-                vertex_buffer: true,
-                ..BufferUsage::empty()
-            },
-            false,
-            v.into_iter().map(Vertex::from),
-        )
-        .unwrap();
+        let mut builder = self
+            .uploads
+            .take()
+            .unwrap_or_else(|| self.make_command_builder());
+        match GpuMesh::upload(&self.alloc_memory, &mut builder, &mesh) {
+            Some(gpu) => self.meshes.insert(mesh.location, gpu),
+            None => self.meshes.remove(&mesh.location),
+        };
+        self.uploads = Some(builder);
 
-        let l = CpuAccessibleBuffer::from_iter(
-            &self.alloc_memory,
-            BufferUsage {
-                vertex_buffer: true,
-                ..BufferUsage::empty()
-            },
-            false,
-            l.into_iter().map(Light::from),
-        )
-        .unwrap();
+        self.remeshed += 1;
+    }
 
-        let i = CpuAccessibleBuffer::from_iter(
-            &self.alloc_memory,
-            BufferUsage {
-                index_buffer: true,
-                ..BufferUsage::empty()
-            },
-            false,
-            i.into_iter().map(|i| i as u16),
-        )
-        .unwrap();
+    /// Replace the geometry of the falling blocks. The upload happens before
+    /// the next frame.
+    pub fn set_entity_mesh(&mut self, data: &RenderData) {
+        if data.indices.is_empty() && self.entities.is_none() {
+            return;
+        }
 
-        (v, l, i)
+        let mut builder = self
+            .uploads
+            .take()
+            .unwrap_or_else(|| self.make_command_builder());
+        self.entities = GpuGeometry::upload(&self.alloc_memory, &mut builder, data);
+        self.uploads = Some(builder);
+    }
+
+    /// Statistics of the last drawn frame.
+    pub fn stats(&self) -> RenderStats {
+        self.stats
+    }
+
+    /// Use the field of view, clipping planes, window size and window mode
+    /// of `settings`. The swapchain follows once the window is resized.
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        if let Some(WindowTarget { window, .. }) = &self.window {
+            if settings.window_mode != self.settings.window_mode {
+                window.set_fullscreen(fullscreen(settings.window_mode, window));
+            }
+            if settings.window_size != self.settings.window_size {
+                let [width, height] = settings.window_size;
+                window.set_inner_size(PhysicalSize { width, height });
+            }
+        }
+        self.settings = settings.clone();
+    }
+
+    /// Size of the frames drawn, in pixels.
+    pub fn extent(&self) -> [u32; 2] {
+        self.extent
     }
 
     #[instrument(skip_all)]
     pub fn set_cursor_locked(&self, locked: bool) -> Result<(), ExternalError> {
+        let Some(WindowTarget { window, .. }) = &self.window else {
+            return Ok(());
+        };
         let grab = if locked {
             CursorGrabMode::Locked
         } else {
             CursorGrabMode::None
         };
-        let res = self.window.set_cursor_grab(grab);
+        let res = window.set_cursor_grab(grab);
 
         if res.is_err() && locked {
-            self.window.set_cursor_grab(CursorGrabMode::Confined)
+            window.set_cursor_grab(CursorGrabMode::Confined)
         } else {
             res
         }
@@ -400,37 +770,238 @@ impl Renderer {
 
     #[instrument(skip_all)]
     pub fn set_cursor_hidden(&self, hidden: bool) {
-        self.window.set_cursor_visible(!hidden)
+        if let Some(WindowTarget { window, .. }) = &self.window {
+            window.set_cursor_visible(!hidden)
+        }
     }
 
     pub fn schedule_recreate_swapchain(&mut self) {
         self.should_recreate_swapchain = true;
     }
 
-    pub fn make_draw_data(&self, game: &GameModel) -> DrawData {
-        let (vertices, lights, indices) = self.make_vli(game);
+    /// The window was resized to `size`. Frames made from now on are for the
+    /// new size, so the aspect ratio and overlay match the recreated
+    /// swapchain.
+    pub fn resize(&mut self, size: PhysicalSize<u32>) {
+        if size.width > 0 && size.height > 0 {
+            self.extent = size.into();
+        }
+        self.schedule_recreate_swapchain();
+    }
+
+    /// What to draw for `game`, with `overlay` over it.
+    pub fn make_draw_data(&self, game: &GameModel, overlay: &Overlay) -> DrawData {
+        let (proj, view) = self.camera_matrices(game);
+        let frustum = Frustum::from_matrix(proj * view);
+        let in_view = |loc| frustum.intersects_chunk(loc);
+
+        // Outside of the region there is nothing to walk from
+        let visible = Region::chunk_location(game.camera.get_chunk_location())
+            .map(|start| visibility::visible_chunks(&game.world, start, in_view));
+
+        let chunks: Vec<_> = self
+            .meshes
+            .iter()
+            .filter(|(&loc, _)| match &visible {
+                Some(visible) => visible.contains(&loc),
+                None => in_view(loc),
+            })
+            .collect();
+
+        let opaque = chunks
+            .iter()
+            .filter_map(|(_, mesh)| mesh.opaque.clone())
+            .chain(self.entities.clone())
+            .collect();
+
+        // Chunks are sorted by their centres, faces within each chunk by
+        // theirs
+        let eye = game.camera.get_block_position().map(|v| v as f32);
+        let distance = |loc: [usize; 3]| {
+            let half = c::CHUNK_EDGE_BLOCKS as f32 / 2.0;
+            let centre = Point3::from(loc.map(|v| (v * c::CHUNK_EDGE_BLOCKS) as f32))
+                + Vector3::new(half, half, half);
+            centre.distance2(eye)
+        };
+        let mut translucent: Vec<_> = chunks
+            .iter()
+            .filter_map(|(&loc, mesh)| Some((distance(loc), mesh.translucent.as_ref()?)))
+            .collect();
+        translucent.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let translucent = translucent
+            .into_iter()
+            .map(|(_, (geometry, data))| {
+                let indices = data.back_to_front(eye).into_iter().map(|i| i as u32);
+                (
+                    geometry.clone(),
+                    self.pool_indices.from_iter(indices).unwrap(),
+                )
+            })
+            .collect();
+
         let uniforms = self.make_uniforms(game);
+        let [r, g, b] = game.atmosphere.horizon;
         DrawData {
-            vertices,
-            lights,
-            indices,
+            clear: [r, g, b, 1.0],
+            opaque,
+            translucent,
+            overlay: self.make_overlay(&overlay.quads),
+            text: self.make_text(&overlay.text),
+            drawn: chunks.len(),
             uniforms,
         }
     }
+
+    /// Vertices and indices of the overlay quads.
+    fn make_overlay(&self, quads: &[OverlayQuad]) -> Option<OverlayBuffers<OverlayVertex>> {
+        if quads.is_empty() {
+            return None;
+        }
+
+        let vertices = quads.iter().flat_map(|quad| {
+            self.quad_corners(quad.position, quad.size)
+                .map(|corner| OverlayVertex {
+                    o_position: corner,
+                    o_color: quad.color,
+                })
+        });
+
+        Some((
+            self.pool_overlay.from_iter(vertices).unwrap(),
+            self.pool_indices
+                .from_iter(quad_indices(quads.len()))
+                .unwrap(),
+        ))
+    }
+
+    /// Vertices and indices of the glyphs of `texts`, laid out with the font.
+    fn make_text(&self, texts: &[Text]) -> Option<OverlayBuffers<TextVertex>> {
+        let (font, _) = self.font.as_ref()?;
+        let atlas = font.atlas_size().map(|v| v as f32);
+
+        let mut vertices = Vec::new();
+        for text in texts {
+            let max_width = text.wrap.map(|wrap| (wrap / text.scale) as u32);
+            for placed in font.layout(&text.text, max_width).glyphs {
+                let position =
+                    [0, 1].map(|a| text.position[a] + placed.position[a] as f32 * text.scale);
+                let size = placed.glyph.size.map(|v| v as f32 * text.scale);
+                let [u, v] = [0, 1].map(|a| placed.glyph.atlas[a] as f32 / atlas[a]);
+                let [du, dv] = [0, 1].map(|a| placed.glyph.size[a] as f32 / atlas[a]);
+                let tex_coords = [[u, v], [u, v + dv], [u + du, v + dv], [u + du, v]];
+
+                let corners = self.quad_corners(position, size);
+                vertices.extend((0..4).map(|i| TextVertex {
+                    t_position: corners[i],
+                    t_tex_coords: tex_coords[i],
+                    t_color: text.color,
+                }));
+            }
+        }
+        if vertices.is_empty() {
+            return None;
+        }
+
+        let quads = vertices.len() / 4;
+        Some((
+            self.pool_text.from_iter(vertices).unwrap(),
+            self.pool_indices.from_iter(quad_indices(quads)).unwrap(),
+        ))
+    }
+
+    /// Corners of a rectangle in pixels, in normalized device coordinates,
+    /// counter-clockwise from the top left.
+    fn quad_corners(&self, [x, y]: [f32; 2], [w, h]: [f32; 2]) -> [[f32; 2]; 4] {
+        let [width, height] = self.extent.map(|v| v as f32);
+        [[x, y], [x, y + h], [x + w, y + h], [x + w, y]]
+            .map(|[x, y]| [x / width * 2.0 - 1.0, y / height * 2.0 - 1.0])
+    }
 }
 
+/// Indices of `quads` quads of four vertices each, two triangles per quad.
+fn quad_indices(quads: usize) -> impl Iterator<Item = u32> {
+    (0..quads as u32).flat_map(|q| [0, 1, 2, 0, 2, 3].map(|i| q * 4 + i))
+}
+
+/// Counters describing a frame.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderStats {
+    /// Chunk meshes uploaded since the previous frame
+    pub remeshed: usize,
+    /// Chunk meshes held on the device
+    pub chunks: usize,
+    /// Chunk meshes that passed culling
+    pub drawn: usize,
+}
+
+/// A rectangle drawn over the world, in pixels from the top left corner.
+#[derive(Clone, Copy, Debug)]
+pub struct OverlayQuad {
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+    pub color: [f32; 4],
+}
+
+/// Text drawn over the world with the font set by [`Renderer::set_font`].
+#[derive(Clone, Debug)]
+pub struct Text {
+    /// Top left corner, in pixels from the top left corner of the screen
+    pub position: [f32; 2],
+    pub text: String,
+    /// Screen pixels per pixel of the font
+    pub scale: f32,
+    pub color: [f32; 4],
+    /// Width in pixels to wrap lines at
+    pub wrap: Option<f32>,
+}
+
+/// Everything drawn over the world: quads, then text over them.
+#[derive(Clone, Debug, Default)]
+pub struct Overlay {
+    pub quads: Vec<OverlayQuad>,
+    pub text: Vec<Text>,
+}
+
+type OverlayBuffers<V> = (Arc<CpuBufferPoolChunk<V>>, Arc<CpuBufferPoolChunk<u32>>);
+
 pub struct DrawData {
-    vertices: Arc<CpuAccessibleBuffer<[Vertex]>>,
-    lights: Arc<CpuAccessibleBuffer<[Light]>>,
-    indices: Arc<CpuAccessibleBuffer<[u16]>>,
+    /// Colour behind everything, in case the sky does not cover it
+    clear: [f32; 4],
+    /// Opaque geometry of the chunks that passed culling
+    opaque: Vec<GpuGeometry>,
+    /// Translucent geometry with indices sorted back to front, furthest
+    /// chunk first
+    translucent: Vec<(GpuGeometry, Arc<CpuBufferPoolChunk<u32>>)>,
+    /// Screen space quads drawn last
+    overlay: Option<OverlayBuffers<OverlayVertex>>,
+    /// Glyphs drawn over the quads
+    text: Option<OverlayBuffers<TextVertex>>,
+    /// Number of chunks that passed culling
+    drawn: usize,
     uniforms: Arc<CpuBufferPoolSubbuffer<shaders::vs::ty::Data>>,
 }
 
+/// Descriptor sets of the world pipelines and of the sky pipeline.
+type DescriptorSets = (
+    Vec<Arc<PersistentDescriptorSet>>,
+    Arc<PersistentDescriptorSet>,
+);
+
 impl Renderer {
-    pub fn draw(&mut self, data: &DrawData, texture: &Texture) {
+    /// Draw a frame to the window with the texture uploaded as `texture`.
+    /// Headless renderers draw nothing here, only in [`Renderer::capture`].
+    pub fn draw(&mut self, data: &DrawData, texture: &str) {
+        let Some(WindowTarget {
+            window, swapchain, ..
+        }) = &self.window
+        else {
+            return;
+        };
+        let swapchain = swapchain.clone();
+
         // Do not draw frame when screen dimensions are zero.
         // On Windows, this can occur from minimizing the application.
-        let dimensions = self.window.inner_size();
+        let dimensions = window.inner_size();
         if dimensions.width == 0 || dimensions.height == 0 {
             return;
         }
@@ -438,40 +1009,36 @@ impl Renderer {
         // Periodic garbage collection.
         self.previous_frame_end.as_mut().unwrap().cleanup_finished();
 
+        self.submit_uploads();
+        self.update_stats(data);
+
         // Whenever the window resizes we need to recreate everything dependent on the
         // window size.
-        if self.should_recreate_swapchain {
-            match self.recreate_swapchain(dimensions) {
+        let swapchain = if self.should_recreate_swapchain {
+            match self.recreate_swapchain(&swapchain, dimensions) {
                 Ok((swapchain, framebuffers)) => {
-                    self.swapchain = swapchain;
-                    self.framebuffers = framebuffers;
+                    self.extent = swapchain.image_extent();
+                    let target = self.window.as_mut().unwrap();
+                    target.swapchain = swapchain.clone();
+                    target.framebuffers = framebuffers;
+                    self.should_recreate_swapchain = false;
+                    swapchain
                 },
                 _ => {
                     return;
                 },
             }
-            self.should_recreate_swapchain = false;
-        }
-
-        let mut command_builder = self.make_command_builder();
-
-        let (texture, sampler) = self.make_texture(&mut command_builder, texture);
+        } else {
+            swapchain
+        };
 
-        let pipeline_layout = self.pipeline.layout().set_layouts().get(0).unwrap();
-        let descriptor_set = PersistentDescriptorSet::new(
-            &self.alloc_ds,
-            pipeline_layout.clone(),
-            [
-                WriteDescriptorSet::buffer(0, data.uniforms.clone()),
-                WriteDescriptorSet::image_view_sampler(1, texture, sampler),
-            ],
-        )
-        .unwrap();
+        let command_builder = self.make_command_builder();
+        let descriptor_sets = self.make_descriptor_sets(data, texture);
 
         // Acquire image from the swapchain for drawing. Wait if no image is yet
         // available.
         let (image_num, suboptimal, acquire_future) =
-            match acquire_next_image(self.swapchain.clone(), None) {
+            match acquire_next_image(swapchain.clone(), None) {
                 Ok(r) => r,
                 Err(AcquireError::OutOfDate) => {
                     self.should_recreate_swapchain = true;
@@ -486,8 +1053,9 @@ impl Renderer {
             self.should_recreate_swapchain = true;
         }
 
+        let framebuffer = self.window.as_ref().unwrap().framebuffers[image_num as usize].clone();
         let command_buffer =
-            self.build_command_buffer(command_builder, image_num as usize, descriptor_set, data);
+            self.build_command_buffer(command_builder, framebuffer, descriptor_sets, data, None);
 
         let future = self
             .previous_frame_end
@@ -499,7 +1067,7 @@ impl Renderer {
             // Submit a present command at the end of the queue.
             .then_swapchain_present(
                 self.queues[0].clone(),
-                SwapchainPresentInfo::swapchain_image_index(self.swapchain.clone(), image_num),
+                SwapchainPresentInfo::swapchain_image_index(swapchain, image_num),
             )
             .then_signal_fence_and_flush();
 
@@ -517,4 +1085,82 @@ impl Renderer {
             },
         }
     }
+
+    /// Draw a frame like [`Renderer::draw`], but into an image of its own, and
+    /// read it back. Blocks until the device is done with it.
+    #[instrument(skip_all)]
+    pub fn capture(&mut self, data: &DrawData, texture: &str) -> Screenshot {
+        if self.offscreen.as_ref().map(Offscreen::extent) != Some(self.extent) {
+            self.offscreen = Some(Offscreen::new(
+                self.alloc_memory.clone(),
+                self.render_pass.clone(),
+                self.format,
+                self.extent,
+            ));
+        }
+
+        self.submit_uploads();
+        self.update_stats(data);
+
+        let command_builder = self.make_command_builder();
+        let descriptor_sets = self.make_descriptor_sets(data, texture);
+        let offscreen = self.offscreen.as_ref().unwrap();
+        let command_buffer = self.build_command_buffer(
+            command_builder,
+            offscreen.framebuffer(),
+            descriptor_sets,
+            data,
+            Some(offscreen),
+        );
+
+        self.previous_frame_end
+            .take()
+            .unwrap()
+            .then_execute(self.queues[0].clone(), command_buffer)
+            .unwrap()
+            .then_signal_fence_and_flush()
+            .unwrap()
+            .wait(None)
+            .unwrap();
+        self.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
+
+        offscreen.read()
+    }
+
+    fn update_stats(&mut self, data: &DrawData) {
+        self.stats = RenderStats {
+            remeshed: std::mem::take(&mut self.remeshed),
+            chunks: self.meshes.len(),
+            drawn: data.drawn,
+        };
+        if self.stats.remeshed > 0 {
+            debug!(remeshed = self.stats.remeshed, chunks = self.stats.chunks);
+        }
+    }
+
+    fn make_descriptor_sets(&self, data: &DrawData, texture: &str) -> DescriptorSets {
+        let texture = self
+            .textures
+            .get(texture)
+            .unwrap_or_else(|| panic!("Texture {} was never uploaded", texture))
+            .clone();
+
+        let pipeline_layout = self.pipeline.layout().set_layouts().get(0).unwrap();
+        let uniforms = PersistentDescriptorSet::new(
+            &self.alloc_ds,
+            pipeline_layout.clone(),
+            [WriteDescriptorSet::buffer(0, data.uniforms.clone())],
+        )
+        .unwrap();
+
+        let sky_layout = self.pipeline_sky.layout().set_layouts().get(0).unwrap();
+        let sky = PersistentDescriptorSet::new(
+            &self.alloc_ds,
+            sky_layout.clone(),
+            [WriteDescriptorSet::buffer(0, data.uniforms.clone())],
+        )
+        .unwrap();
+
+        (vec![uniforms, texture], sky)
+    }
 }
@@ -0,0 +1,127 @@
+use std::{fs::File, io::BufWriter, path::Path, sync::Arc};
+
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer},
+    command_buffer::CopyImageToBufferInfo,
+    format::Format,
+    image::{AttachmentImage, ImageUsage},
+    memory::allocator::StandardMemoryAllocator,
+    pipeline::graphics::viewport::Viewport,
+    render_pass::{Framebuffer, RenderPass},
+};
+
+use super::{framebuffer, Acbb};
+
+/// An image frames are rendered into instead of the window, and a buffer
+/// they are copied to for the host to read.
+pub struct Offscreen {
+    format: Format,
+    extent: [u32; 2],
+    image: Arc<AttachmentImage>,
+    framebuffer: Arc<Framebuffer>,
+    buffer: Arc<CpuAccessibleBuffer<[u8]>>,
+}
+
+impl Offscreen {
+    pub fn new(
+        allocator: Arc<StandardMemoryAllocator>,
+        render_pass: Arc<RenderPass>,
+        format: Format,
+        extent: [u32; 2],
+    ) -> Self {
+        let usage = ImageUsage {
+            color_attachment: true,
+            transfer_src: true,
+            ..ImageUsage::empty()
+        };
+        let image = AttachmentImage::with_usage(&allocator, extent, format, usage).unwrap();
+
+        // The viewport is the renderer's, it has the same size already
+        let mut viewport = Viewport {
+            origin: [0.0, 0.0],
+            dimensions: [0.0, 0.0],
+            depth_range: 0.0..1.0,
+        };
+        let framebuffer = framebuffer::make_framebuffers(
+            &[image.clone()],
+            render_pass,
+            &mut viewport,
+            allocator.clone(),
+        )
+        .remove(0);
+
+        let usage = BufferUsage {
+            transfer_dst: true,
+            ..BufferUsage::empty()
+        };
+        let size = extent[0] as usize * extent[1] as usize * 4;
+        let buffer =
+            CpuAccessibleBuffer::from_iter(&allocator, usage, false, (0..size).map(|_| 0u8))
+                .unwrap();
+
+        Self {
+            format,
+            extent,
+            image,
+            framebuffer,
+            buffer,
+        }
+    }
+
+    pub fn extent(&self) -> [u32; 2] {
+        self.extent
+    }
+
+    pub fn framebuffer(&self) -> Arc<Framebuffer> {
+        self.framebuffer.clone()
+    }
+
+    /// Copy the rendered image to the buffer, after the render pass.
+    pub fn record_readback(&self, builder: &mut Acbb) {
+        builder
+            .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+                self.image.clone(),
+                self.buffer.clone(),
+            ))
+            .unwrap();
+    }
+
+    /// Read the copied image. The copy must have finished.
+    pub fn read(&self) -> Screenshot {
+        let mut rgba = self.buffer.read().unwrap().to_vec();
+        if matches!(self.format, Format::B8G8R8A8_SRGB | Format::B8G8R8A8_UNORM) {
+            for pixel in rgba.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        Screenshot {
+            width: self.extent[0],
+            height: self.extent[1],
+            rgba,
+        }
+    }
+}
+
+/// A rendered frame read back from the device.
+pub struct Screenshot {
+    pub width: u32,
+    pub height: u32,
+    /// Rows from the top, 8 bits per channel, sRGB encoded
+    pub rgba: Vec<u8>,
+}
+
+impl Screenshot {
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), png::EncodingError> {
+        let file = BufWriter::new(File::create(path)?);
+
+        let mut encoder = png::Encoder::new(file, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_srgb(png::SrgbRenderingIntent::Perceptual);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.rgba)?;
+        writer.finish()
+    }
+}
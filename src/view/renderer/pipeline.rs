@@ -8,7 +8,7 @@ use vulkano::{
             depth_stencil::DepthStencilState,
             input_assembly::InputAssemblyState,
             rasterization::{CullMode, RasterizationState},
-            vertex_input::BuffersDefinition,
+            vertex_input::{BuffersDefinition, Vertex as VertexType},
             viewport::ViewportState,
         },
         GraphicsPipeline,
@@ -18,27 +18,44 @@ use vulkano::{
     shader::ShaderModule,
 };
 
-use super::data::{Light, Vertex};
+use super::data::{Light, TexCoord, Vertex};
 
+/// Make the pipeline for opaque geometry or, with `translucent`, for
+/// translucent geometry drawn over it.
+///
+/// Translucent faces are blended, seen from both sides, and do not write
+/// depth so that faces behind them still show.
 pub fn make_pipeline(
     device: Arc<Device>,
     render_pass: Arc<RenderPass>,
     vs: Arc<ShaderModule>,
     fs: Arc<ShaderModule>,
+    translucent: bool,
 ) -> Arc<GraphicsPipeline> {
     let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
 
+    let mut depth_stencil = DepthStencilState::simple_depth_test();
+    let mut color_blend = ColorBlendState::new(subpass.num_color_attachments());
+    let cull_mode = if translucent {
+        depth_stencil.depth.as_mut().unwrap().write_enable = StateMode::Fixed(false);
+        color_blend = color_blend.blend_alpha();
+        CullMode::None
+    } else {
+        CullMode::Back
+    };
+
     GraphicsPipeline::start()
         // How the vertices are laid out.
         .vertex_input_state(
             BuffersDefinition::new()
                 .vertex::<Vertex>()
-                .vertex::<Light>(),
+                .vertex::<Light>()
+                .vertex::<TexCoord>(),
         )
         .vertex_shader(vs.entry_point("main").unwrap(), ())
         .rasterization_state(RasterizationState {
             // polygon_mode: todo!(),
-            cull_mode: StateMode::Fixed(CullMode::Back),
+            cull_mode: StateMode::Fixed(cull_mode),
             ..Default::default()
         })
         // The content of the vertex buffer describes a list of triangles.
@@ -46,10 +63,53 @@ pub fn make_pipeline(
         // Use a resizable viewport set to draw over the entire window
         .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
         .fragment_shader(fs.entry_point("main").unwrap(), ())
-        .depth_stencil_state(DepthStencilState::simple_depth_test())
-        .color_blend_state(ColorBlendState::new(subpass.num_color_attachments()).blend_alpha())
+        .depth_stencil_state(depth_stencil)
+        .color_blend_state(color_blend)
         // Which subpass of which render pass this pipeline is going to be used in.
         .render_pass(Subpass::from(render_pass, 0).unwrap())
         .build(device)
         .unwrap()
 }
+
+/// Make the pipeline for the sky, drawn first without vertex buffers or
+/// depth.
+pub fn make_sky_pipeline(
+    device: Arc<Device>,
+    render_pass: Arc<RenderPass>,
+    vs: Arc<ShaderModule>,
+    fs: Arc<ShaderModule>,
+) -> Arc<GraphicsPipeline> {
+    GraphicsPipeline::start()
+        .vertex_input_state(BuffersDefinition::new())
+        .vertex_shader(vs.entry_point("main").unwrap(), ())
+        .input_assembly_state(InputAssemblyState::new())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .fragment_shader(fs.entry_point("main").unwrap(), ())
+        .depth_stencil_state(DepthStencilState::disabled())
+        .render_pass(Subpass::from(render_pass, 0).unwrap())
+        .build(device)
+        .unwrap()
+}
+
+/// Make a pipeline for the overlay, quads or text with vertices `V`, drawn
+/// last over everything in screen space and blended.
+pub fn make_overlay_pipeline<V: VertexType>(
+    device: Arc<Device>,
+    render_pass: Arc<RenderPass>,
+    vs: Arc<ShaderModule>,
+    fs: Arc<ShaderModule>,
+) -> Arc<GraphicsPipeline> {
+    let subpass = Subpass::from(render_pass, 0).unwrap();
+
+    GraphicsPipeline::start()
+        .vertex_input_state(BuffersDefinition::new().vertex::<V>())
+        .vertex_shader(vs.entry_point("main").unwrap(), ())
+        .input_assembly_state(InputAssemblyState::new())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .fragment_shader(fs.entry_point("main").unwrap(), ())
+        .depth_stencil_state(DepthStencilState::disabled())
+        .color_blend_state(ColorBlendState::new(subpass.num_color_attachments()).blend_alpha())
+        .render_pass(subpass)
+        .build(device)
+        .unwrap()
+}
@@ -1,8 +1,8 @@
 use std::sync::Arc;
 
-use vulkano::{device::Device, format::Format, render_pass::RenderPass, swapchain::Swapchain};
+use vulkano::{device::Device, format::Format, render_pass::RenderPass};
 
-pub fn make_render_pass(device: Arc<Device>, swapchain: Arc<Swapchain>) -> Arc<RenderPass> {
+pub fn make_render_pass(device: Arc<Device>, format: Format) -> Arc<RenderPass> {
     vulkano::single_pass_renderpass!(
         device,
         // TODO: read about attachments
@@ -13,7 +13,7 @@ pub fn make_render_pass(device: Arc<Device>, swapchain: Arc<Swapchain>) -> Arc<R
                 load: Clear,
                 // Actually store the output of the draw in the image...
                 store: Store,
-                format: swapchain.image_format(),
+                format: format,
                 // Don't do multisampling, we don't want antialiasing (yet)
                 samples: 1,
             },
@@ -6,31 +6,42 @@ pub mod vs {
 
             layout(location = 0) in vec3 v_position;
             layout(location = 1) in float v_light;
+            layout(location = 2) in vec2 v_tex_coords;
+            layout(location = 3) in float v_occlusion;
+            layout(location = 4) in uint v_tex_layer;
 
             layout(location = 0) out vec2 f_tex_coords;
             layout(location = 1) out float f_light;
+            layout(location = 2) out float f_occlusion;
+            layout(location = 3) flat out uint f_tex_layer;
+            layout(location = 4) out float f_distance;
 
             layout(set = 0, binding = 0) uniform Data {
                 mat4 world;
                 mat4 view;
                 mat4 proj;
+                // From clip space to directions in the world
+                mat4 sky;
+                // Camera position in block coordinates
+                vec4 eye;
+                // Mode (0 linear, 1 exponential), start, end, density
+                vec4 fog;
+                vec4 zenith;
+                vec4 horizon;
+                vec4 ambient;
             } uniforms;
 
-            const vec2 tex_corners[4] = vec2[](
-                vec2(0, 0),
-                vec2(1, 0),
-                vec2(1, 1),
-                vec2(0, 1)
-            );
-
             void main() {
                 // View transformations
                 vec4 position = vec4(v_position, 1);
                 mat4 worldview = uniforms.view * uniforms.world;
 
                 // Fragment properties
-                f_tex_coords = tex_corners[gl_VertexIndex % 4];
+                f_tex_coords = v_tex_coords;
                 f_light = v_light;
+                f_occlusion = v_occlusion;
+                f_tex_layer = v_tex_layer;
+                f_distance = distance(v_position, uniforms.eye.xyz);
 
                 gl_Position = uniforms.proj * worldview * position;
             }
@@ -51,24 +62,203 @@ pub mod fs {
 
             layout(location = 0) in vec2 f_tex_coords;
             layout(location = 1) in float f_light;
+            layout(location = 2) in float f_occlusion;
+            layout(location = 3) flat in uint f_tex_layer;
+            layout(location = 4) in float f_distance;
 
             layout(location = 0) out vec4 f_color;
 
-            layout(set = 0, binding = 1) uniform sampler2D tex;
+            layout(set = 0, binding = 0) uniform Data {
+                mat4 world;
+                mat4 view;
+                mat4 proj;
+                // From clip space to directions in the world
+                mat4 sky;
+                // Camera position in block coordinates
+                vec4 eye;
+                // Mode (0 linear, 1 exponential), start, end, density
+                vec4 fog;
+                vec4 zenith;
+                vec4 horizon;
+                vec4 ambient;
+            } uniforms;
+
+            layout(set = 1, binding = 0) uniform sampler2DArray tex;
 
             const vec3 light_color = vec3(1.0, 1.0, 1.0);
 
-            const vec3 ambient_color = vec3(1.0, 1.0, 1.0);
-            const float ambient_strength = 0.2;
+            // Same as `Fog::amount`
+            float fog_amount(float d) {
+                if (uniforms.fog.x < 0.5) {
+                    return clamp((d - uniforms.fog.y) / (uniforms.fog.z - uniforms.fog.y), 0.0, 1.0);
+                }
+                return 1.0 - exp(-uniforms.fog.w * d);
+            }
 
             void main() {
-                vec3 ambient = ambient_color * ambient_strength;
+                vec3 ambient = uniforms.ambient.rgb;
                 vec3 local = light_color * f_light;
 
-                vec4 texture_color = texture(tex, f_tex_coords);
-                vec4 texture = vec4(ambient + local, 1) * texture_color;
+                vec4 texture_color = texture(tex, vec3(f_tex_coords, f_tex_layer));
+                vec4 texture = vec4((ambient + local) * f_occlusion, 1) * texture_color;
+
+                vec3 color = mix(texture.rgb, uniforms.horizon.rgb, fog_amount(f_distance));
+                f_color = vec4(color, texture.a);
+            }
+        "
+    }
+}
+
+/// Draws the sky gradient behind everything with one triangle covering the
+/// screen.
+pub mod sky_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+            #version 450
+
+            layout(location = 0) out vec4 f_direction;
+
+            layout(set = 0, binding = 0) uniform Data {
+                mat4 world;
+                mat4 view;
+                mat4 proj;
+                // From clip space to directions in the world
+                mat4 sky;
+                // Camera position in block coordinates
+                vec4 eye;
+                // Mode (0 linear, 1 exponential), start, end, density
+                vec4 fog;
+                vec4 zenith;
+                vec4 horizon;
+                vec4 ambient;
+            } uniforms;
+
+            void main() {
+                vec2 position = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0;
 
-                f_color = texture;
+                // On the far plane. Divided by w in the fragment shader, so
+                // that it interpolates correctly.
+                f_direction = uniforms.sky * vec4(position, 1, 1);
+
+                gl_Position = vec4(position, 1, 1);
+            }
+        "
+    }
+}
+
+pub mod sky_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 450
+
+            layout(location = 0) in vec4 f_direction;
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 0, binding = 0) uniform Data {
+                mat4 world;
+                mat4 view;
+                mat4 proj;
+                // From clip space to directions in the world
+                mat4 sky;
+                // Camera position in block coordinates
+                vec4 eye;
+                // Mode (0 linear, 1 exponential), start, end, density
+                vec4 fog;
+                vec4 zenith;
+                vec4 horizon;
+                vec4 ambient;
+            } uniforms;
+
+            void main() {
+                vec3 direction = normalize(f_direction.xyz / f_direction.w);
+                float height = clamp(direction.y, 0.0, 1.0);
+
+                f_color = vec4(mix(uniforms.horizon.rgb, uniforms.zenith.rgb, height), 1);
+            }
+        "
+    }
+}
+
+pub mod overlay_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+            #version 450
+
+            // Already in normalized device coordinates
+            layout(location = 0) in vec2 o_position;
+            layout(location = 1) in vec4 o_color;
+
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                f_color = o_color;
+                gl_Position = vec4(o_position, 0, 1);
+            }
+        "
+    }
+}
+
+pub mod overlay_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 450
+
+            layout(location = 0) in vec4 f_color;
+
+            layout(location = 0) out vec4 out_color;
+
+            void main() {
+                out_color = f_color;
+            }
+        "
+    }
+}
+
+pub mod text_vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+            #version 450
+
+            // Already in normalized device coordinates
+            layout(location = 0) in vec2 t_position;
+            layout(location = 1) in vec2 t_tex_coords;
+            layout(location = 2) in vec4 t_color;
+
+            layout(location = 0) out vec2 f_tex_coords;
+            layout(location = 1) out vec4 f_color;
+
+            void main() {
+                f_tex_coords = t_tex_coords;
+                f_color = t_color;
+                gl_Position = vec4(t_position, 0, 1);
+            }
+        "
+    }
+}
+
+pub mod text_fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+            #version 450
+
+            layout(location = 0) in vec2 f_tex_coords;
+            layout(location = 1) in vec4 f_color;
+
+            layout(location = 0) out vec4 out_color;
+
+            // The font atlas, glyphs are where it is opaque
+            layout(set = 0, binding = 0) uniform sampler2DArray atlas;
+
+            void main() {
+                float coverage = texture(atlas, vec3(f_tex_coords, 0)).a;
+                out_color = vec4(f_color.rgb, f_color.a * coverage);
             }
         "
     }
@@ -0,0 +1,315 @@
+use std::{collections::HashMap, fmt};
+
+/// Shown in place of characters the font does not have, if it has it.
+const REPLACEMENT: char = '?';
+
+/// Where a glyph is in the atlas and how it is placed, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Glyph {
+    /// Top left corner in the atlas
+    pub atlas: [u32; 2],
+    pub size: [u32; 2],
+    /// From the pen position, at the top of the line, to the top left corner
+    /// of the glyph
+    pub offset: [i32; 2],
+    /// How far the pen moves after the glyph
+    pub advance: u32,
+}
+
+/// A bitmap font: glyphs in an atlas image, and their metrics.
+///
+/// Metrics are text, with a `line_height <pixels>` line and a line for each
+/// glyph: `<code point> <x> <y> <width> <height> <x offset> <y offset>
+/// <advance>`. Empty lines and lines starting with `#` are skipped.
+///
+/// There is no kerning. A font is monospace when all of its glyphs have the
+/// same advance, proportional otherwise.
+#[derive(Clone, Debug)]
+pub struct Font {
+    glyphs: HashMap<char, Glyph>,
+    line_height: u32,
+    /// Size of the atlas image
+    atlas_size: [u32; 2],
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FontError {
+    /// A line, counted from 1, that can't be understood
+    Malformed(usize),
+    MissingLineHeight,
+    /// The glyph does not fit in the atlas
+    OutsideAtlas(char),
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed(line) => write!(f, "malformed font metrics on line {}", line),
+            Self::MissingLineHeight => write!(f, "font metrics have no line height"),
+            Self::OutsideAtlas(c) => write!(f, "glyph {:?} is outside of the atlas", c),
+        }
+    }
+}
+
+impl std::error::Error for FontError {}
+
+/// A glyph placed by [`Font::layout`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PlacedGlyph {
+    pub glyph: Glyph,
+    /// Top left corner, from the top left corner of the text
+    pub position: [i32; 2],
+}
+
+/// Text laid out in lines.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Layout {
+    /// Visible glyphs, in the order of the text
+    pub glyphs: Vec<PlacedGlyph>,
+    /// Width of the widest line and height of all the lines
+    pub size: [u32; 2],
+}
+
+impl Font {
+    /// Read the metrics of a font with an atlas of `atlas_size` pixels.
+    pub fn parse(metrics: &str, atlas_size: [u32; 2]) -> Result<Self, FontError> {
+        let mut glyphs = HashMap::new();
+        let mut line_height = None;
+
+        for (i, line) in metrics.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let malformed = || FontError::Malformed(i + 1);
+
+            let fields: Vec<_> = line.split_whitespace().collect();
+            if let ["line_height", height] = fields[..] {
+                line_height = Some(height.parse().map_err(|_| malformed())?);
+                continue;
+            }
+
+            let [code, x, y, width, height, offset_x, offset_y, advance] = fields[..] else {
+                return Err(malformed());
+            };
+            let c = code
+                .parse()
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or_else(malformed)?;
+            let number = |s: &str| s.parse::<u32>().map_err(|_| malformed());
+            let offset = |s: &str| s.parse::<i32>().map_err(|_| malformed());
+            let glyph = Glyph {
+                atlas: [number(x)?, number(y)?],
+                size: [number(width)?, number(height)?],
+                offset: [offset(offset_x)?, offset(offset_y)?],
+                advance: number(advance)?,
+            };
+
+            let fits = (0..2).all(|a| glyph.atlas[a] + glyph.size[a] <= atlas_size[a]);
+            if !fits {
+                return Err(FontError::OutsideAtlas(c));
+            }
+            glyphs.insert(c, glyph);
+        }
+
+        Ok(Self {
+            glyphs,
+            line_height: line_height.ok_or(FontError::MissingLineHeight)?,
+            atlas_size,
+        })
+    }
+
+    pub fn line_height(&self) -> u32 {
+        self.line_height
+    }
+
+    pub fn atlas_size(&self) -> [u32; 2] {
+        self.atlas_size
+    }
+
+    /// The glyph for `c`, or for the replacement character if the font does
+    /// not have it.
+    pub fn glyph(&self, c: char) -> Option<&Glyph> {
+        self.glyphs
+            .get(&c)
+            .or_else(|| self.glyphs.get(&REPLACEMENT))
+    }
+
+    fn advance(&self, c: char) -> u32 {
+        self.glyph(c).map_or(0, |g| g.advance)
+    }
+
+    /// Lay out `text` in lines starting at `\n`. With `max_width`, lines are
+    /// also broken at spaces so that they fit, and words that don't fit on
+    /// a line of their own are broken anywhere.
+    pub fn layout(&self, text: &str, max_width: Option<u32>) -> Layout {
+        let mut layout = Layout::default();
+        let mut pen = [0, 0];
+        let mut lines = 0;
+
+        for (i, paragraph) in text.split('\n').enumerate() {
+            if i > 0 {
+                pen = [0, pen[1] + self.line_height];
+            }
+            lines += 1;
+
+            for (j, word) in paragraph.split(' ').enumerate() {
+                if j > 0 {
+                    pen[0] += self.advance(' ');
+                }
+
+                let width: u32 = word.chars().map(|c| self.advance(c)).sum();
+                let overflows = |pen: [u32; 2], width| {
+                    max_width.is_some_and(|max| pen[0] > 0 && pen[0] + width > max)
+                };
+                if overflows(pen, width) {
+                    pen = [0, pen[1] + self.line_height];
+                    lines += 1;
+                }
+
+                for c in word.chars() {
+                    let Some(&glyph) = self.glyph(c) else {
+                        continue;
+                    };
+                    if overflows(pen, glyph.advance) {
+                        pen = [0, pen[1] + self.line_height];
+                        lines += 1;
+                    }
+
+                    if glyph.size.iter().all(|&v| v > 0) {
+                        layout.glyphs.push(PlacedGlyph {
+                            glyph,
+                            position: [
+                                pen[0] as i32 + glyph.offset[0],
+                                pen[1] as i32 + glyph.offset[1],
+                            ],
+                        });
+                    }
+                    pen[0] += glyph.advance;
+                    layout.size[0] = layout.size[0].max(pen[0]);
+                }
+            }
+        }
+
+        layout.size[1] = lines * self.line_height;
+        layout
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Glyphs in a row of 8x8 cells: `m` is wide, `i` narrow, the rest
+    /// monospace.
+    const METRICS: &str = "
+        # Test font
+        line_height 10
+
+        32 0 0 0 0 0 0 4
+        63 0 0 3 8 0 0 4
+        97 8 0 3 8 0 0 4
+        98 16 0 3 8 0 1 4
+        105 24 0 1 8 0 0 2
+        109 32 0 5 8 0 0 6
+    ";
+
+    fn font() -> Font {
+        Font::parse(METRICS, [64, 8]).unwrap()
+    }
+
+    fn positions(layout: &Layout) -> Vec<[i32; 2]> {
+        layout.glyphs.iter().map(|g| g.position).collect()
+    }
+
+    #[test]
+    fn hud_font() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/res/fonts/hud.txt");
+        let font = Font::parse(&std::fs::read_to_string(path).unwrap(), [64, 32]).unwrap();
+
+        for c in ('a'..='z').chain('0'..='9') {
+            assert_ne!(font.glyph(c), font.glyph('?'), "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn parsing() {
+        let font = font();
+        assert_eq!(font.line_height(), 10);
+        assert_eq!(
+            font.glyph('b'),
+            Some(&Glyph {
+                atlas: [16, 0],
+                size: [3, 8],
+                offset: [0, 1],
+                advance: 4,
+            })
+        );
+        // Replaced
+        assert_eq!(font.glyph('z'), font.glyph('?'));
+
+        assert_eq!(
+            Font::parse("line_height 10\n97 8 0 3", [64, 8]).unwrap_err(),
+            FontError::Malformed(2)
+        );
+        assert_eq!(
+            Font::parse("line_height x", [64, 8]).unwrap_err(),
+            FontError::Malformed(1)
+        );
+        assert_eq!(
+            Font::parse("97 8 0 3 8 0 0 4", [64, 8]).unwrap_err(),
+            FontError::MissingLineHeight
+        );
+        assert_eq!(
+            Font::parse("line_height 10\n97 62 0 3 8 0 0 4", [64, 8]).unwrap_err(),
+            FontError::OutsideAtlas('a')
+        );
+    }
+
+    #[test]
+    fn monospace() {
+        let layout = font().layout("ab a", None);
+
+        // The space takes room but has no glyph, `b` is offset down
+        assert_eq!(positions(&layout), vec![[0, 0], [4, 1], [12, 0]]);
+        assert_eq!(layout.size, [16, 10]);
+    }
+
+    #[test]
+    fn proportional() {
+        let layout = font().layout("mim", None);
+
+        assert_eq!(positions(&layout), vec![[0, 0], [6, 0], [8, 0]]);
+        assert_eq!(layout.size, [14, 10]);
+    }
+
+    #[test]
+    fn line_breaks() {
+        let layout = font().layout("aa\na\n\na", None);
+
+        assert_eq!(positions(&layout), vec![[0, 0], [4, 0], [0, 10], [0, 30]]);
+        assert_eq!(layout.size, [8, 40]);
+    }
+
+    #[test]
+    fn wrapping() {
+        let font = font();
+
+        // "aa aa" is just as wide as allowed
+        let layout = font.layout("aa aa aa", Some(20));
+        assert_eq!(
+            positions(&layout),
+            vec![[0, 0], [4, 0], [12, 0], [16, 0], [0, 10], [4, 10]]
+        );
+        assert_eq!(layout.size, [20, 20]);
+
+        // Too long for a line of its own
+        let layout = font.layout("a aaaaa", Some(10));
+        assert_eq!(
+            positions(&layout),
+            vec![[0, 0], [0, 10], [4, 10], [0, 20], [4, 20], [0, 30]]
+        );
+        assert!(layout.size[0] <= 10);
+    }
+}
@@ -3,8 +3,12 @@ use std::{fs::File, path::PathBuf};
 use png::OutputInfo;
 use tracing::instrument;
 
+use super::text::Font;
+
 pub struct Texture {
     pub info: OutputInfo,
+    /// Number of images of the same size stacked in `bytes`
+    pub layers: u32,
     pub bytes: Vec<u8>,
 }
 
@@ -28,6 +32,44 @@ impl TextureLoader {
         // Read the next frame. An APNG might contain multiple frames.
         let info = reader.next_frame(&mut bytes).unwrap();
 
-        Texture { info, bytes }
+        Texture {
+            info,
+            layers: 1,
+            bytes,
+        }
+    }
+
+    /// Load the font `fonts/{name}`: its atlas from the `.png` file and its
+    /// metrics from the `.txt` file next to it.
+    #[instrument(skip(self))]
+    pub fn load_font(&self, name: &str) -> (Font, Texture) {
+        let atlas = self.load(&format!("fonts/{name}.png"));
+        let metrics = std::fs::read_to_string(self.path.join(format!("fonts/{name}.txt"))).unwrap();
+        let font = Font::parse(&metrics, [atlas.info.width, atlas.info.height])
+            .unwrap_or_else(|e| panic!("Font {name}: {e}"));
+
+        (font, atlas)
+    }
+
+    /// Load `{dir}/{name}.png` for every name into one texture with a layer
+    /// per name, in order. All the images must have the same size.
+    #[instrument(skip(self))]
+    pub fn load_array(&self, dir: &str, names: &[&str]) -> Texture {
+        let mut layers = names
+            .iter()
+            .map(|name| (name, self.load(&format!("{dir}/{name}.png"))));
+
+        let (_, mut array) = layers.next().expect("Texture array must not be empty");
+
+        for (name, layer) in layers {
+            assert!(
+                (layer.info.width, layer.info.height) == (array.info.width, array.info.height),
+                "Texture {name} differs in size from the others"
+            );
+            array.bytes.extend(layer.bytes);
+            array.layers += 1;
+        }
+
+        array
     }
 }